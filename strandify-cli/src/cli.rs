@@ -1,6 +1,9 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use clap_verbosity_flag::Verbosity;
 use image::ImageReader;
+use strandify::animation::AnimationConfig;
+use strandify::gcode::GcodeConfig;
+use strandify::peg::Yarn;
 
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -27,6 +30,30 @@ fn check_file_exists(input: &str) -> Result<String, String> {
     }
 }
 
+fn check_image_exists(input: &str) -> Result<String, String> {
+    let input = check_file_exists(input)?;
+    if PathBuf::from(&input)
+        .extension()
+        .is_some_and(|ext| ext == "json")
+    {
+        Err(format!("File {:?} is not an image.", input))
+    } else {
+        Ok(input)
+    }
+}
+
+fn check_blueprint_exists(input: &str) -> Result<String, String> {
+    let input = check_file_exists(input)?;
+    if PathBuf::from(&input)
+        .extension()
+        .is_some_and(|ext| ext == "json")
+    {
+        Ok(input)
+    } else {
+        Err(format!("File {:?} is not a blueprint json file.", input))
+    }
+}
+
 fn number_between_0_and_1(value: &str) -> Result<f64, String> {
     let value: f64 = value
         .parse()
@@ -39,6 +66,104 @@ fn number_between_0_and_1(value: &str) -> Result<f64, String> {
     }
 }
 
+fn non_negative_number(value: &str) -> Result<f64, String> {
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", value))?;
+
+    if value.is_finite() && value >= 0. {
+        Ok(value)
+    } else {
+        Err(format!("Value '{:?}' should be positive", value))
+    }
+}
+
+fn positive_number(value: &str) -> Result<f64, String> {
+    let value = non_negative_number(value)?;
+    if value > 0. {
+        Ok(value)
+    } else {
+        Err(format!("Value '{:?}' should be positive", value))
+    }
+}
+
+fn positive_integer(value: &str) -> Result<usize, String> {
+    let value: usize = value
+        .parse()
+        .map_err(|_| format!("{:?} is not a positive integer", value))?;
+
+    if value > 0 {
+        Ok(value)
+    } else {
+        Err(format!("Value '{:?}' should be positive", value))
+    }
+}
+
+fn polygon_sides(value: &str) -> Result<usize, String> {
+    let value = positive_integer(value)?;
+    if value >= 3 {
+        Ok(value)
+    } else {
+        Err(format!("A polygon needs at least 3 sides, got {value}"))
+    }
+}
+
+fn svg_path_data(value: &str) -> Result<String, String> {
+    strandify::peg::shape::from_svg_path(value, (0, 0), (1, 1), 1)
+        .map(|_| value.to_string())
+        .map_err(|err| err.to_string())
+}
+
+fn positive_number_f32(value: &str) -> Result<f32, String> {
+    positive_number(value).map(|value| value as f32)
+}
+
+fn non_negative_number_f32(value: &str) -> Result<f32, String> {
+    non_negative_number(value).map(|value| value as f32)
+}
+
+/// Parse the dimensions of an image in pixels, e.g. "800x600".
+fn image_size(value: &str) -> Result<(u32, u32), String> {
+    let (width, height) = value
+        .split_once('x')
+        .ok_or(format!("Expected WIDTHxHEIGHT, got {value:?}"))?;
+    let dimension = |value: &str| match value.trim().parse::<u32>() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(format!("{value:?} is not a positive integer")),
+    };
+    Ok((dimension(width)?, dimension(height)?))
+}
+
+/// Parse a physical length, e.g. "500mm", "50cm", "0.5m" or "20in", to mm. Numbers without units
+/// are in mm.
+fn length_in_mm(value: &str) -> Result<f64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| c.is_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("{:?} is not a number", number))?;
+    let scale = match unit {
+        "" | "mm" => 1.,
+        "cm" => 10.,
+        "m" => 1000.,
+        "in" => 25.4,
+        _ => {
+            return Err(format!(
+                "Unrecognized unit '{unit}', expected mm, cm, m or in"
+            ))
+        }
+    };
+    if number.is_finite() && number > 0. {
+        Ok(number * scale)
+    } else {
+        Err(format!("Length '{value}' should be positive"))
+    }
+}
+
 #[derive(Debug, Clone)]
 /// Helper struct to parse RGB command line input.
 pub struct Rgb {
@@ -66,53 +191,177 @@ impl FromStr for Rgb {
 
 #[derive(Parser, Debug)]
 #[clap(author = "Loic Coyle")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 /// CLI utility to generate string art.
+///
+/// Without a COMMAND, INPUT is handled according to its type: images are pathed like with the generate command, blueprint json files are rendered or converted depending on the OUTPUT extension, and peg json files are turned into a peg board template like with the pegs command.
 pub struct Arguments {
-    /// Input image or blueprint json file
-    #[clap(value_parser=check_file_exists)]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// Input image, blueprint json file or peg json file written by --save-pegs
+    #[clap(value_parser=check_file_exists, required = true)]
+    pub input: Option<String>,
+    /// Output file, either image format, svg, json, txt and csv for build instructions, gcode and nc for G-code, or gif and apng for the build animation
+    pub output: Option<String>,
+    /// Verbosity level.
+    #[clap(flatten)]
+    pub verbose: Verbosity,
+    #[clap(flatten)]
+    pub options: GenerateOptions,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compute the string art of an image, and write the blueprint, its render, build instructions, G-code or build animation
+    Generate(Box<GenerateArguments>),
+    /// Render a blueprint, as an image, a svg, or a gif, apng or numbered png sequence build animation
+    Render(RenderCommandArguments),
+    /// Create the pegs of an image, and write them to a peg json file or a peg board template
+    Pegs(PegsArguments),
+    /// Print statistics of a blueprint: lines, peg usage, repeated segments, thread lengths and bounding box
+    Info(InfoArguments),
+    /// Convert a blueprint to build instructions or G-code, or update its frame size
+    Convert(ConvertArguments),
+    /// Score a blueprint against its source image, with the MSE, PSNR, SSIM and blurred MSE of its render at the image's resolution
+    Score(ScoreArguments),
+    /// Search the pathing parameters giving the best score against the input image, over a grid or randomly, and write the best blueprint
+    Tune(Box<TuneArguments>),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct GenerateArguments {
+    /// Input image
+    #[clap(value_parser=check_image_exists)]
     pub input: String,
-    /// Output file, either image format, svg or json
+    /// Output file, either image format, svg, json, txt and csv for build instructions, gcode and nc for G-code, or gif and apng for the build animation
     pub output: Option<String>,
-    /// Number of iterations
-    #[clap(short, long, value_parser, default_value_t = 4000)]
-    pub iterations: usize,
-    /// Transparent background
-    #[clap(short = 't', action, default_value_t = false)]
-    pub transparent: bool,
-    /// Yarn color
-    #[clap(short = 'c', long, value_parser, default_value = "0 0 0")]
-    pub yarn_color: Rgb,
-    /// Project image to yarn color.
-    #[clap(long, value_parser, default_value_t = false)]
-    pub project_to_yarn_color: bool,
-    /// Peg distribution shape
-    #[clap(short = 'S', long, value_parser=["circle", "square", "border"], default_value = "circle", name="SHAPE")]
-    pub peg_shape: String,
+    #[clap(flatten)]
+    pub options: GenerateOptions,
+}
+
+#[derive(clap::Args, Debug)]
+/// The options of the generate command.
+pub struct GenerateOptions {
+    #[clap(flatten)]
+    pub pegs: PegArguments,
+    #[clap(flatten)]
+    pub pathing: PathingArguments,
+    #[clap(flatten)]
+    pub render: RenderArguments,
+    #[clap(flatten)]
+    pub export: ExportArguments,
+    #[clap(flatten)]
+    pub gcode: GcodeArguments,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Pegs")]
+/// Peg generation options.
+pub struct PegArguments {
+    #[clap(flatten)]
+    pub shape: ShapeArguments,
     /// Number of pegs. Depending on the shape, can be slightly off.
     #[clap(short = 'n', long, value_parser, default_value_t = 288)]
     pub peg_number: usize,
     /// Margin between pegs and image edge [0, 1]
     #[clap(short = 'm', long, value_parser=number_between_0_and_1, default_value_t = 0.05)]
     pub peg_margin: f64,
+    /// Interior peg layout, added inside the SHAPE: a square or hexagonal grid, a poisson-disc random field, or scattered pegs denser where the image has more edges
+    #[clap(long, value_parser=["square-grid", "hex-grid", "poisson", "adaptive"], name = "LAYOUT")]
+    pub interior: Option<String>,
+    /// Distance between the interior pegs in pixels, the minimum distance for the poisson and adaptive layouts [default: 5% of the smallest image dimension]
+    #[clap(long, value_parser=positive_number, name = "SPACING")]
+    pub interior_spacing: Option<f64>,
+    /// Number of interior pegs of the adaptive layout
+    #[clap(long, value_parser=positive_integer, default_value_t = 200, name = "NUMBER")]
+    pub interior_number: usize,
     /// Add jitter to the peg position
     #[clap(short = 'j', value_parser, long)]
     pub peg_jitter: Option<u32>,
+    /// Seed of the peg jitter and of the random interior layouts, to reproduce a run. The json blueprint records the seed of its generated or resumed pegs [default: random]
+    #[clap(long, value_parser)]
+    pub seed: Option<u64>,
+    /// Radius of the pegs, in pixels. The thread wraps clockwise or counter-clockwise around the pegs, and leaves them from tangent points instead of their centers
+    #[clap(long, value_parser=non_negative_number_f32, name = "RADIUS")]
+    pub peg_radius: Option<f32>,
+    /// Write pegs to file
+    #[clap(long, name = "PEG_SAVE_FILE")]
+    pub save_pegs: Option<String>,
+    /// Read pegs from file
+    #[clap(long, name="PEG_LOAD_FILE", value_parser=check_file_exists)]
+    pub load_pegs: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+/// Peg distribution shape options.
+pub struct ShapeArguments {
+    /// Peg distribution shape. The ellipse and the border fill the image, the path is scaled to fit in it
+    #[clap(short = 'S', long, value_parser=["circle", "square", "border", "ellipse", "polygon", "path"], default_value = "circle", name="SHAPE")]
+    pub peg_shape: String,
+    /// Number of sides of the polygon shape
+    #[clap(long, value_parser=polygon_sides, default_value_t = 6, name = "SIDES")]
+    pub polygon_sides: usize,
+    /// Rotation of the polygon shape, in degrees clockwise, 0 puts a vertex at the top
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 0.,
+        allow_negative_numbers = true,
+        name = "DEGREES"
+    )]
+    pub polygon_rotation: f64,
+    /// SVG path data of the path shape, e.g. the d attribute of a path element, the pegs are evenly spaced along it
+    #[clap(long, value_parser=svg_path_data, required_if_eq("SHAPE", "path"), name = "PATH_DATA")]
+    pub peg_path: Option<String>,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Pathing")]
+/// Pathing algorithm options.
+pub struct PathingArguments {
+    /// Number of iterations
+    #[clap(short, long, value_parser, default_value_t = 4000)]
+    pub iterations: usize,
+    /// Yarn colors to use for multi color string art, one strand is computed for each color. Overrides --yarn-color
+    #[clap(short = 'p', long, value_parser, num_args = 1.., name = "COLOR")]
+    pub palette: Vec<Rgb>,
+    /// Project image to yarn color.
+    #[clap(long, value_parser, default_value_t = false)]
+    pub project_to_yarn_color: bool,
     /// Don't connect pegs within pixel distance
     #[clap(short = 's', value_parser, long)]
     pub peg_skip_within: Option<u32>,
-    /// Yarn opacity to use to render the image [0, 1]
-    #[clap(short = 'O', long, value_parser=number_between_0_and_1, default_value_t = 0.2)]
-    pub yarn_opacity: f64,
-    /// Yarn width to use to render the image
-    #[clap(short = 'W', long, value_parser, default_value_t = 1.)]
-    pub yarn_width: f32,
+    /// Don't draw lines passing within PIXELS of the edge of another peg, e.g. to keep the thread from catching on interior pegs
+    #[clap(long, value_parser=non_negative_number_f32, name = "PIXELS")]
+    pub peg_clearance: Option<f32>,
     /// Line opacity to use when computing the path, controls how much to lighten the pixels at each line pass, low values encourage more line overlap [0, 1]
     #[clap(short = 'o', long, value_parser=number_between_0_and_1, default_value_t = 0.1)]
     pub line_opacity: f64,
     /// Line width to use when computing the path.
-    #[clap(short = 'w', long, value_parser, default_value_t = 2)]
-    pub line_width: u32,
-    /// Beam search width, a value of 1 results in a purely greedy algorithm.
+    #[clap(short = 'w', long, value_parser, default_value_t = 2.)]
+    pub line_width: f32,
+    /// Rasterize the lines with anti-aliasing when computing the path, honours fractional line widths.
+    #[clap(short = 'a', long, action, default_value_t = false)]
+    pub anti_aliasing: bool,
+    /// Grayscale weight mask image, with the same dimensions as INPUT. Lines are steered towards bright regions, black regions are treated as not requiring any yarn
+    #[clap(long, value_parser=check_image_exists, name = "MASK")]
+    pub weight_mask: Option<String>,
+    /// Never draw lines crossing black regions of the weight mask.
+    #[clap(long, action, default_value_t = false, requires = "MASK")]
+    pub exclude_masked: bool,
+    /// Loss function used to score the lines when computing the path.
+    #[clap(short = 'l', long, value_parser=strandify::loss::LOSS_NAMES, default_value = "mean")]
+    pub loss: String,
+    /// Blueprint json file to resume pathing from, ITERATIONS more lines are added to it. Uses the blueprint's pegs, unless pegs are loaded with --load-pegs, which must include the pegs of the blueprint.
+    #[clap(long, value_parser=check_blueprint_exists, name = "BLUEPRINT", conflicts_with = "COLOR")]
+    pub resume: Option<String>,
+    /// Line cache file, loaded if it matches the pegs, image dimensions and line parameters, otherwise the lines are computed and saved to it.
+    #[clap(long, name = "LINE_CACHE_FILE")]
+    pub line_cache: Option<String>,
+    /// Compute the lines on demand instead of precomputing all of them, keeping up to CAPACITY lines in a cache (0 to disable caching). Reduces memory usage for large numbers of pegs at the cost of speed.
+    #[clap(long, value_parser, name = "CAPACITY")]
+    pub lazy_lines: Option<usize>,
+    /// Beam search width, a value of 1 results in a purely greedy algorithm. Not supported with a palette
     #[clap(short, long, default_value_t = 1)]
     pub beam_width: usize,
     /// If provided, early stop pathing when consecutive path losses are greater than threshold.
@@ -121,17 +370,266 @@ pub struct Arguments {
     /// Number of consecutive iterations with path losses above threshold to allow.
     #[clap(short = 'E', long, value_parser, default_value_t = 100)]
     pub early_stop_count: u32,
+    /// Stop pathing after SECONDS, keeping the lines computed so far.
+    #[clap(long, value_parser=non_negative_number, name = "SECONDS")]
+    pub time_limit: Option<f64>,
+}
 
-    /// Output scale
-    #[clap(long, value_parser, default_value_t = 1.)]
-    pub output_scale: f64,
-    /// Write pegs to file
-    #[clap(long, name = "PEG_SAVE_FILE")]
-    pub save_pegs: Option<String>,
-    /// Read pegs from file
-    #[clap(long, name="PEG_LOAD_FILE", value_parser=check_file_exists)]
-    pub load_pegs: Option<String>,
-    /// Verbosity level.
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Render")]
+/// Render options, of images, svgs and build animations.
+pub struct RenderArguments {
+    /// Transparent background
+    #[clap(short = 't', action, default_value_t = false)]
+    pub transparent: bool,
     #[clap(flatten)]
-    pub verbose: Verbosity,
+    pub yarn: YarnArguments,
+    /// Number of lines added by each frame of the build animation, rendered when OUTPUT is a gif or apng, or a numbered png sequence when OUTPUT is a png [default: 10]
+    #[clap(long, value_parser=positive_integer, name = "LINES")]
+    pub lines_per_frame: Option<usize>,
+    /// Frame rate of the build animation, in frames per second
+    #[clap(long, value_parser=positive_number, default_value_t = 25.)]
+    pub frame_rate: f64,
+    /// Output scale [default: 1, or the render scale of the blueprint]
+    #[clap(long, value_parser)]
+    pub output_scale: Option<f64>,
+}
+
+impl RenderArguments {
+    /// The [`AnimationConfig`] of the arguments.
+    pub fn animation_config(&self) -> AnimationConfig {
+        AnimationConfig {
+            lines_per_frame: self
+                .lines_per_frame
+                .unwrap_or(AnimationConfig::default().lines_per_frame),
+            frame_rate: self.frame_rate,
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+/// Yarn options, used to render blueprints.
+pub struct YarnArguments {
+    /// Yarn color
+    #[clap(short = 'c', long, value_parser, default_value = "0 0 0")]
+    pub yarn_color: Rgb,
+    /// Yarn opacity to use to render the image [0, 1]
+    #[clap(short = 'O', long, value_parser=number_between_0_and_1, default_value_t = 0.2)]
+    pub yarn_opacity: f64,
+    /// Yarn width to use to render the image
+    #[clap(short = 'W', long, value_parser, default_value_t = 1.)]
+    pub yarn_width: f32,
+}
+
+impl YarnArguments {
+    /// The [`Yarn`] of the arguments.
+    pub fn yarn(&self) -> Yarn {
+        Yarn::new(
+            self.yarn_width,
+            self.yarn_opacity,
+            (self.yarn_color.r, self.yarn_color.g, self.yarn_color.b),
+        )
+    }
+}
+
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "Export")]
+/// Build instructions and peg board template options.
+pub struct ExportArguments {
+    /// Write build instructions to file, with the pegs numbered clockwise around the frame then the interior pegs row by row, either txt, csv or printable A4 svg pages, numbered <NAME>_1.svg, <NAME>_2.svg, ... when there are several
+    #[clap(long, name = "INSTRUCTIONS_FILE", requires = "output")]
+    pub instructions: Option<String>,
+    /// Number of steps per session of the build instructions
+    #[clap(long, value_parser, default_value_t = 100)]
+    pub session_size: usize,
+    /// Physical width of the frame, e.g. 500mm, 50cm or 20in. Thread lengths are reported in real units and svgs are rendered with real dimensions
+    #[clap(long, value_parser=length_in_mm, name = "LENGTH")]
+    pub frame_size: Option<f64>,
+    /// Write a peg board template to drill or laser cut the frame to file, either svg or dxf. Requires the frame size
+    #[clap(long, name = "TEMPLATE_FILE")]
+    pub template: Option<String>,
+    /// Dimensions of the image the pegs were placed on, e.g. 800x600, to write the template of a peg json INPUT
+    #[clap(long, value_parser=image_size, name = "WIDTHxHEIGHT")]
+    pub image_size: Option<(u32, u32)>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct RenderCommandArguments {
+    /// Blueprint json file
+    #[clap(value_parser=check_blueprint_exists)]
+    pub blueprint: String,
+    /// Output file, either image format, svg, or gif and apng for the build animation
+    pub output: String,
+    #[clap(flatten)]
+    pub render: RenderArguments,
+    #[clap(flatten)]
+    pub export: ExportArguments,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PegsArguments {
+    /// Input image, to place the pegs on, or peg json file written by --save-pegs
+    #[clap(value_parser=check_file_exists)]
+    pub input: String,
+    /// Output peg json file
+    pub output: Option<String>,
+    #[clap(flatten)]
+    pub pegs: PegArguments,
+    /// Physical width of the frame, e.g. 500mm, 50cm or 20in
+    #[clap(long, value_parser=length_in_mm, name = "LENGTH")]
+    pub frame_size: Option<f64>,
+    /// Write a peg board template to drill or laser cut the frame to file, either svg or dxf. Requires the frame size
+    #[clap(long, name = "TEMPLATE_FILE", requires = "LENGTH")]
+    pub template: Option<String>,
+    /// Dimensions of the image the pegs were placed on, e.g. 800x600, to write the template of a peg json INPUT
+    #[clap(long, value_parser=image_size, name = "WIDTHxHEIGHT")]
+    pub image_size: Option<(u32, u32)>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct InfoArguments {
+    /// Blueprint json file
+    #[clap(value_parser=check_blueprint_exists)]
+    pub blueprint: String,
+    /// Physical width of the frame, e.g. 500mm, 50cm or 20in. Thread lengths are reported in real units
+    #[clap(long, value_parser=length_in_mm, name = "LENGTH")]
+    pub frame_size: Option<f64>,
+    /// Print the statistics as json, with the usage of each peg by peg number, numbered like the build instructions
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConvertArguments {
+    /// Blueprint json file
+    #[clap(value_parser=check_blueprint_exists)]
+    pub blueprint: String,
+    /// Output file, either json, txt and csv for build instructions, or gcode and nc for G-code
+    pub output: String,
+    #[clap(flatten)]
+    pub export: ExportArguments,
+    #[clap(flatten)]
+    pub gcode: GcodeArguments,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ScoreArguments {
+    /// Source image
+    #[clap(value_parser=check_image_exists)]
+    pub input: String,
+    /// Blueprint json file
+    #[clap(value_parser=check_blueprint_exists)]
+    pub blueprint: String,
+    #[clap(flatten)]
+    pub yarn: YarnArguments,
+    /// Print the metrics as json
+    #[clap(long, action, default_value_t = false)]
+    pub json: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TuneArguments {
+    /// Input image
+    #[clap(value_parser=check_image_exists)]
+    pub input: String,
+    /// Output blueprint json file of the best parameters
+    pub output: String,
+    /// Line opacity values to try [0, 1]
+    #[clap(short = 'o', long, value_parser=number_between_0_and_1, num_args = 1.., default_values_t = [0.05, 0.1, 0.2])]
+    pub line_opacity: Vec<f64>,
+    /// Line width values to try
+    #[clap(short = 'w', long, value_parser=positive_number_f32, num_args = 1.., default_values_t = [2.])]
+    pub line_width: Vec<f32>,
+    /// Number of pegs values to try
+    #[clap(short = 'n', long, value_parser=positive_integer, num_args = 1.., default_values_t = [288])]
+    pub peg_number: Vec<usize>,
+    /// Number of iterations values to try
+    #[clap(short, long, value_parser=positive_integer, num_args = 1.., default_values_t = [2000, 4000])]
+    pub iterations: Vec<usize>,
+    /// Try SAMPLES random combinations, with the line opacity and iterations drawn between the smallest and largest of their values and the line width and peg number picked among theirs, instead of all the combinations
+    #[clap(long, value_parser=positive_integer, name = "SAMPLES")]
+    pub random: Option<usize>,
+    /// Seed of the random combinations, to reproduce a run. The best blueprint records the seed used [default: random]
+    #[clap(long, value_parser)]
+    pub seed: Option<u64>,
+    /// Metric used to rank the parameters
+    #[clap(long, value_parser=strandify::tune::OBJECTIVE_NAMES, default_value = "blurred-mse")]
+    pub metric: String,
+    /// Write the scores of all the parameters to file, either txt, csv or json. Printed when not provided
+    #[clap(short, long, name = "REPORT_FILE")]
+    pub report: Option<String>,
+    #[clap(flatten)]
+    pub shape: ShapeArguments,
+    /// Margin between pegs and image edge [0, 1]
+    #[clap(short = 'm', long, value_parser=number_between_0_and_1, default_value_t = 0.05)]
+    pub peg_margin: f64,
+    /// Don't connect pegs within pixel distance
+    #[clap(short = 's', value_parser, long)]
+    pub peg_skip_within: Option<u32>,
+    /// Loss function used to score the lines when computing the path.
+    #[clap(short = 'l', long, value_parser=strandify::loss::LOSS_NAMES, default_value = "mean")]
+    pub loss: String,
+    /// Yarn opacity to use to render the blueprints when scoring them [0, 1]
+    #[clap(short = 'O', long, value_parser=number_between_0_and_1, default_value_t = 0.2)]
+    pub yarn_opacity: f64,
+    /// Yarn width to use to render the blueprints when scoring them
+    #[clap(short = 'W', long, value_parser, default_value_t = 1.)]
+    pub yarn_width: f32,
+}
+
+#[derive(clap::Args, Debug)]
+#[command(next_help_heading = "G-code")]
+/// G-code export options, in mm, the G-code export requires --frame-size.
+pub struct GcodeArguments {
+    /// Feed rate while laying the thread, in units per minute
+    #[clap(long, value_parser=non_negative_number, default_value_t = 3000.)]
+    pub feed_rate: f64,
+    /// Feed rate of the travel moves, in units per minute
+    #[clap(long, value_parser=non_negative_number, default_value_t = 6000.)]
+    pub travel_feed_rate: f64,
+    /// Height of the tool while travelling and moving between pegs, above the pegs
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 10.,
+        allow_negative_numbers = true
+    )]
+    pub travel_height: f64,
+    /// Height of the tool while wrapping the thread around a peg
+    #[clap(
+        long,
+        value_parser,
+        default_value_t = 0.,
+        allow_negative_numbers = true
+    )]
+    pub work_height: f64,
+    /// Radius of the wrap-around move at each peg
+    #[clap(long, value_parser=non_negative_number, default_value_t = 2.)]
+    pub wrap_radius: f64,
+    /// Machine coordinates of the top left corner of the frame
+    #[clap(long, value_parser, num_args = 2, value_names = ["X", "Y"], allow_negative_numbers = true, default_values_t = [0., 0.])]
+    pub machine_offset: Vec<f64>,
+    /// Keep the image's y axis, pointing down, instead of flipping it
+    #[clap(long, action, default_value_t = false)]
+    pub no_flip_y: bool,
+    /// Command template replacing the move and wrap-around at each peg, with the {x}, {y}, {peg} and {feed} placeholders
+    #[clap(long, name = "TEMPLATE")]
+    pub peg_command: Option<String>,
+}
+
+impl GcodeArguments {
+    /// The [`GcodeConfig`] of the arguments.
+    pub fn config(&self) -> GcodeConfig {
+        GcodeConfig {
+            scale: None,
+            offset: (self.machine_offset[0], self.machine_offset[1]),
+            flip_y: !self.no_flip_y,
+            feed_rate: self.feed_rate,
+            travel_feed_rate: self.travel_feed_rate,
+            travel_height: self.travel_height,
+            work_height: self.work_height,
+            wrap_radius: self.wrap_radius,
+            peg_command: self.peg_command.clone(),
+        }
+    }
 }
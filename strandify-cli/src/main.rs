@@ -3,18 +3,27 @@ use std::error::Error;
 use std::fs::File;
 use std::io::BufReader;
 use std::iter::zip;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use clap::Parser;
 use image::imageops;
-use log::{debug, info};
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
 
 use strandify::blueprint;
+use strandify::loss;
 use strandify::pather;
 use strandify::peg;
+use strandify::template;
+use strandify::tune;
 use strandify::utils;
 
 mod cli;
+
+/// Output extensions of the convert command, the other extensions are rendered.
+const CONVERT_EXTENSIONS: [&str; 5] = ["json", "txt", "csv", "gcode", "nc"];
+
 fn main() -> Result<(), Box<dyn Error>> {
     let args = cli::Arguments::parse();
     env_logger::Builder::new()
@@ -22,55 +31,108 @@ fn main() -> Result<(), Box<dyn Error>> {
         .init();
 
     debug!("cli args: {:?}", args);
-    let input_file = PathBuf::from(&args.input);
-    let output_file = args.output.as_ref().map(PathBuf::from);
-    let output_file_extension = match &output_file {
-        Some(output) => Some(
-            output
-                .extension()
-                .ok_or("Could not determine OUTPUT extension.")?
-                .to_owned(),
+    let progress_bar = !args.verbose.is_silent();
+    match &args.command {
+        Some(cli::Command::Generate(generate_args)) => generate(
+            &generate_args.input,
+            generate_args.output.as_deref(),
+            &generate_args.options,
+            progress_bar,
         ),
-        None => None,
-    };
+        Some(cli::Command::Render(render_args)) => render(render_args, progress_bar),
+        Some(cli::Command::Pegs(pegs_args)) => pegs(pegs_args),
+        Some(cli::Command::Info(info_args)) => info(info_args),
+        Some(cli::Command::Convert(convert_args)) => convert(convert_args),
+        Some(cli::Command::Score(score_args)) => score(score_args),
+        Some(cli::Command::Tune(tune_args)) => tune(tune_args, progress_bar),
+        None => run_input(&args, progress_bar),
+    }
+}
 
-    // Handle blueprint json as input
+/// Handle INPUT according to its type, when no command is given: images are pathed, blueprints
+/// are rendered or converted and peg files are turned into a template.
+fn run_input(args: &cli::Arguments, progress_bar: bool) -> Result<(), Box<dyn Error>> {
+    let input = args.input.as_deref().ok_or("INPUT required.")?;
+    let input_file = PathBuf::from(input);
     if input_file
         .extension()
         .ok_or("Could not determine INPUT extension.")?
-        == "json"
+        != "json"
     {
-        info!("Loading blueprint from file '{input_file:?}'");
+        return generate(input, args.output.as_deref(), &args.options, progress_bar);
+    }
 
-        let bp = blueprint::Blueprint::from_file(input_file)?;
+    let options = &args.options;
+    let json: serde_json::Value =
+        serde_json::from_reader(BufReader::new(File::open(&input_file)?))?;
 
-        let output_file = output_file
+    // A peg file, as written by --save-pegs, can only be turned into a template
+    if json.is_array() {
+        info!("Loading pegs from file '{input_file:?}'");
+        let pegs: peg::PegLayout = serde_json::from_value(json)?;
+        let template_file = options
+            .export
+            .template
             .as_ref()
-            .ok_or("Output file required to render output image.")?;
+            .ok_or("Template file required to use pegs as input.")?;
+        let frame_size = options
+            .export
+            .frame_size
+            .ok_or("Frame size required to write a peg board template.")?;
+        let (width, height) = options
+            .export
+            .image_size
+            .ok_or("Image size required to write the peg board template of pegs.")?;
+        info!("Writing peg board template to {template_file:?}.");
+        return template::Template::new(&pegs, width, height, frame_size).to_file(template_file);
+    }
 
-        return if output_file_extension.unwrap() == "json" {
-            // This case is probably useless, read the json then write the json...
-            info!("Writing blueprint to {output_file:?}.");
-            bp.to_file(output_file)
-        } else {
-            info!("Rendering blueprint to {output_file:?}.");
-            let yarn = peg::Yarn::new(args.yarn_width, args.yarn_opacity, (0, 0, 0));
-            bp.render(output_file, &yarn)
-        };
+    info!("Loading blueprint from file '{input_file:?}'");
+    let mut bp: blueprint::Blueprint = serde_json::from_value(json)?;
+    bp.link_pegs()?;
+    if options.export.frame_size.is_some() {
+        bp.frame_size = options.export.frame_size;
     }
+    bp.progress_bar = progress_bar;
+    log_thread_length(&bp);
 
-    let img_rgb = utils::open_img_transparency_to_white(PathBuf::from(args.input))?;
+    write_template(&bp, &options.export)?;
+    let Some(output) = &args.output else {
+        if options.export.template.is_some() {
+            return Ok(());
+        }
+        return Err("Output file required to render output image.".into());
+    };
+    write_instructions(&bp, &options.export)?;
+    apply_render_options(&mut bp, &options.render);
+    write_output(&bp, Path::new(output), options)
+}
 
-    let img = if args.project_to_yarn_color
-        && (args.yarn_color.r != args.yarn_color.g || args.yarn_color.g != args.yarn_color.b)
+/// Compute the string art of an image and write the outputs.
+fn generate(
+    input: &str,
+    output: Option<&str>,
+    options: &cli::GenerateOptions,
+    progress_bar: bool,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(output) = output {
+        Path::new(output)
+            .extension()
+            .ok_or("Could not determine OUTPUT extension.")?;
+    }
+    let img_rgb = utils::open_img_transparency_to_white(input)?;
+
+    let img = if options.pathing.project_to_yarn_color
+        && (options.render.yarn.yarn_color.r != options.render.yarn.yarn_color.g
+            || options.render.yarn.yarn_color.g != options.render.yarn.yarn_color.b)
     {
         info!("Projecting to yarn color");
         // otherwise project along the color vector
         // convert to [0, 1]
         let yarn_color_float = (
-            args.yarn_color.r as f32 / 255.,
-            args.yarn_color.g as f32 / 255.,
-            args.yarn_color.b as f32 / 255.,
+            options.render.yarn.yarn_color.r as f32 / 255.,
+            options.render.yarn.yarn_color.g as f32 / 255.,
+            options.render.yarn.yarn_color.b as f32 / 255.,
         );
         // compute the norm of the yarn color vector
         let color_norm = ((yarn_color_float.0).powi(2)
@@ -117,103 +179,565 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     let (width, height) = img_rgb.dimensions();
     let min_dim = min(width, height);
+    let previous = match &options.pathing.resume {
+        Some(resume_path) if output.is_some() => {
+            info!("Resuming from blueprint {resume_path:?}");
+            Some(blueprint::Blueprint::from_file(resume_path)?)
+        }
+        _ => None,
+    };
+    // the resumed blueprint's pegs and seed, unless other pegs are loaded
+    let (pegs, seed) = match &previous {
+        Some(bp) if options.pegs.load_pegs.is_none() && !bp.pegs.is_empty() => {
+            info!("Using the pegs of the resumed blueprint");
+            (bp.pegs.clone(), bp.seed)
+        }
+        _ => make_pegs(&options.pegs, &img)?,
+    };
+
+    if let Some(template_file) = &options.export.template {
+        let frame_size = options
+            .export
+            .frame_size
+            .ok_or("Frame size required to write a peg board template.")?;
+        info!("Writing peg board template to {template_file:?}.");
+        template::Template::new(&pegs, width, height, frame_size).to_file(template_file)?;
+    }
+
+    if let Some(output_file) = output {
+        let args = &options.pathing;
+        let skip_peg_within = args.peg_skip_within.unwrap_or(min_dim / 8);
+        info!("Skipping pegs within: {skip_peg_within:?}px");
+
+        let mut config = pather::PatherConfig::new(
+            args.iterations,
+            peg::Yarn::new(args.line_width, args.line_opacity, (0, 0, 0)),
+            pather::EarlyStopConfig {
+                loss_threshold: args.early_stop_threshold,
+                max_count: args.early_stop_count,
+            },
+            5,
+            skip_peg_within,
+            args.beam_width,
+            progress_bar,
+        );
+        config.loss = loss::from_name(&args.loss)?;
+        config.peg_clearance = args.peg_clearance;
+        config.exclude_masked = args.exclude_masked;
+        config.anti_aliasing = args.anti_aliasing;
+        config.wrapping = pegs.iter().any(|peg| peg.radius.is_some());
+        config.time_limit = args.time_limit.map(Duration::from_secs_f64);
+        if let Some(cache_capacity) = args.lazy_lines {
+            config.line_mode = pather::LineMode::Lazy { cache_capacity };
+        }
+        debug!("config: {config:?}");
+
+        let mut string_pather = pather::Pather::new(img, pegs, config);
+        if let Some(mask_path) = &args.weight_mask {
+            info!("Reading weight mask {mask_path:?}");
+            string_pather.weight_mask = Some(image::open(mask_path)?.into_luma8());
+        }
+        let line_cache_loaded = match &args.line_cache {
+            Some(line_cache_path) if PathBuf::from(line_cache_path).exists() => {
+                match string_pather.load_line_cache(line_cache_path) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        warn!("Could not load line cache {line_cache_path:?}: {err}");
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+        if !line_cache_loaded && string_pather.config.line_mode == pather::LineMode::Eager {
+            string_pather.populate_line_cache()?;
+            if let Some(line_cache_path) = &args.line_cache {
+                string_pather.save_line_cache(line_cache_path)?;
+            }
+        }
+
+        let mut bp = if let Some(previous) = previous {
+            if !previous.strands.is_empty() {
+                return Err("Resuming multi color blueprints is not supported.".into());
+            }
+            string_pather.resume_wrapped(&previous.peg_order, &previous.wraps)?
+        } else if args.palette.is_empty() {
+            string_pather.compute()?
+        } else {
+            let palette = args
+                .palette
+                .iter()
+                .map(|color| {
+                    peg::Yarn::new(
+                        args.line_width,
+                        args.line_opacity,
+                        (color.r, color.g, color.b),
+                    )
+                })
+                .collect::<Vec<_>>();
+            info!("Using multi yarn pathing with {} colors", palette.len());
+            string_pather.compute_multi(&img_rgb, &palette)?
+        };
+        apply_render_options(&mut bp, &options.render);
+        bp.frame_size = options.export.frame_size;
+        bp.seed = seed;
+        log_thread_length(&bp);
+
+        write_instructions(&bp, &options.export)?;
+        write_output(&bp, Path::new(output_file), options)?;
+    }
+
+    Ok(())
+}
+
+/// Render a blueprint.
+fn render(args: &cli::RenderCommandArguments, progress_bar: bool) -> Result<(), Box<dyn Error>> {
+    let output_file = Path::new(&args.output);
+    let extension = output_file
+        .extension()
+        .ok_or("Could not determine OUTPUT extension.")?;
+    if CONVERT_EXTENSIONS.iter().any(|ext| extension == *ext) {
+        return Err(format!(
+            "Can't render to '{}', use the convert command.",
+            extension.to_string_lossy()
+        )
+        .into());
+    }
+
+    let mut bp = load_blueprint(&args.blueprint, args.export.frame_size)?;
+    bp.progress_bar = progress_bar;
+    write_template(&bp, &args.export)?;
+    write_instructions(&bp, &args.export)?;
+    apply_render_options(&mut bp, &args.render);
+    render_output(&bp, output_file, &args.render)
+}
+
+/// Convert a blueprint to build instructions or G-code.
+fn convert(args: &cli::ConvertArguments) -> Result<(), Box<dyn Error>> {
+    let bp = load_blueprint(&args.blueprint, args.export.frame_size)?;
+    write_template(&bp, &args.export)?;
+    write_instructions(&bp, &args.export)?;
+    convert_output(&bp, Path::new(&args.output), &args.export, &args.gcode)
+}
+
+/// Create the pegs of an image, or load them from a peg file, and write them.
+fn pegs(args: &cli::PegsArguments) -> Result<(), Box<dyn Error>> {
+    if args.output.is_none() && args.template.is_none() {
+        return Err("Output file or template file required.".into());
+    }
+    let input_file = PathBuf::from(&args.input);
+    let (pegs, dimensions) = if input_file.extension().is_some_and(|ext| ext == "json") {
+        info!("Loading pegs from file '{input_file:?}'");
+        let reader = BufReader::new(File::open(&input_file)?);
+        let pegs: peg::PegLayout = serde_json::from_reader(reader)?;
+        (pegs, args.image_size)
+    } else {
+        let img = imageops::grayscale(&utils::open_img_transparency_to_white(&input_file)?);
+        (make_pegs(&args.pegs, &img)?.0, Some(img.dimensions()))
+    };
+    info!("Number of pegs: {}", pegs.len());
+
+    if let Some(output) = &args.output {
+        info!("Writing pegs to {output:?}");
+        serde_json::to_writer(File::create(output)?, &pegs)?;
+    }
+    if let Some(template_file) = &args.template {
+        let frame_size = args
+            .frame_size
+            .ok_or("Frame size required to write a peg board template.")?;
+        let (width, height) =
+            dimensions.ok_or("Image size required to write the peg board template of pegs.")?;
+        info!("Writing peg board template to {template_file:?}.");
+        template::Template::new(&pegs, width, height, frame_size).to_file(template_file)?;
+    }
+    Ok(())
+}
+
+/// Print the statistics of a blueprint.
+fn info(args: &cli::InfoArguments) -> Result<(), Box<dyn Error>> {
+    let bp = load_blueprint(&args.blueprint, args.frame_size)?;
+    let stats = bp.stats();
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+    } else {
+        println!("Dimensions:        {}x{} px", bp.width, bp.height);
+        if let Some(frame_size) = bp.frame_size {
+            println!("Frame size:        {frame_size:.1} mm");
+        }
+        if let Some(seed) = bp.seed {
+            println!("Seed:              {seed}");
+        }
+        print!("{}", stats.to_text());
+    }
+    Ok(())
+}
+
+/// Load a blueprint json file, with its frame size overridden by `frame_size`.
+fn load_blueprint(
+    path: &str,
+    frame_size: Option<f64>,
+) -> Result<blueprint::Blueprint, Box<dyn Error>> {
+    info!("Loading blueprint from file '{path:?}'");
+    let mut bp = blueprint::Blueprint::from_file(path)?;
+    if frame_size.is_some() {
+        bp.frame_size = frame_size;
+    }
+    log_thread_length(&bp);
+    Ok(bp)
+}
+
+/// Create the pegs from their shape or load them from file, and add jitter and radius. The
+/// random layouts and the jitter of generated pegs are drawn from the seed, which is returned,
+/// [`None`] for loaded pegs.
+fn make_pegs(
+    args: &cli::PegArguments,
+    img: &image::GrayImage,
+) -> Result<(peg::PegLayout, Option<u64>), Box<dyn Error>> {
+    let (width, height) = img.dimensions();
+    let min_dim = min(width, height);
     let margin = (min_dim as f64 * args.peg_margin).round() as u32;
     info!("Peg margin: {margin}px");
 
     // Handle the generation of pegs
-    let pegs: Vec<peg::Peg> = match args.load_pegs {
+    let (pegs, seed): (peg::PegLayout, _) = match &args.load_pegs {
         // A json file containing the pegs was given, load it.
         Some(peg_path) => {
             // Load pegs from file
             info!("Reading {peg_path:?}");
             let reader = BufReader::new(File::open(peg_path)?);
-            serde_json::from_reader(reader)?
+            (serde_json::from_reader(reader)?, None)
         }
         // Generate from scratch
         None => {
-            let center = (width / 2, height / 2);
-            match args.peg_shape.as_str() {
-                "circle" => peg::shape::circle(center, (min_dim - 2 * margin) / 2, args.peg_number),
-                "square" => {
-                    let length = min_dim - 2 * margin;
-                    peg::shape::square(
-                        (
-                            center.0.saturating_sub(length / 2),
-                            center.1.saturating_sub(length / 2),
-                        ),
-                        length,
-                        args.peg_number,
-                    )
-                }
-                "border" => peg::shape::rectangle(
-                    (margin, margin),
-                    width - 2 * margin,
-                    height - 2 * margin,
-                    args.peg_number,
-                ),
-                _ => {
-                    return Err(format!("Unrecognized SHAPE '{}'", args.peg_shape).into());
-                }
+            let seed = args.seed.unwrap_or_else(rand::random);
+            info!("Seed: {seed}");
+            let mut rng = utils::seeded_rng(Some(seed));
+            let mut pegs = shape_pegs(&args.shape, width, height, margin, args.peg_number)?;
+            if let Some(layout) = &args.interior {
+                let interior = interior_pegs(layout, args, img, margin, &pegs, &mut rng)?;
+                info!("Number of interior pegs: {}", interior.len());
+                pegs.extend(interior);
             }
-            .into_iter()
-            .map(|peg| {
-                if let Some(jitter) = args.peg_jitter {
-                    peg.with_jitter(jitter as i64)
-                } else {
-                    peg
-                }
-            })
-            .collect::<Vec<_>>()
+            let pegs = pegs
+                .into_iter()
+                .map(|peg| {
+                    if let Some(jitter) = args.peg_jitter {
+                        peg.with_jitter(jitter as i64, &mut rng)
+                    } else {
+                        peg
+                    }
+                })
+                .collect();
+            (pegs, Some(seed))
         }
     };
 
+    let pegs = match args.peg_radius {
+        Some(radius) => pegs.map(|peg| peg.with_radius(radius)),
+        None => pegs,
+    };
+
     info!("Number of pegs: {}", pegs.len());
 
-    if let Some(peg_path) = args.save_pegs {
+    if let Some(peg_path) = &args.save_pegs {
         info!("Saving pegs to {peg_path:?}");
         serde_json::to_writer(File::create(peg_path)?, &pegs)?
     }
+    Ok((pegs, seed))
+}
 
-    if let Some(output_file) = output_file {
-        let skip_peg_within = args.peg_skip_within.unwrap_or(min_dim / 8);
-        info!("Skipping pegs within: {skip_peg_within:?}px");
-        let render_yarn = peg::Yarn::new(
-            args.yarn_width,
-            args.yarn_opacity,
-            (args.yarn_color.r, args.yarn_color.g, args.yarn_color.b),
-        );
+/// Write the peg board template of the blueprint, if requested.
+fn write_template(
+    bp: &blueprint::Blueprint,
+    args: &cli::ExportArguments,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(template_file) = &args.template {
+        info!("Writing peg board template to {template_file:?}.");
+        bp.template()
+            .ok_or("Frame size required to write a peg board template.")?
+            .to_file(template_file)?;
+    }
+    Ok(())
+}
 
-        let config = pather::PatherConfig::new(
-            args.iterations,
-            peg::Yarn::new(args.line_width as f32, args.line_opacity, (0, 0, 0)),
-            pather::EarlyStopConfig {
-                loss_threshold: args.early_stop_threshold,
-                max_count: args.early_stop_count,
-            },
-            5,
-            skip_peg_within,
-            args.beam_width,
-            !args.verbose.is_silent(),
-        );
-        debug!("config: {config:?}");
+/// Write the build instructions of the blueprint, if requested.
+fn write_instructions(
+    bp: &blueprint::Blueprint,
+    args: &cli::ExportArguments,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(instructions_file) = &args.instructions {
+        info!("Writing build instructions to {instructions_file:?}.");
+        bp.instructions(args.session_size)
+            .to_file(instructions_file)?;
+    }
+    Ok(())
+}
 
-        let mut string_pather = pather::Pather::new(img, pegs, config);
-        string_pather.populate_line_cache()?;
+/// Apply the background and scale render options to the blueprint.
+fn apply_render_options(bp: &mut blueprint::Blueprint, args: &cli::RenderArguments) {
+    if args.transparent {
+        bp.background = None;
+    }
+    if let Some(output_scale) = args.output_scale {
+        bp.render_scale = output_scale;
+    }
+}
+
+/// Write the blueprint to the output file, converted or rendered depending on the extension.
+fn write_output(
+    bp: &blueprint::Blueprint,
+    output_file: &Path,
+    options: &cli::GenerateOptions,
+) -> Result<(), Box<dyn Error>> {
+    let extension = output_file
+        .extension()
+        .ok_or("Could not determine OUTPUT extension.")?;
+    if CONVERT_EXTENSIONS.iter().any(|ext| extension == *ext) {
+        convert_output(bp, output_file, &options.export, &options.gcode)
+    } else {
+        render_output(bp, output_file, &options.render)
+    }
+}
 
-        let mut bp = string_pather.compute()?;
-        if args.transparent {
-            bp.background = None;
+/// Write the blueprint as json, build instructions or G-code, depending on the extension.
+fn convert_output(
+    bp: &blueprint::Blueprint,
+    output_file: &Path,
+    export: &cli::ExportArguments,
+    gcode: &cli::GcodeArguments,
+) -> Result<(), Box<dyn Error>> {
+    let output_file_extension = output_file
+        .extension()
+        .ok_or("Could not determine OUTPUT extension.")?;
+    if output_file_extension == "json" {
+        info!("Writing blueprint to {output_file:?}.");
+        bp.to_file(output_file)
+    } else if output_file_extension == "txt" || output_file_extension == "csv" {
+        info!("Writing build instructions to {output_file:?}.");
+        bp.instructions(export.session_size).to_file(output_file)
+    } else if output_file_extension == "gcode" || output_file_extension == "nc" {
+        info!("Writing G-code to {output_file:?}.");
+        std::fs::write(output_file, bp.gcode(&gcode.config())?)?;
+        Ok(())
+    } else {
+        Err(format!(
+            "Unsupported conversion format '{}'.",
+            output_file_extension.to_string_lossy()
+        )
+        .into())
+    }
+}
+
+/// Render the blueprint as an image, a svg or a build animation, depending on the extension.
+fn render_output(
+    bp: &blueprint::Blueprint,
+    output_file: &Path,
+    args: &cli::RenderArguments,
+) -> Result<(), Box<dyn Error>> {
+    let output_file_extension = output_file
+        .extension()
+        .ok_or("Could not determine OUTPUT extension.")?;
+    let yarn = args.yarn.yarn();
+    if output_file_extension == "gif"
+        || output_file_extension == "apng"
+        || (output_file_extension == "png" && args.lines_per_frame.is_some())
+    {
+        info!("Rendering build animation to {output_file:?}.");
+        bp.animation(&yarn, args.animation_config())
+            .to_file(output_file)
+    } else {
+        info!("Rendering blueprint to {output_file:?}.");
+        bp.render(output_file, &yarn)
+    }
+}
+
+/// Score a blueprint against its source image and print the metrics.
+fn score(args: &cli::ScoreArguments) -> Result<(), Box<dyn Error>> {
+    info!("Reading source image {:?}", args.input);
+    let img = imageops::grayscale(&utils::open_img_transparency_to_white(&args.input)?);
+    info!("Loading blueprint from file {:?}", args.blueprint);
+    let bp = blueprint::Blueprint::from_file(&args.blueprint)?;
+    let yarn = args.yarn.yarn();
+
+    let metrics = bp.score(&img, &yarn)?;
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&metrics)?);
+    } else {
+        println!("MSE:         {:.6}", metrics.mse);
+        println!("PSNR:        {:.2} dB", metrics.psnr);
+        println!("SSIM:        {:.4}", metrics.ssim);
+        println!("Blurred MSE: {:.6}", metrics.blurred_mse);
+    }
+    Ok(())
+}
+
+/// Search the pathing parameters with the best score and write the best blueprint.
+fn tune(args: &cli::TuneArguments, progress_bar: bool) -> Result<(), Box<dyn Error>> {
+    let img = imageops::grayscale(&utils::open_img_transparency_to_white(&args.input)?);
+    let (width, height) = img.dimensions();
+    let min_dim = min(width, height);
+    let margin = (min_dim as f64 * args.peg_margin).round() as u32;
+
+    let mut config = pather::PatherConfig::new(
+        0,
+        peg::Yarn::default(),
+        pather::EarlyStopConfig::default(),
+        5,
+        args.peg_skip_within.unwrap_or(min_dim / 8),
+        1,
+        progress_bar,
+    );
+    config.loss = loss::from_name(&args.loss)?;
+    let yarn = peg::Yarn::new(args.yarn_width, args.yarn_opacity, (0, 0, 0));
+    let pegs = |peg_number| {
+        shape_pegs(&args.shape, width, height, margin, peg_number)
+            .expect("SHAPE should be validated by clap")
+    };
+    let mut tuner = tune::Tuner::new(img, pegs, config, yarn);
+    tuner.objective = tune::Objective::from_name(&args.metric)?;
+
+    let space = tune::SearchSpace {
+        line_opacity: args.line_opacity.clone(),
+        line_width: args.line_width.clone(),
+        peg_number: args.peg_number.clone(),
+        iterations: args.iterations.clone(),
+    };
+    let seed = args.random.map(|_| args.seed.unwrap_or_else(rand::random));
+    let candidates = match (args.random, seed) {
+        (Some(samples), Some(seed)) => {
+            info!("Seed: {seed}");
+            space.random(samples, &mut utils::seeded_rng(Some(seed)))
         }
-        bp.render_scale = args.output_scale;
+        _ => space.grid(),
+    };
+    let report = tuner.run(&candidates)?;
 
-        if output_file_extension.unwrap() == "json" {
-            info!("Writing blueprint to {output_file:?}.");
-            bp.to_file(&output_file)?;
-        } else {
-            info!("Rendering blueprint to {output_file:?}.");
-            bp.render(&output_file, &render_yarn)?;
+    match &args.report {
+        Some(report_file) => {
+            info!("Writing tuning report to {report_file:?}.");
+            report.to_file(report_file)?;
         }
+        None => print!("{}", report.to_text()),
     }
+    let best = report.best().ok_or("No parameters to try.")?;
+    info!("Best parameters: {:?}", best.candidate);
+    info!("Writing best blueprint to {:?}.", args.output);
+    let mut bp = best.blueprint.clone();
+    bp.seed = seed;
+    bp.to_file(&args.output)
+}
 
-    Ok(())
+/// Create the pegs of a shape, inside the image with a margin.
+fn shape_pegs(
+    shape: &cli::ShapeArguments,
+    width: u32,
+    height: u32,
+    margin: u32,
+    peg_number: usize,
+) -> Result<Vec<peg::Peg>, Box<dyn Error>> {
+    let min_dim = min(width, height);
+    let center = (width / 2, height / 2);
+    let pegs = match shape.peg_shape.as_str() {
+        "circle" => peg::shape::circle(center, (min_dim - 2 * margin) / 2, peg_number),
+        "square" => {
+            let length = min_dim - 2 * margin;
+            peg::shape::square(
+                (
+                    center.0.saturating_sub(length / 2),
+                    center.1.saturating_sub(length / 2),
+                ),
+                length,
+                peg_number,
+            )
+        }
+        "border" => peg::shape::rectangle(
+            (margin, margin),
+            width - 2 * margin,
+            height - 2 * margin,
+            peg_number,
+        ),
+        "ellipse" => peg::shape::ellipse(
+            center,
+            ((width - 2 * margin) / 2, (height - 2 * margin) / 2),
+            peg_number,
+        ),
+        "polygon" => peg::shape::regular_polygon(
+            center,
+            (min_dim - 2 * margin) / 2,
+            shape.polygon_sides,
+            (shape.polygon_rotation - 90.).to_radians(),
+            peg_number,
+        ),
+        "path" => peg::shape::from_svg_path(
+            shape
+                .peg_path
+                .as_deref()
+                .ok_or("The path shape requires --peg-path")?,
+            (margin, margin),
+            (width - 2 * margin, height - 2 * margin),
+            peg_number,
+        )?,
+        _ => {
+            return Err(format!("Unrecognized SHAPE '{}'", shape.peg_shape).into());
+        }
+    };
+    Ok(pegs)
+}
+
+/// Create the interior pegs of a layout, inside the outline of the shape pegs and away from
+/// them.
+fn interior_pegs(
+    layout: &str,
+    args: &cli::PegArguments,
+    img: &image::GrayImage,
+    margin: u32,
+    outline: &[peg::Peg],
+    rng: &mut StdRng,
+) -> Result<Vec<peg::Peg>, Box<dyn Error>> {
+    let (width, height) = img.dimensions();
+    let spacing = args
+        .interior_spacing
+        .unwrap_or(min(width, height) as f64 * 0.05);
+    let top_left = (margin, margin);
+    let size = (width - 2 * margin, height - 2 * margin);
+    let pegs = match layout {
+        "square-grid" => peg::shape::square_grid(top_left, size, spacing),
+        "hex-grid" => peg::shape::hex_grid(top_left, size, spacing),
+        "poisson" => peg::shape::poisson_disc(top_left, size, spacing, rng),
+        "adaptive" => {
+            peg::shape::image_adaptive(img, top_left, size, args.interior_number, spacing, rng)?
+        }
+        _ => return Err(format!("Unrecognized interior LAYOUT '{layout}'").into()),
+    };
+
+    let inside = |peg: &peg::Peg| {
+        // even-odd rule, with the shape pegs as the vertices of the outline
+        let (x, y) = (peg.x as f64, peg.y as f64);
+        let edges = outline.iter().zip(outline.iter().cycle().skip(1));
+        edges
+            .filter(|(a, b)| {
+                let (ax, ay, bx, by) = (a.x as f64, a.y as f64, b.x as f64, b.y as f64);
+                (ay > y) != (by > y) && x < ax + (y - ay) * (bx - ax) / (by - ay)
+            })
+            .count()
+            % 2
+            == 1
+    };
+    Ok(pegs
+        .into_iter()
+        .filter(|peg| {
+            inside(peg)
+                && outline
+                    .iter()
+                    .all(|other| other.dist_to(peg) as f64 >= spacing / 2.)
+        })
+        .collect())
+}
+
+/// Log the total length of thread of the blueprint, in m if its frame size is set.
+fn log_thread_length(bp: &blueprint::Blueprint) {
+    match bp.thread_length_mm() {
+        Some(length) => info!("Thread length: {:.2} m", length / 1000.),
+        None => info!("Thread length: {:.0} px", bp.thread_length()),
+    }
 }
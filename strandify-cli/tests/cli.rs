@@ -8,6 +8,7 @@ use image::{self, GenericImageView};
 use predicates::prelude::*;
 
 use strandify::blueprint::Blueprint;
+use strandify::gcode::{simulate, GcodeConfig};
 use strandify::peg::Peg;
 
 fn input_file() -> PathBuf {
@@ -45,6 +46,8 @@ fn save_load_pegs() -> Result<(), Box<dyn Error>> {
     let reader = BufReader::new(File::open(peg_file.path())?);
     let pegs: Vec<Peg> = serde_json::from_reader(reader)?;
     assert_eq!(pegs.len(), n_pegs);
+    let ids: Vec<usize> = pegs.iter().map(|peg| peg.id).collect();
+    assert_eq!(ids, (0..n_pegs).collect::<Vec<_>>());
 
     // read the pegs from file
     let output_file = assert_fs::NamedTempFile::new("output.jpg").unwrap();
@@ -65,6 +68,22 @@ fn save_load_pegs() -> Result<(), Box<dyn Error>> {
     let input_img = image::open(input_file())?;
     let output_img = image::open(output_file)?;
     assert_eq!(input_img.dimensions(), output_img.dimensions());
+
+    // pegs with duplicate ids are rejected
+    let duplicate_file = assert_fs::NamedTempFile::new("duplicate.json").unwrap();
+    duplicate_file.write_str(r#"[{"x": 0, "y": 0, "id": 1}, {"x": 5, "y": 5, "id": 1}]"#)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["pegs", duplicate_file.to_str().unwrap()]);
+    cmd.arg(peg_file_str);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Duplicate peg id 1"));
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(duplicate_file.to_str().unwrap());
+    cmd.args(["--frame-size", "40cm", "--template", "template.svg"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Duplicate peg id 1"));
     Ok(())
 }
 
@@ -77,6 +96,8 @@ fn string_art_img() -> Result<(), Box<dyn Error>> {
     cmd.arg(output_file.to_str().unwrap());
     cmd.arg("-i");
     cmd.arg("100");
+    cmd.arg("-n");
+    cmd.arg("60");
     cmd.arg("-w");
     cmd.arg("1");
     cmd.arg("-q");
@@ -98,6 +119,8 @@ fn string_art_svg() -> Result<(), Box<dyn Error>> {
     cmd.arg(output_file.to_str().unwrap());
     cmd.arg("-i");
     cmd.arg("100");
+    cmd.arg("-n");
+    cmd.arg("60");
     cmd.arg("-w");
     cmd.arg("1");
     cmd.arg("-q");
@@ -117,6 +140,8 @@ fn save_render_string_art_blueprint() -> Result<(), Box<dyn Error>> {
     cmd.arg(input_file().to_str().unwrap());
     cmd.arg("-i");
     cmd.arg(n_lines.to_string());
+    cmd.arg("-n");
+    cmd.arg("60");
     cmd.arg(blueprint_file.to_str().unwrap());
     cmd.arg("-q");
 
@@ -144,3 +169,855 @@ fn save_render_string_art_blueprint() -> Result<(), Box<dyn Error>> {
     img_file.assert(predicate::path::is_file());
     Ok(())
 }
+
+#[test]
+fn string_art_palette() -> Result<(), Box<dyn Error>> {
+    let n_lines = 50;
+    let blueprint_file = assert_fs::NamedTempFile::new("bp.json").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.arg("-i");
+    cmd.arg(n_lines.to_string());
+    cmd.arg("-n");
+    cmd.arg("60");
+    cmd.arg("--palette");
+    cmd.arg("0 0 0");
+    cmd.arg("255 0 0");
+    cmd.arg("-q");
+
+    cmd.assert().success();
+    let reader = BufReader::new(File::open(blueprint_file.path())?);
+    let bp: Blueprint = serde_json::from_reader(reader)?;
+    assert_eq!(bp.strands.len(), 2);
+    assert_eq!(
+        bp.strands
+            .iter()
+            .map(|strand| strand.peg_order.len())
+            .sum::<usize>(),
+        n_lines + 2
+    );
+
+    // render the multi color blueprint
+    let img_file = assert_fs::NamedTempFile::new("bp.png").unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.arg(img_file.to_str().unwrap());
+    cmd.arg("-q");
+
+    cmd.assert().success();
+    img_file.assert(predicate::path::is_file());
+
+    // the multi color pathing is greedy
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.args(["-i", "10", "-n", "60", "-b", "2", "-q"]);
+    cmd.args(["--palette", "0 0 0"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("not supported with a palette"));
+    Ok(())
+}
+
+#[test]
+fn string_art_loss() -> Result<(), Box<dyn Error>> {
+    let output_file = assert_fs::NamedTempFile::new("output.svg").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.arg("-i");
+    cmd.arg("20");
+    cmd.arg("-n");
+    cmd.arg("60");
+    cmd.arg("--loss");
+    cmd.arg("length-normalized");
+    cmd.arg("-q");
+
+    cmd.assert().success();
+    output_file.assert(predicate::path::is_file());
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg("--loss");
+    cmd.arg("unknown");
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn string_art_weight_mask() -> Result<(), Box<dyn Error>> {
+    let input_img = image::open(input_file())?;
+    let (width, height) = input_img.dimensions();
+    // mask out the right half of the image
+    let mask = image::GrayImage::from_fn(width, height, |x, _| {
+        image::Luma([if x < width / 2 { 255 } else { 0 }])
+    });
+    let mask_file = assert_fs::NamedTempFile::new("mask.png").unwrap();
+    mask.save(mask_file.path())?;
+    let blueprint_file = assert_fs::NamedTempFile::new("bp.json").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.arg("-i");
+    cmd.arg("50");
+    cmd.arg("-n");
+    cmd.arg("60");
+    cmd.arg("--weight-mask");
+    cmd.arg(mask_file.to_str().unwrap());
+    cmd.arg("--exclude-masked");
+    cmd.arg("-q");
+
+    cmd.assert().success();
+    let reader = BufReader::new(File::open(blueprint_file.path())?);
+    let bp: Blueprint = serde_json::from_reader(reader)?;
+    // lines can't cross the masked region
+    assert!(bp.peg_order.iter().all(|peg| peg.x < width / 2));
+
+    // nor the lines of the strands, whose start pegs ignore the weight mask
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "--palette", "0 0 0", "255 0 0"]);
+    cmd.args(["--weight-mask", mask_file.to_str().unwrap()]);
+    cmd.args(["--exclude-masked", "-q"]);
+
+    cmd.assert().success();
+    let bp = Blueprint::from_file(blueprint_file.path())?;
+    assert!(bp
+        .strands
+        .iter()
+        .flat_map(|strand| &strand.peg_order[1..])
+        .all(|peg| peg.x < width / 2));
+    Ok(())
+}
+
+#[test]
+fn string_art_anti_aliasing() -> Result<(), Box<dyn Error>> {
+    let output_file = assert_fs::NamedTempFile::new("output.png").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.arg("-i");
+    cmd.arg("50");
+    cmd.arg("-n");
+    cmd.arg("60");
+    cmd.arg("--anti-aliasing");
+    cmd.arg("-w");
+    cmd.arg("1.5");
+    cmd.arg("-q");
+
+    cmd.assert().success();
+    output_file.assert(predicate::path::is_file());
+    Ok(())
+}
+
+#[test]
+fn string_art_lazy_lines() -> Result<(), Box<dyn Error>> {
+    let eager_file = assert_fs::NamedTempFile::new("eager.json").unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(eager_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-q"]);
+    cmd.assert().success();
+
+    // lazily computed lines lead to the same path
+    let lazy_file = assert_fs::NamedTempFile::new("lazy.json").unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(lazy_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-q", "--lazy-lines", "100"]);
+    cmd.assert().success();
+
+    let eager = Blueprint::from_file(eager_file.path())?;
+    let lazy = Blueprint::from_file(lazy_file.path())?;
+    let coords = |bp: &Blueprint| {
+        bp.peg_order
+            .iter()
+            .map(|peg| (peg.x, peg.y))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(coords(&eager), coords(&lazy));
+    Ok(())
+}
+
+#[test]
+fn string_art_line_cache() -> Result<(), Box<dyn Error>> {
+    let line_cache_file = assert_fs::NamedTempFile::new("lines.bin").unwrap();
+    let output_file = assert_fs::NamedTempFile::new("output.json").unwrap();
+    let coords = |bp: &Blueprint| {
+        bp.peg_order
+            .iter()
+            .map(|peg| (peg.x, peg.y))
+            .collect::<Vec<_>>()
+    };
+
+    let mut peg_orders = vec![];
+    // computes and saves the lines, then loads them
+    for _ in 0..2 {
+        let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+        cmd.arg(input_file().to_str().unwrap());
+        cmd.arg(output_file.to_str().unwrap());
+        cmd.args(["-i", "50", "-n", "60", "-q", "--line-cache"]);
+        cmd.arg(line_cache_file.to_str().unwrap());
+        cmd.assert().success();
+        line_cache_file.assert(predicate::path::is_file());
+        peg_orders.push(coords(&Blueprint::from_file(output_file.path())?));
+    }
+    assert_eq!(peg_orders[0], peg_orders[1]);
+
+    // different line parameters, the line cache is recomputed
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-w", "3", "-v", "--line-cache"]);
+    cmd.arg(line_cache_file.to_str().unwrap());
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Could not load line cache"));
+    Ok(())
+}
+
+#[test]
+fn string_art_resume() -> Result<(), Box<dyn Error>> {
+    let previous_file = assert_fs::NamedTempFile::new("previous.json").unwrap();
+    let output_file = assert_fs::NamedTempFile::new("output.json").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(previous_file.to_str().unwrap());
+    cmd.args(["-i", "30", "-n", "60", "-q"]);
+    cmd.assert().success();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    // the pegs of the resumed blueprint are used
+    cmd.args(["-i", "20", "-n", "40", "-q", "--resume"]);
+    cmd.arg(previous_file.to_str().unwrap());
+    cmd.assert().success();
+
+    let coords = |bp: &Blueprint| {
+        bp.peg_order
+            .iter()
+            .map(|peg| (peg.x, peg.y))
+            .collect::<Vec<_>>()
+    };
+    let previous = coords(&Blueprint::from_file(previous_file.path())?);
+    let resumed = coords(&Blueprint::from_file(output_file.path())?);
+    assert_eq!(previous.len(), 31);
+    assert_eq!(resumed.len(), 51);
+    assert_eq!(resumed[..31], previous[..]);
+    // the seed of the reused pegs is kept
+    let seed = Blueprint::from_file(previous_file.path())?.seed;
+    assert!(seed.is_some());
+    assert_eq!(Blueprint::from_file(output_file.path())?.seed, seed);
+
+    // the pegs of the blueprint should be among the loaded pegs
+    let pegs_file = assert_fs::NamedTempFile::new("pegs.json").unwrap();
+    let first = previous[0];
+    let pegs = Blueprint::from_file(previous_file.path())?
+        .pegs
+        .iter()
+        .filter(|peg| (peg.x, peg.y) != first)
+        .copied()
+        .collect::<Vec<_>>();
+    serde_json::to_writer(File::create(pegs_file.path())?, &pegs)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.args(["-i", "20", "-q", "--load-pegs", pegs_file.to_str().unwrap()]);
+    cmd.arg("--resume");
+    cmd.arg(previous_file.to_str().unwrap());
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn string_art_time_limit() -> Result<(), Box<dyn Error>> {
+    let output_file = assert_fs::NamedTempFile::new("output.json").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.args(["-i", "1000000", "-n", "60", "-q", "--time-limit", "0.5"]);
+    cmd.assert().success();
+
+    let bp = Blueprint::from_file(output_file.path())?;
+    assert!(bp.peg_order.len() < 1000001);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.args(["--time-limit", "-1"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn string_art_instructions() -> Result<(), Box<dyn Error>> {
+    let output_file = assert_fs::NamedTempFile::new("output.txt").unwrap();
+    let csv_file = assert_fs::NamedTempFile::new("instructions.csv").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-q", "--session-size", "20"]);
+    cmd.arg("--instructions");
+    cmd.arg(csv_file.to_str().unwrap());
+    cmd.assert().success();
+
+    output_file.assert(predicate::str::contains("Pegs: 60"));
+    output_file.assert(predicate::str::contains("Session 3 (steps 41-50)"));
+    // header and one row per step
+    csv_file.assert(predicate::function(|csv: &str| csv.lines().count() == 51));
+
+    // from a blueprint
+    let blueprint_file = assert_fs::NamedTempFile::new("blueprint.json").unwrap();
+    let svg_file = assert_fs::NamedTempFile::new("instructions.svg").unwrap();
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-q"]);
+    cmd.assert().success();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(blueprint_file.to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.arg("--instructions");
+    cmd.arg(svg_file.to_str().unwrap());
+    cmd.assert().success();
+    svg_file.assert(predicate::str::contains("210mm"));
+    Ok(())
+}
+
+#[test]
+fn string_art_frame_size() -> Result<(), Box<dyn Error>> {
+    let output_file = assert_fs::NamedTempFile::new("output.svg").unwrap();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "--frame-size", "50cm", "-vv"]);
+    cmd.assert()
+        .success()
+        .stderr(predicate::str::contains("Thread length:").and(predicate::str::contains(" m")));
+    output_file.assert(predicate::str::contains("width=\"500mm\""));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.args(["--frame-size", "50parsecs"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn string_art_template() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let peg_file = temp_dir.child("pegs.json");
+    let bp_file = temp_dir.child("bp.json");
+    let svg_template = temp_dir.child("template.svg");
+    let dxf_template = temp_dir.child("template.dxf");
+    let bp_template = temp_dir.child("bp_template.svg");
+
+    // from the image, writing the pegs and the blueprint
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-q", "--frame-size", "40cm"]);
+    cmd.args(["--save-pegs", peg_file.to_str().unwrap()]);
+    cmd.args(["--template", svg_template.to_str().unwrap()]);
+    cmd.assert().success();
+    svg_template.assert(predicate::str::contains("mm\""));
+    // 60 peg holes and 4 registration marks
+    svg_template.assert(predicate::function(|svg: &str| {
+        svg.matches("<circle").count() == 64
+    }));
+
+    // from the peg file, with the dimensions of the image
+    let (width, height) = image::open(input_file())?.dimensions();
+    let image_size = format!("{width}x{height}");
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(peg_file.to_str().unwrap());
+    cmd.args(["--frame-size", "40cm", "--image-size", &image_size]);
+    cmd.args(["--template", dxf_template.to_str().unwrap()]);
+    cmd.assert().success();
+    dxf_template.assert(predicate::str::contains("CIRCLE").and(predicate::str::ends_with("EOF\n")));
+
+    // the peg file requires a frame size and the image size
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(peg_file.to_str().unwrap());
+    cmd.args(["--template", dxf_template.to_str().unwrap()]);
+    cmd.args(["--image-size", &image_size]);
+    cmd.assert().failure();
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(peg_file.to_str().unwrap());
+    cmd.args(["--template", dxf_template.to_str().unwrap()]);
+    cmd.args(["--frame-size", "40cm"]);
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("Image size required"));
+
+    // from the blueprint, which recorded the frame size
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["--template", bp_template.to_str().unwrap()]);
+    cmd.assert().success();
+    bp_template.assert(predicate::path::eq_file(svg_template.path()));
+    Ok(())
+}
+
+#[test]
+fn string_art_gcode() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bp_file = temp_dir.child("bp.json");
+    let gcode_file = temp_dir.child("bp.gcode");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-i", "50", "-n", "60", "-q"]);
+    cmd.assert().success();
+
+    // the G-code is in millimeters, it requires the frame size
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.arg(gcode_file.to_str().unwrap());
+    cmd.assert().failure();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.arg(gcode_file.to_str().unwrap());
+    cmd.args(["--frame-size", "400mm", "--feed-rate", "1200"]);
+    cmd.args(["--machine-offset", "-200", "-200"]);
+    cmd.assert().success();
+    gcode_file.assert(predicate::str::contains("G2 ").and(predicate::str::contains(" F1200\n")));
+
+    // the simulator reads back the peg order
+    let mut bp = Blueprint::from_file(bp_file.path())?;
+    bp.frame_size = Some(400.);
+    let config = GcodeConfig {
+        offset: (-200., -200.),
+        feed_rate: 1200.,
+        ..Default::default()
+    };
+    let gcode = std::fs::read_to_string(gcode_file.path())?;
+    let strands = simulate(&gcode, &bp, &config)?;
+    assert_eq!(strands.len(), 1);
+    let ids = |pegs: &[Peg]| pegs.iter().map(|peg| peg.id).collect::<Vec<_>>();
+    assert_eq!(ids(&strands[0]), ids(&bp.peg_order));
+    Ok(())
+}
+
+#[test]
+fn string_art_peg_radius() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bp_file = temp_dir.child("bp.json");
+    let resumed_file = temp_dir.child("resumed.json");
+    let instructions_file = temp_dir.child("instructions.txt");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-i", "20", "-n", "30", "-q", "--peg-radius", "3"]);
+    cmd.args(["--instructions", instructions_file.to_str().unwrap()]);
+    cmd.assert().success();
+    instructions_file.assert(predicate::str::is_match(r"\d+ -> \d+ \(c?cw\)\n").unwrap());
+
+    let bp = Blueprint::from_file(bp_file.path())?;
+    assert_eq!(bp.wraps.len(), bp.peg_order.len());
+    assert!(bp.peg_order.iter().all(|peg| peg.radius == Some(3.)));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(resumed_file.to_str().unwrap());
+    cmd.args(["-i", "10", "-n", "30", "-q", "--peg-radius", "3"]);
+    cmd.args(["--resume", bp_file.to_str().unwrap()]);
+    cmd.assert().success();
+
+    let resumed = Blueprint::from_file(resumed_file.path())?;
+    assert_eq!(resumed.peg_order.len(), bp.peg_order.len() + 10);
+    assert_eq!(resumed.wraps[..bp.wraps.len()], bp.wraps[..]);
+    Ok(())
+}
+
+#[test]
+fn string_art_animation() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bp_file = temp_dir.child("bp.json");
+    let gif_file = temp_dir.child("build.gif");
+    let apng_file = temp_dir.child("build.apng");
+    let frame_file = temp_dir.child("frame.png");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(gif_file.to_str().unwrap());
+    cmd.args(["-i", "20", "-n", "60", "-q", "--lines-per-frame", "5"]);
+    cmd.assert().success();
+    let decoder =
+        image::codecs::gif::GifDecoder::new(BufReader::new(File::open(gif_file.path())?))?;
+    assert_eq!(image::AnimationDecoder::into_frames(decoder).count(), 4);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-i", "20", "-n", "60", "-q"]);
+    cmd.assert().success();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.arg(apng_file.to_str().unwrap());
+    cmd.args(["--lines-per-frame", "10", "--frame-rate", "5"]);
+    cmd.assert().success();
+    apng_file.assert(predicate::path::is_file());
+
+    // a numbered png sequence
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.arg(frame_file.to_str().unwrap());
+    cmd.args(["--lines-per-frame", "10"]);
+    cmd.assert().success();
+    frame_file.assert(predicate::path::missing());
+    temp_dir
+        .child("frame_0001.png")
+        .assert(predicate::path::is_file());
+    temp_dir
+        .child("frame_0002.png")
+        .assert(predicate::path::is_file());
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.arg(gif_file.to_str().unwrap());
+    cmd.args(["--lines-per-frame", "0"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn string_art_score() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let few_lines_file = temp_dir.child("few_lines.json");
+    let more_lines_file = temp_dir.child("more_lines.json");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(more_lines_file.to_str().unwrap());
+    cmd.args(["-i", "100", "-n", "60", "-q"]);
+    cmd.assert().success();
+    // the first lines of the same path
+    let mut bp = Blueprint::from_file(more_lines_file.path())?;
+    bp.peg_order.truncate(6);
+    bp.to_file(&few_lines_file)?;
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["score", input_file().to_str().unwrap()]);
+    cmd.arg(few_lines_file.to_str().unwrap());
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("PSNR:"))
+        .stdout(predicate::str::contains("SSIM:"));
+
+    let mut scores = vec![];
+    for bp_file in [&few_lines_file, &more_lines_file] {
+        let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+        cmd.args(["score", input_file().to_str().unwrap()]);
+        cmd.args([bp_file.to_str().unwrap(), "--json"]);
+        let output = cmd.assert().success().get_output().stdout.clone();
+        let metrics: serde_json::Value = serde_json::from_slice(&output)?;
+        scores.push(metrics["mse"].as_f64().unwrap());
+    }
+    // more lines get closer to the source image
+    assert!(scores[1] < scores[0]);
+
+    // the blueprint must match the image's aspect ratio
+    let tall_file = temp_dir.child("tall.json");
+    Blueprint::new(vec![], 10, 50, Some((255, 255, 255)), 1., false).to_file(&tall_file)?;
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["score", input_file().to_str().unwrap()]);
+    cmd.arg(tall_file.to_str().unwrap());
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn string_art_tune() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bp_file = temp_dir.child("best.json");
+    let report_file = temp_dir.child("report.csv");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["tune", input_file().to_str().unwrap()]);
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-n", "20", "30", "-i", "5", "20", "-o", "0.2", "-q"]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("blurred_mse"));
+    let bp = Blueprint::from_file(bp_file.path())?;
+    assert_eq!(bp.peg_order.len(), 21);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["tune", input_file().to_str().unwrap()]);
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args([
+        "-n", "20", "30", "-i", "5", "20", "--random", "3", "--metric", "ssim", "-q",
+    ]);
+    cmd.args(["--report", report_file.to_str().unwrap()]);
+    cmd.assert().success();
+    let report = std::fs::read_to_string(report_file.path())?;
+    assert_eq!(report.lines().count(), 4);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["tune", input_file().to_str().unwrap()]);
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-o", "1.5"]);
+    cmd.assert().failure();
+
+    // the input must be an image
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["tune", bp_file.to_str().unwrap()]);
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.assert()
+        .failure()
+        .stderr(predicate::str::contains("is not an image"));
+    Ok(())
+}
+
+#[test]
+fn subcommands() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bp_file = temp_dir.child("bp.json");
+    let pegs_file = temp_dir.child("pegs.json");
+    let template_file = temp_dir.child("template.svg");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["pegs", input_file().to_str().unwrap()]);
+    cmd.arg(pegs_file.to_str().unwrap());
+    cmd.args(["-n", "30", "--frame-size", "30cm"]);
+    cmd.args(["--template", template_file.to_str().unwrap()]);
+    cmd.assert().success();
+    template_file.assert(predicate::path::is_file());
+    let reader = BufReader::new(File::open(pegs_file.path())?);
+    let pegs: Vec<Peg> = serde_json::from_reader(reader)?;
+    assert_eq!(pegs.len(), 30);
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["generate", input_file().to_str().unwrap()]);
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["--load-pegs", pegs_file.to_str().unwrap(), "-i", "50", "-q"]);
+    cmd.assert().success();
+    let bp = Blueprint::from_file(bp_file.path())?;
+    assert_eq!(bp.peg_order.len(), 51);
+
+    // the yarn color is used when rendering a blueprint
+    let img_file = temp_dir.child("red.png");
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["render", bp_file.to_str().unwrap()]);
+    cmd.arg(img_file.to_str().unwrap());
+    cmd.args(["-c", "255 0 0", "-O", "1", "-q"]);
+    cmd.assert().success();
+    let img = image::open(img_file.path())?.to_rgb8();
+    assert!(img.pixels().any(|pixel| pixel.0 == [255, 0, 0]));
+    assert!(img.pixels().all(|pixel| pixel.0[0] == 255));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["render", bp_file.to_str().unwrap()]);
+    cmd.arg(temp_dir.child("render.json").to_str().unwrap());
+    cmd.assert().failure();
+
+    let gcode_file = temp_dir.child("bp.gcode");
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["convert", bp_file.to_str().unwrap()]);
+    cmd.arg(gcode_file.to_str().unwrap());
+    cmd.args(["--frame-size", "50cm"]);
+    cmd.assert().success();
+    gcode_file.assert(predicate::path::is_file());
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["convert", bp_file.to_str().unwrap()]);
+    cmd.arg(temp_dir.child("render.png").to_str().unwrap());
+    cmd.assert().failure();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["info", bp_file.to_str().unwrap()]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Lines:             50"))
+        .stdout(predicate::str::contains("Unique pegs:"));
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["info", bp_file.to_str().unwrap(), "--json"]);
+    let output = cmd.assert().success().get_output().stdout.clone();
+    let stats: serde_json::Value = serde_json::from_slice(&output)?;
+    assert_eq!(stats["lines"], 50);
+    let visits: u64 = stats["peg_usage"]
+        .as_object()
+        .unwrap()
+        .values()
+        .map(|count| count.as_u64().unwrap())
+        .sum();
+    assert_eq!(visits, 51);
+
+    // the subcommands only accept their own options
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["render", bp_file.to_str().unwrap()]);
+    cmd.arg(img_file.to_str().unwrap());
+    cmd.args(["-i", "10"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn legacy_render_yarn_color() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let bp_file = temp_dir.child("bp.json");
+    let img_file = temp_dir.child("red.png");
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(input_file().to_str().unwrap());
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.args(["-i", "20", "-n", "20", "-q"]);
+    cmd.assert().success();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.arg(bp_file.to_str().unwrap());
+    cmd.arg(img_file.to_str().unwrap());
+    cmd.args(["-c", "255 0 0", "-O", "1", "-q"]);
+    cmd.assert().success();
+    let img = image::open(img_file.path())?.to_rgb8();
+    assert!(img.pixels().any(|pixel| pixel.0 == [255, 0, 0]));
+    Ok(())
+}
+
+#[test]
+fn peg_shapes() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let pegs_file = temp_dir.child("pegs.json");
+    let heart = "M 10,30 A 20,20 0,0,1 50,30 A 20,20 0,0,1 90,30 Q 90,60 50,90 Q 10,60 10,30 z";
+    let (width, height) = image::image_dimensions(input_file())?;
+
+    for shape_args in [
+        vec!["-S", "ellipse"],
+        vec!["-S", "polygon", "--polygon-sides", "5"],
+        vec!["-S", "polygon", "--polygon-rotation", "-45"],
+        vec!["-S", "path", "--peg-path", heart],
+    ] {
+        let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+        cmd.args(["pegs", input_file().to_str().unwrap()]);
+        cmd.arg(pegs_file.to_str().unwrap());
+        cmd.args(["-n", "60"]);
+        cmd.args(&shape_args);
+        cmd.assert().success();
+        let reader = BufReader::new(File::open(pegs_file.path())?);
+        let pegs: Vec<Peg> = serde_json::from_reader(reader)?;
+        assert_eq!(pegs.len(), 60);
+        assert!(pegs.iter().all(|peg| peg.x < width && peg.y < height));
+    }
+
+    // the path shape requires a valid path
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["pegs", input_file().to_str().unwrap()]);
+    cmd.arg(pegs_file.to_str().unwrap());
+    cmd.args(["-S", "path"]);
+    cmd.assert().failure();
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["pegs", input_file().to_str().unwrap()]);
+    cmd.arg(pegs_file.to_str().unwrap());
+    cmd.args(["-S", "path", "--peg-path", "M 0 0 X"]);
+    cmd.assert().failure();
+    Ok(())
+}
+
+#[test]
+fn interior_pegs() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let pegs_file = temp_dir.child("pegs.json");
+    let (width, height) = image::image_dimensions(input_file())?;
+
+    for layout in ["square-grid", "hex-grid", "poisson", "adaptive"] {
+        let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+        cmd.args(["pegs", input_file().to_str().unwrap()]);
+        cmd.arg(pegs_file.to_str().unwrap());
+        cmd.args(["-n", "60", "--interior", layout]);
+        cmd.assert().success();
+        let reader = BufReader::new(File::open(pegs_file.path())?);
+        let pegs: Vec<Peg> = serde_json::from_reader(reader)?;
+        assert!(pegs.len() > 60, "{layout} added no interior pegs");
+        assert!(pegs.iter().all(|peg| peg.x < width && peg.y < height));
+    }
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["generate", input_file().to_str().unwrap()]);
+    cmd.arg(temp_dir.child("blueprint.json").to_str().unwrap());
+    cmd.args(["-n", "60", "-i", "20", "--interior", "hex-grid"]);
+    cmd.args(["--interior-spacing", "40", "--peg-clearance", "2"]);
+    cmd.assert().success();
+    temp_dir
+        .child("blueprint.json")
+        .assert(predicate::path::is_file());
+
+    // closely spaced pegs don't block the lines leaving their neighbours
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["generate", input_file().to_str().unwrap()]);
+    cmd.arg(temp_dir.child("blueprint.json").to_str().unwrap());
+    cmd.args([
+        "-n",
+        "288",
+        "-i",
+        "5",
+        "--lazy-lines",
+        "0",
+        "--peg-clearance",
+        "6",
+    ]);
+    cmd.assert().success();
+    let bp = Blueprint::from_file(temp_dir.child("blueprint.json").path())?;
+    assert_eq!(bp.peg_order.len(), 6);
+    Ok(())
+}
+
+#[test]
+fn string_art_seed() -> Result<(), Box<dyn Error>> {
+    let temp_dir = assert_fs::TempDir::new().unwrap();
+    let generate = |seed: &str| -> Result<Blueprint, Box<dyn Error>> {
+        let output_file = temp_dir.child(format!("output_{seed}.json"));
+        let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+        cmd.args(["generate", input_file().to_str().unwrap()]);
+        cmd.arg(output_file.to_str().unwrap());
+        cmd.args(["-i", "20", "-n", "40", "-j", "5", "--interior", "poisson"]);
+        cmd.args(["--interior-spacing", "80"]);
+        cmd.args(["--seed", seed]);
+        cmd.assert().success();
+        Blueprint::from_file(output_file.path())
+    };
+    let coords = |bp: &Blueprint| {
+        bp.peg_order
+            .iter()
+            .map(|peg| (peg.x, peg.y, peg.id))
+            .collect::<Vec<_>>()
+    };
+
+    let bp = generate("7")?;
+    assert_eq!(bp.seed, Some(7));
+    assert_eq!(coords(&bp), coords(&generate("7")?));
+    assert_ne!(coords(&bp), coords(&generate("8")?));
+
+    // the seed is recorded when not given
+    let output_file = temp_dir.child("output.json");
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["generate", input_file().to_str().unwrap()]);
+    cmd.arg(output_file.to_str().unwrap());
+    cmd.args(["-i", "20", "-n", "40"]);
+    cmd.assert().success();
+    assert!(Blueprint::from_file(output_file.path())?.seed.is_some());
+
+    let mut cmd = assert_cmd::Command::cargo_bin("strandify")?;
+    cmd.args(["info", output_file.to_str().unwrap()]);
+    cmd.assert()
+        .success()
+        .stdout(predicate::str::contains("Seed:"));
+    Ok(())
+}
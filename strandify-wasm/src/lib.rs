@@ -1,7 +1,7 @@
 mod wrapper;
 use base64::{engine::general_purpose, Engine as _};
 use std::io::Cursor;
-use strandify::{blueprint::Blueprint, pather::Pather};
+use strandify::{blueprint::Blueprint, pather::Pather, peg::PegLayout};
 use wasm_bindgen::prelude::*;
 
 pub use wrapper::*;
@@ -15,29 +15,83 @@ fn compute_bp(
     image_data: &[u8],
     pegs: Vec<wrapper::Peg>,
     pather_config: wrapper::PatherConfig,
+    weight_mask_data: Option<Vec<u8>>,
+    on_step: Option<js_sys::Function>,
+    work_image_every: Option<usize>,
 ) -> Result<Blueprint, JsValue> {
     let image = match image::load_from_memory(image_data) {
         Ok(image) => image.to_luma8(),
         Err(err) => return Err(JsValue::from(err.to_string())),
     };
 
-    let pegs = pegs.iter().map(|peg| peg.inner).collect();
+    let pegs: PegLayout = pegs.iter().map(|peg| peg.inner).collect();
     let mut pather = Pather::new(image, pegs, pather_config.inner);
+    if let Some(weight_mask_data) = weight_mask_data {
+        match image::load_from_memory(&weight_mask_data) {
+            Ok(weight_mask) => pather.weight_mask = Some(weight_mask.to_luma8()),
+            Err(err) => return Err(JsValue::from(err.to_string())),
+        };
+    }
 
-    pather
-        .compute()
-        .map_err(|err| JsValue::from(err.to_string()))
+    let Some(on_step) = on_step else {
+        return pather
+            .compute()
+            .map_err(|err| JsValue::from(err.to_string()));
+    };
+
+    let mut js_err = None;
+    let bp = pather
+        .compute_with_callback(|step, work_image| {
+            // copying the work image is costly, only done every `work_image_every` lines
+            let work_image = work_image_every
+                .filter(|every| *every > 0 && (step.iteration + 1) % every == 0)
+                .map(|_| work_image.as_raw().clone());
+            let step = wrapper::PatherStep {
+                inner: step.clone(),
+                work_image,
+            };
+            match on_step.call1(&JsValue::NULL, &JsValue::from(step)) {
+                // only an explicit `false` stops the pathing
+                Ok(value) => value.as_bool() != Some(false),
+                Err(err) => {
+                    js_err = Some(err);
+                    false
+                }
+            }
+        })
+        .map_err(|err| JsValue::from(err.to_string()))?;
+    match js_err {
+        Some(err) => Err(err),
+        None => Ok(bp),
+    }
 }
 
 /// Compute the [`Blueprint`](crate::blueprint::Blueprint) of the image and return it as an SVG string.
+///
+/// An optional grayscale weight mask image, with the same dimensions as the image, can be provided
+/// to steer the lines.
+///
+/// An optional `onStep` callback is called with a `PatherStep` after each line, holding the
+/// chosen peg and the line's loss, and the grayscale work image every `workImageEvery` lines.
+/// Returning `false` from the callback stops the pathing early.
 #[wasm_bindgen(js_name = computeSvg)]
 pub fn compute_svg(
     image_data: &[u8],
     pegs: Vec<wrapper::Peg>,
     pather_config: wrapper::PatherConfig,
     yarn: wrapper::Yarn,
+    weight_mask_data: Option<Vec<u8>>,
+    on_step: Option<js_sys::Function>,
+    work_image_every: Option<usize>,
 ) -> Result<String, JsValue> {
-    let bp = compute_bp(image_data, pegs, pather_config)?;
+    let bp = compute_bp(
+        image_data,
+        pegs,
+        pather_config,
+        weight_mask_data,
+        on_step,
+        work_image_every,
+    )?;
 
     Ok(bp
         .render_svg(&yarn.inner)
@@ -46,14 +100,31 @@ pub fn compute_svg(
 }
 
 /// Compute the [`Blueprint`](crate::blueprint::Blueprint) of the image and return it as a base64-encoded PNG string.
+///
+/// An optional grayscale weight mask image, with the same dimensions as the image, can be provided
+/// to steer the lines.
+///
+/// An optional `onStep` callback is called with a `PatherStep` after each line, holding the
+/// chosen peg and the line's loss, and the grayscale work image every `workImageEvery` lines.
+/// Returning `false` from the callback stops the pathing early.
 #[wasm_bindgen(js_name = computePng)]
 pub fn compute_png(
     image_data: &[u8],
     pegs: Vec<wrapper::Peg>,
     pather_config: wrapper::PatherConfig,
     yarn: wrapper::Yarn,
+    weight_mask_data: Option<Vec<u8>>,
+    on_step: Option<js_sys::Function>,
+    work_image_every: Option<usize>,
 ) -> Result<String, JsValue> {
-    let bp = compute_bp(image_data, pegs, pather_config)?;
+    let bp = compute_bp(
+        image_data,
+        pegs,
+        pather_config,
+        weight_mask_data,
+        on_step,
+        work_image_every,
+    )?;
     let img = bp
         .render_img(&yarn.inner)
         .map_err(|err| JsValue::from(err.to_string()))?;
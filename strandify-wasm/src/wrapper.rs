@@ -1,14 +1,23 @@
 #![allow(non_snake_case)]
 use strandify::pather::EarlyStopConfig as RsEarlyStopConfig;
+use strandify::pather::LineMode as RsLineMode;
 use strandify::pather::PatherConfig as RsPatherConfig;
+use strandify::pather::PatherStep as RsPatherStep;
 use strandify::peg::Peg as RsPeg;
 use strandify::peg::Yarn as RsYarn;
 use wasm_bindgen::prelude::*;
 
 use strandify::utils::circle_coords as rs_circle_coords;
+use strandify::utils::ellipse_coords as rs_ellipse_coords;
+use strandify::utils::hex_grid_coords as rs_hex_grid_coords;
 use strandify::utils::line_coords as rs_line_coords;
+use strandify::utils::poisson_disc_coords as rs_poisson_disc_coords;
 use strandify::utils::rectangle_coords as rs_rectangle_coords;
+use strandify::utils::regular_polygon_coords as rs_regular_polygon_coords;
+use strandify::utils::seeded_rng as rs_seeded_rng;
 use strandify::utils::square_coords as rs_square_coords;
+use strandify::utils::square_grid_coords as rs_square_grid_coords;
+use strandify::utils::svg_path_coords as rs_svg_path_coords;
 
 #[derive(Clone)]
 #[wasm_bindgen]
@@ -49,6 +58,69 @@ pub fn rectangle_coords(x: u32, y: u32, width: u32, height: u32, n_points: usize
     let (x, y) = rs_rectangle_coords((x, y), width, height, n_points);
     ShapeCoords { x, y }
 }
+#[wasm_bindgen(js_name = ellipseCoords)]
+pub fn ellipse_coords(
+    x: u32,
+    y: u32,
+    radius_x: u32,
+    radius_y: u32,
+    n_points: usize,
+) -> ShapeCoords {
+    let (x, y) = rs_ellipse_coords((x, y), (radius_x, radius_y), n_points);
+    ShapeCoords { x, y }
+}
+#[wasm_bindgen(js_name = regularPolygonCoords)]
+pub fn regular_polygon_coords(
+    x: u32,
+    y: u32,
+    radius: u32,
+    n_sides: usize,
+    rotation: f64,
+    n_points: usize,
+) -> ShapeCoords {
+    let (x, y) = rs_regular_polygon_coords((x, y), radius, n_sides, rotation, n_points);
+    ShapeCoords { x, y }
+}
+#[wasm_bindgen(js_name = svgPathCoords)]
+pub fn svg_path_coords(
+    path_data: &str,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    n_points: usize,
+) -> Result<ShapeCoords, JsValue> {
+    let (x, y) = rs_svg_path_coords(path_data, (x, y), (width, height), n_points)
+        .map_err(|err| JsValue::from(err.to_string()))?;
+    Ok(ShapeCoords { x, y })
+}
+#[wasm_bindgen(js_name = squareGridCoords)]
+pub fn square_grid_coords(x: u32, y: u32, width: u32, height: u32, spacing: f64) -> ShapeCoords {
+    let (x, y) = rs_square_grid_coords((x, y), (width, height), spacing);
+    ShapeCoords { x, y }
+}
+#[wasm_bindgen(js_name = hexGridCoords)]
+pub fn hex_grid_coords(x: u32, y: u32, width: u32, height: u32, spacing: f64) -> ShapeCoords {
+    let (x, y) = rs_hex_grid_coords((x, y), (width, height), spacing);
+    ShapeCoords { x, y }
+}
+#[wasm_bindgen(js_name = poissonDiscCoords)]
+pub fn poisson_disc_coords(
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    minDistance: f64,
+    seed: Option<u64>,
+) -> ShapeCoords {
+    let (x, y) = rs_poisson_disc_coords(
+        (x, y),
+        (width, height),
+        minDistance,
+        &mut rs_seeded_rng(seed),
+    );
+    ShapeCoords { x, y }
+}
 
 #[wasm_bindgen]
 pub struct Peg {
@@ -64,14 +136,34 @@ impl Peg {
         }
     }
 
+    /// Jitter a single peg, jittering each peg of a layout with its own seed shifts them all
+    /// the same way, use `jitterPegs` instead.
     #[wasm_bindgen(js_name = withJitter)]
-    pub fn with_jitter(&self, jitter: i64) -> Self {
+    pub fn with_jitter(&self, jitter: i64, seed: Option<u64>) -> Self {
         Self {
-            inner: self.inner.with_jitter(jitter),
+            inner: self.inner.with_jitter(jitter, &mut rs_seeded_rng(seed)),
+        }
+    }
+
+    #[wasm_bindgen(js_name = withRadius)]
+    pub fn with_radius(&self, radius: f32) -> Self {
+        Self {
+            inner: self.inner.with_radius(radius),
         }
     }
 }
 
+/// Jitter the pegs of a layout, drawing their offsets from a single random number generator.
+#[wasm_bindgen(js_name = jitterPegs)]
+pub fn jitter_pegs(pegs: Vec<Peg>, jitter: i64, seed: Option<u64>) -> Vec<Peg> {
+    let mut rng = rs_seeded_rng(seed);
+    pegs.into_iter()
+        .map(|peg| Peg {
+            inner: peg.inner.with_jitter(jitter, &mut rng),
+        })
+        .collect()
+}
+
 #[wasm_bindgen]
 pub struct Yarn {
     pub(crate) inner: RsYarn,
@@ -133,4 +225,76 @@ impl PatherConfig {
             ),
         }
     }
+
+    #[wasm_bindgen(js_name = setExcludeMasked)]
+    pub fn set_exclude_masked(&mut self, excludeMasked: bool) {
+        self.inner.exclude_masked = excludeMasked;
+    }
+
+    #[wasm_bindgen(js_name = setAntiAliasing)]
+    pub fn set_anti_aliasing(&mut self, antiAliasing: bool) {
+        self.inner.anti_aliasing = antiAliasing;
+    }
+
+    #[wasm_bindgen(js_name = setLazyLines)]
+    pub fn set_lazy_lines(&mut self, cacheCapacity: usize) {
+        self.inner.line_mode = RsLineMode::Lazy {
+            cache_capacity: cacheCapacity,
+        };
+    }
+
+    #[wasm_bindgen(js_name = setWrapping)]
+    pub fn set_wrapping(&mut self, wrapping: bool) {
+        self.inner.wrapping = wrapping;
+    }
+
+    #[wasm_bindgen(js_name = setPegClearance)]
+    pub fn set_peg_clearance(&mut self, pegClearance: Option<f32>) {
+        self.inner.peg_clearance = pegClearance;
+    }
+}
+
+#[wasm_bindgen]
+pub struct PatherStep {
+    pub(crate) inner: RsPatherStep,
+    pub(crate) work_image: Option<Vec<u8>>,
+}
+
+#[wasm_bindgen]
+impl PatherStep {
+    #[wasm_bindgen(getter)]
+    pub fn iteration(&self) -> usize {
+        self.inner.iteration
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn from(&self) -> Peg {
+        Peg {
+            inner: self.inner.from,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn peg(&self) -> Peg {
+        Peg {
+            inner: self.inner.peg,
+        }
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn wrap(&self) -> String {
+        self.inner.wrap.to_string()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn loss(&self) -> f64 {
+        self.inner.loss
+    }
+
+    /// Take the grayscale work image, only set every `workImageEvery` lines, returns
+    /// `undefined` otherwise and on later calls.
+    #[wasm_bindgen(js_name = workImage)]
+    pub fn work_image(&mut self) -> Option<Vec<u8>> {
+        self.work_image.take()
+    }
 }
@@ -47,9 +47,19 @@ fn benchmark_greedy(c: &mut Criterion) {
     });
 }
 
+fn benchmark_line_cache(c: &mut Criterion) {
+    let mut pather = create_pather();
+    c.bench_function("populate_line_cache", |b| {
+        b.iter(|| {
+            let result = black_box(pather.populate_line_cache());
+            assert!(result.is_ok());
+        })
+    });
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().sample_size(50);
-    targets = benchmark_greedy, benchmark_beam_search
+    targets = benchmark_greedy, benchmark_beam_search, benchmark_line_cache
 }
 criterion_main!(benches);
@@ -0,0 +1,47 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use strandify::blueprint::Blueprint;
+use strandify::peg::{self, Yarn};
+
+fn create_blueprint(n_lines: usize, render_scale: f64) -> Blueprint {
+    let pegs = peg::shape::circle((250, 250), 240, 288);
+    let peg_order = (0..=n_lines)
+        .map(|i| pegs[(i * 97 + i / 7) % pegs.len()])
+        .collect();
+    Blueprint::new(
+        peg_order,
+        500,
+        500,
+        Some((255, 255, 255)),
+        render_scale,
+        false,
+    )
+}
+
+fn benchmark_render_img(c: &mut Criterion) {
+    let yarn = Yarn::default();
+    let mut group = c.benchmark_group("render_img");
+    for (n_lines, render_scale) in [(2000, 1.), (10000, 2.)] {
+        let bp = create_blueprint(n_lines, render_scale);
+        group.bench_function(format!("svg_{n_lines}_x{render_scale}"), |b| {
+            b.iter(|| {
+                let result = black_box(bp.render_img_svg(&yarn));
+                assert!(result.is_ok());
+            })
+        });
+        group.bench_function(format!("native_{n_lines}_x{render_scale}"), |b| {
+            b.iter(|| {
+                let result = black_box(bp.render_img(&yarn));
+                assert!(result.is_ok());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = benchmark_render_img
+}
+criterion_main!(benches);
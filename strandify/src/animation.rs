@@ -0,0 +1,324 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use resvg::tiny_skia;
+
+use crate::blueprint::{self, Blueprint, Segment};
+use crate::peg::Yarn;
+use crate::utils;
+
+#[derive(Debug, Clone, Copy)]
+/// Configuration of the [`Animation`] of the build process.
+pub struct AnimationConfig {
+    /// Number of lines added by each frame.
+    pub lines_per_frame: usize,
+    /// Number of frames per second, positive.
+    pub frame_rate: f64,
+}
+
+impl Default for AnimationConfig {
+    fn default() -> Self {
+        Self {
+            lines_per_frame: 10,
+            frame_rate: 25.,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// Animation of the build process of a [`Blueprint`], the lines appear in peg order,
+/// [`Strand`](crate::blueprint::Strand) after [`Strand`](crate::blueprint::Strand). Written as a
+/// GIF, an APNG or a numbered PNG sequence.
+///
+/// The frames are rendered one at a time, each frame's lines are drawn on top of the previous
+/// frame, so the last frame matches [`Blueprint::render_img`].
+pub struct Animation<'a> {
+    blueprint: &'a Blueprint,
+    yarn: Yarn,
+    /// The [`AnimationConfig`].
+    pub config: AnimationConfig,
+}
+
+impl<'a> Animation<'a> {
+    /// Create the [`Animation`] of a [`Blueprint`].
+    ///
+    /// # Arguments
+    ///
+    /// * `blueprint`: The [`Blueprint`] to animate.
+    /// * `yarn`: The [`Yarn`] to use to render the frames, see [`Blueprint::render_img`].
+    /// * `config`: The [`AnimationConfig`].
+    pub fn new(blueprint: &'a Blueprint, yarn: &Yarn, config: AnimationConfig) -> Self {
+        Self {
+            blueprint,
+            yarn: yarn.clone(),
+            config,
+        }
+    }
+
+    /// Number of lines added by each frame, at least 1.
+    fn lines_per_frame(&self) -> usize {
+        self.config.lines_per_frame.max(1)
+    }
+
+    /// Number of frames of the [`Animation`], a single frame of background when the
+    /// [`Blueprint`] has no lines.
+    pub fn n_frames(&self) -> usize {
+        let n_lines = self.blueprint.segments().count();
+        n_lines.div_ceil(self.lines_per_frame()).max(1)
+    }
+
+    /// Display duration of each frame.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the [`AnimationConfig::frame_rate`] isn't a positive
+    /// number, or is too small for the delay to be represented.
+    pub fn frame_delay(&self) -> Result<Duration, Box<dyn Error>> {
+        let frame_rate = self.config.frame_rate;
+        if frame_rate.is_nan() || frame_rate <= 0. {
+            return Err(format!("Frame rate must be positive, got {frame_rate}.").into());
+        }
+        Ok(Duration::try_from_secs_f64(1. / frame_rate)?)
+    }
+
+    /// Iterate over the rendered frames.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::animation::AnimationConfig;
+    /// use strandify::blueprint::Blueprint;
+    /// use strandify::peg::{Peg, Yarn};
+    ///
+    /// let pegs = vec![Peg::new(0, 0), Peg::new(9, 9), Peg::new(0, 9), Peg::new(9, 0)];
+    /// let bp = Blueprint::new(pegs, 10, 10, Some((255, 255, 255)), 1., false);
+    /// let config = AnimationConfig {
+    ///     lines_per_frame: 2,
+    ///     ..Default::default()
+    /// };
+    /// let animation = bp.animation(&Yarn::default(), config);
+    /// assert_eq!(animation.frames().unwrap().count(), 2);
+    /// ```
+    pub fn frames(&self) -> Result<Frames<'_>, Box<dyn Error>> {
+        let (width, height) = self.blueprint.render_dimensions();
+        let pixmap = tiny_skia::Pixmap::new(width, height)
+            .ok_or("Could not create a pixmap of the render dimensions.")?;
+        Ok(Frames {
+            animation: self,
+            lines: self.blueprint.yarn_segments(&self.yarn),
+            pixmap,
+            frame: 0,
+            n_frames: self.n_frames(),
+        })
+    }
+
+    /// Write the [`Animation`] as an infinitely looping GIF.
+    pub fn write_gif<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let pbar = utils::pbar(self.n_frames() as u64, !self.blueprint.progress_bar)?
+            .with_message("Rendering gif");
+        let delay = Delay::from_saturating_duration(self.frame_delay()?);
+        let mut encoder = GifEncoder::new_with_speed(writer, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for frame in pbar.wrap_iter(self.frames()?) {
+            encoder.encode_frame(Frame::from_parts(frame, 0, 0, delay))?;
+        }
+        Ok(())
+    }
+
+    /// Write the [`Animation`] as an infinitely looping APNG.
+    pub fn write_apng<W: Write>(&self, writer: W) -> Result<(), Box<dyn Error>> {
+        let pbar = utils::pbar(self.n_frames() as u64, !self.blueprint.progress_bar)?
+            .with_message("Rendering apng");
+        let (width, height) = self.blueprint.render_dimensions();
+        let mut encoder = png::Encoder::new(writer, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(self.n_frames() as u32, 0)?;
+        let delay_ms = self.frame_delay()?.as_millis().clamp(1, u16::MAX as u128) as u16;
+        encoder.set_frame_delay(delay_ms, 1000)?;
+
+        let mut writer = encoder.write_header()?;
+        for frame in pbar.wrap_iter(self.frames()?) {
+            writer.write_image_data(frame.as_raw())?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Write the frames of the [`Animation`] as a numbered PNG sequence, the frame number is
+    /// appended to the file stem of `path`, e.g. `frame.png` becomes `frame_0001.png`,
+    /// `frame_0002.png`, ...
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<PathBuf>`: The paths of the written frames.
+    pub fn write_png_sequence<P: AsRef<Path>>(
+        &self,
+        path: P,
+    ) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+        let path = path.as_ref();
+        let stem = path
+            .file_stem()
+            .ok_or("Could not determine file name.")?
+            .to_string_lossy();
+        let digits = self.n_frames().to_string().len().max(4);
+        let pbar = utils::pbar(self.n_frames() as u64, !self.blueprint.progress_bar)?
+            .with_message("Rendering frames");
+
+        let mut paths = vec![];
+        for (index, frame) in pbar.wrap_iter(self.frames()?.enumerate()) {
+            let frame_path = path.with_file_name(format!("{stem}_{:0digits$}.png", index + 1));
+            frame.save(&frame_path)?;
+            paths.push(frame_path);
+        }
+        Ok(paths)
+    }
+
+    /// Write the [`Animation`] to file, the format is determined by the extension, either `gif`,
+    /// `apng` or `png` for a numbered PNG sequence, see [`Animation::write_png_sequence`].
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .ok_or("Could not determine extension.")?
+            .to_string_lossy();
+        match extension.as_ref() {
+            "gif" => self.write_gif(BufWriter::new(File::create(path)?))?,
+            "apng" => self.write_apng(BufWriter::new(File::create(path)?))?,
+            "png" => {
+                self.write_png_sequence(path)?;
+            }
+            _ => return Err(format!("Unsupported animation format '{extension}'.").into()),
+        }
+        Ok(())
+    }
+}
+
+/// Iterator over the rendered frames of an [`Animation`], created with [`Animation::frames`].
+pub struct Frames<'a> {
+    animation: &'a Animation<'a>,
+    lines: Vec<(Yarn, Segment)>,
+    pixmap: tiny_skia::Pixmap,
+    frame: usize,
+    n_frames: usize,
+}
+
+impl Iterator for Frames<'_> {
+    type Item = RgbaImage;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.frame >= self.n_frames {
+            return None;
+        }
+        let lines_per_frame = self.animation.lines_per_frame();
+        let start = (self.frame * lines_per_frame).min(self.lines.len());
+        let end = (start + lines_per_frame).min(self.lines.len());
+        // only the new lines are drawn, on top of the previous frame
+        self.animation.blueprint.draw_lines(
+            &self.lines[start..end],
+            self.frame == 0,
+            &mut self.pixmap,
+        );
+        self.frame += 1;
+        Some(blueprint::pixmap_to_img(&self.pixmap))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.n_frames - self.frame;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Frames<'_> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blueprint::Strand;
+    use crate::peg::Peg;
+    use image::AnimationDecoder;
+    use std::io::Cursor;
+
+    fn blueprint() -> Blueprint {
+        Blueprint::from_strands(
+            vec![
+                Strand::new(
+                    (255, 0, 0),
+                    vec![Peg::new(0, 0), Peg::new(31, 31), Peg::new(0, 31)],
+                ),
+                Strand::new(
+                    (0, 0, 255),
+                    vec![Peg::new(31, 0), Peg::new(0, 16), Peg::new(31, 16)],
+                ),
+            ],
+            32,
+            32,
+            Some((255, 255, 255)),
+            2.,
+            false,
+        )
+    }
+
+    #[test]
+    fn animation_frames() {
+        let bp = blueprint();
+        let yarn = Yarn::new(2., 0.5, (0, 0, 0));
+        let config = AnimationConfig {
+            lines_per_frame: 3,
+            ..Default::default()
+        };
+        let animation = bp.animation(&yarn, config);
+        assert_eq!(animation.n_frames(), 2);
+
+        let frames: Vec<_> = animation.frames().unwrap().collect();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].dimensions(), (64, 64));
+        assert_ne!(frames[0], frames[1]);
+        // the last frame holds all the lines
+        assert_eq!(frames[1], bp.render_img(&yarn).unwrap());
+
+        // a blueprint without lines is a single frame of background
+        let empty = Blueprint::new(vec![], 8, 8, Some((255, 255, 255)), 1., false);
+        let frames: Vec<_> = empty.animation(&yarn, config).frames().unwrap().collect();
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn animation_gif_apng() {
+        let bp = blueprint();
+        let config = AnimationConfig {
+            lines_per_frame: 1,
+            frame_rate: 10.,
+        };
+        let animation = bp.animation(&Yarn::default(), config);
+        assert_eq!(animation.frame_delay().unwrap(), Duration::from_millis(100));
+        for frame_rate in [0., -1., f64::NAN] {
+            let config = AnimationConfig {
+                frame_rate,
+                ..config
+            };
+            let animation = bp.animation(&Yarn::default(), config);
+            assert!(animation.frame_delay().is_err());
+            assert!(animation.write_gif(&mut vec![]).is_err());
+        }
+
+        let mut gif = vec![];
+        animation.write_gif(&mut gif).unwrap();
+        let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(gif)).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].delay(), Delay::from_numer_denom_ms(100, 1));
+
+        let mut apng = vec![];
+        animation.write_apng(&mut apng).unwrap();
+        let reader = png::Decoder::new(Cursor::new(apng)).read_info().unwrap();
+        let control = reader.info().animation_control().unwrap();
+        assert_eq!(control.num_frames, 4);
+        assert_eq!(control.num_plays, 0);
+    }
+}
@@ -16,14 +16,107 @@ use svg::node::element::path::Data;
 use svg::node::element::{Path as PathSVG, Rectangle};
 use svg::{Document, Node};
 
-use crate::peg::{Peg, Yarn};
+use crate::animation::{Animation, AnimationConfig};
+use crate::gcode::{self, GcodeConfig};
+use crate::instructions::Instructions;
+use crate::metrics::Metrics;
+use crate::peg::{Peg, PegLayout, WrapDirection, Yarn};
+use crate::stats::Stats;
+use crate::template::Template;
 use crate::utils;
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A segment of thread, from its start point to its end point, in pixels.
+pub type Segment = ((f64, f64), (f64, f64));
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A single colored thread of a multi [`Yarn`] [`Blueprint`].
+pub struct Strand {
+    /// Color of the [`Strand`]'s [`Yarn`].
+    pub color: (u8, u8, u8),
+    /// The order with which to connect the [`Pegs`](Peg) with this [`Strand`].
+    pub peg_order: Vec<Peg>,
+    /// The [`WrapDirection`] around each [`Peg`] of the peg order, empty when the wrapping
+    /// isn't modelled.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wraps: Vec<WrapDirection>,
+    /// Opacity of the [`Strand`]'s [`Yarn`], the render [`Yarn::opacity`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opacity: Option<f64>,
+    /// Width of the [`Strand`]'s [`Yarn`], in pixels, the render [`Yarn::width`] when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub width: Option<f32>,
+}
+
+impl Strand {
+    /// Creates a new [`Strand`], rendered with the opacity and width of the render [`Yarn`].
+    pub fn new(color: (u8, u8, u8), peg_order: Vec<Peg>) -> Self {
+        Self {
+            color,
+            peg_order,
+            wraps: vec![],
+            opacity: None,
+            width: None,
+        }
+    }
+
+    /// Creates a new [`Strand`] of a [`Yarn`], rendered with its color, opacity and width.
+    pub fn from_yarn(yarn: &Yarn, peg_order: Vec<Peg>) -> Self {
+        Self {
+            opacity: Some(yarn.opacity),
+            width: Some(yarn.width),
+            ..Self::new(yarn.color, peg_order)
+        }
+    }
+
+    /// The [`Yarn`] the [`Strand`] is rendered with, its color, and its opacity and width when
+    /// set, those of `yarn` otherwise.
+    pub fn yarn(&self, yarn: &Yarn) -> Yarn {
+        Yarn::new(
+            self.width.unwrap_or(yarn.width),
+            self.opacity.unwrap_or(yarn.opacity),
+            self.color,
+        )
+    }
+
+    /// Iterate over successive pairs of [`Pegs`](Peg).
+    pub fn zip(
+        &self,
+    ) -> std::iter::Zip<std::slice::Iter<'_, Peg>, std::iter::Skip<std::slice::Iter<'_, Peg>>> {
+        self.peg_order.iter().zip(self.peg_order.iter().skip(1))
+    }
+
+    /// Iterate over the segments of thread between the [`Pegs`](Peg), see
+    /// [`Blueprint::segments`].
+    pub fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        segments(&self.peg_order, &self.wraps)
+    }
+}
+
+/// Iterate over the segments of thread joining the tangent points of successive [`Pegs`](Peg),
+/// wrapped in the `wraps` directions, clockwise when missing.
+fn segments<'a>(
+    peg_order: &'a [Peg],
+    wraps: &'a [WrapDirection],
+) -> impl Iterator<Item = Segment> + 'a {
+    let wrap = |index: usize| wraps.get(index).copied().unwrap_or_default();
+    peg_order
+        .iter()
+        .zip(peg_order.iter().skip(1))
+        .enumerate()
+        .map(move |(index, (peg_a, peg_b))| {
+            peg_a.tangent_points(wrap(index), peg_b, wrap(index + 1))
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// A string art [`Blueprint`]. Holds the result of the [`crate::pather::Pather`]'s pathing algorithm and renders it to file.
 pub struct Blueprint {
     /// The order with which to connect the [`Pegs`](Peg).
     pub peg_order: Vec<Peg>,
+    /// The [`WrapDirection`] around each [`Peg`] of the peg order, empty when the wrapping
+    /// isn't modelled, see [`PatherConfig::wrapping`](crate::pather::PatherConfig::wrapping).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub wraps: Vec<WrapDirection>,
     /// Width of the [`Blueprint`], should be the same dimensions as the image used.
     pub width: u32,
     /// Height of the [`Blueprint`], should be the same dimensions as the image used.
@@ -33,6 +126,24 @@ pub struct Blueprint {
     pub background: Option<(u8, u8, u8)>,
     /// Render scale, how much to up/down scale the render.
     pub render_scale: f64,
+    /// The colored [`Strands`](Strand) of a multi [`Yarn`] [`Blueprint`], rendered on top of
+    /// each other in order. Empty for single [`Yarn`] blueprints, which use
+    /// [`Blueprint::peg_order`].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub strands: Vec<Strand>,
+    /// All the [`Pegs`](Peg) of the frame, including the unused ones, the [`Pegs`](Peg) of the
+    /// peg order and strands are referenced by their [`Peg::id`], their index in the layout. Used
+    /// to number the pegs of the build [`Instructions`].
+    #[serde(default, skip_serializing_if = "<[Peg]>::is_empty")]
+    pub pegs: PegLayout,
+    /// Physical width of the frame, in mm, the height is scaled accordingly. Used to report
+    /// thread lengths and peg positions in mm, and to render svgs with real units.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_size: Option<f64>,
+    /// Seed of the random number generator used to lay out the [`Pegs`](Peg), to reproduce the
+    /// [`Blueprint`], see [`utils::seeded_rng`](crate::utils::seeded_rng).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
     /// Display progress bar.
     #[serde(skip)]
     pub progress_bar: bool,
@@ -54,6 +165,11 @@ impl Blueprint {
             height,
             background,
             render_scale,
+            wraps: vec![],
+            strands: vec![],
+            pegs: PegLayout::default(),
+            frame_size: None,
+            seed: None,
             progress_bar,
         }
     }
@@ -73,18 +189,77 @@ impl Blueprint {
             height,
             background,
             render_scale,
+            wraps: vec![],
+            strands: vec![],
+            pegs: PegLayout::default(),
+            frame_size: None,
+            seed: None,
             progress_bar,
         }
     }
 
-    /// Read a [`Blueprint`] from a json file.
+    /// Create a multi [`Yarn`] [`Blueprint`] from colored [`Strands`](Strand).
+    pub fn from_strands(
+        strands: Vec<Strand>,
+        width: u32,
+        height: u32,
+        background: Option<(u8, u8, u8)>,
+        render_scale: f64,
+        progress_bar: bool,
+    ) -> Self {
+        Self {
+            peg_order: vec![],
+            wraps: vec![],
+            width,
+            height,
+            background,
+            render_scale,
+            strands,
+            pegs: PegLayout::default(),
+            frame_size: None,
+            seed: None,
+            progress_bar,
+        }
+    }
+
+    /// Read a [`Blueprint`] from a json file, see [`Blueprint::link_pegs`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file isn't a valid [`Blueprint`], if its
+    /// [`Blueprint::pegs`] have duplicate ids, or if its peg order uses other [`Pegs`](Peg).
     pub fn from_file<P: AsRef<Path>>(file_path: P) -> Result<Self, Box<dyn Error>> {
         let reader = BufReader::new(File::open(file_path)?);
-        let out: Self = serde_json::from_reader(reader)?;
+        let mut out: Self = serde_json::from_reader(reader)?;
+        out.link_pegs()?;
 
         Ok(out)
     }
 
+    /// Match the [`Pegs`](Peg) of the peg order and of the [`Strands`](Strand) to the
+    /// [`Blueprint::pegs`] layout, see [`PegLayout::find`], and set their ids to their index in
+    /// the layout. Does nothing when the layout is empty.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if a [`Peg`] isn't in the layout.
+    pub fn link_pegs(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.pegs.is_empty() {
+            return Ok(());
+        }
+        let peg_orders = std::iter::once(&mut self.peg_order)
+            .chain(self.strands.iter_mut().map(|strand| &mut strand.peg_order));
+        for peg in peg_orders.flatten() {
+            *peg = *self.pegs.find(peg).ok_or_else(|| {
+                format!(
+                    "Peg at ({}, {}) is not one of the frame's pegs.",
+                    peg.x, peg.y
+                )
+            })?;
+        }
+        Ok(())
+    }
+
     /// Write a [`Blueprint`] to a json file.
     pub fn to_file<P: AsRef<Path>>(&self, file_path: P) -> Result<(), Box<dyn Error>> {
         let file = File::create(file_path)?;
@@ -101,98 +276,379 @@ impl Blueprint {
     /// use strandify::peg::Peg;
     /// let bp = Blueprint::new(vec![Peg::new(0, 0), Peg::new(3, 3)], 4, 4, Some((255, 255, 255)), 1., false);
     /// for (peg_a, peg_b) in bp.zip() {
-    ///     assert_eq!(peg_a.id, 0);
-    ///     assert_eq!(peg_b.id, 1);
+    ///     assert_eq!((peg_a.x, peg_a.y), (0, 0));
+    ///     assert_eq!((peg_b.x, peg_b.y), (3, 3));
     /// }
     /// assert_eq!(bp.zip().len(), 1);
     ///```
     pub fn zip(
         &self,
-    ) -> std::iter::Zip<std::slice::Iter<Peg>, std::iter::Skip<std::slice::Iter<Peg>>> {
+    ) -> std::iter::Zip<std::slice::Iter<'_, Peg>, std::iter::Skip<std::slice::Iter<'_, Peg>>> {
         self.peg_order.iter().zip(self.peg_order.iter().skip(1))
     }
 
-    /// Render the [`Blueprint`] as a raster image.
+    /// Iterate over the segments of thread of all the [`Strands`](Strand), or of the
+    /// [`Blueprint::peg_order`] for single [`Yarn`] blueprints. The segments join the tangent
+    /// points of the [`Pegs`](Peg) with a radius, wrapped in the [`Blueprint::wraps`] directions,
+    /// and the centers of the [`Pegs`](Peg) without.
+    pub fn segments(&self) -> Box<dyn Iterator<Item = Segment> + '_> {
+        if self.strands.is_empty() {
+            Box::new(segments(&self.peg_order, &self.wraps))
+        } else {
+            Box::new(self.strands.iter().flat_map(|strand| strand.segments()))
+        }
+    }
+
+    /// Size of a pixel in mm, [`None`] if the [`Blueprint::frame_size`] is not set.
+    pub fn mm_per_pixel(&self) -> Option<f64> {
+        self.frame_size.map(|size| size / self.width as f64)
+    }
+
+    /// Length of each segment of thread, in pixels, in peg order, [`Strand`] after [`Strand`].
+    pub fn segment_lengths(&self) -> Vec<f64> {
+        self.segments()
+            .map(|(start, end)| (start.0 - end.0).hypot(start.1 - end.1))
+            .collect()
+    }
+
+    /// Total length of thread, in pixels.
+    pub fn thread_length(&self) -> f64 {
+        self.segment_lengths().iter().sum()
+    }
+
+    /// Length of each segment of thread, in mm, see [`Blueprint::segment_lengths`]. [`None`] if
+    /// the [`Blueprint::frame_size`] is not set.
+    pub fn segment_lengths_mm(&self) -> Option<Vec<f64>> {
+        let scale = self.mm_per_pixel()?;
+        Some(
+            self.segment_lengths()
+                .into_iter()
+                .map(|length| length * scale)
+                .collect(),
+        )
+    }
+
+    /// Total length of thread, in mm. [`None`] if the [`Blueprint::frame_size`] is not set.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::blueprint::Blueprint;
+    /// use strandify::peg::Peg;
+    /// let mut bp = Blueprint::new(vec![Peg::new(0, 0), Peg::new(100, 0)], 200, 200, None, 1., false);
+    /// assert_eq!(bp.thread_length_mm(), None);
+    /// bp.frame_size = Some(500.);
+    /// assert_eq!(bp.thread_length_mm(), Some(250.));
+    /// ```
+    pub fn thread_length_mm(&self) -> Option<f64> {
+        Some(self.thread_length() * self.mm_per_pixel()?)
+    }
+
+    /// Position of a [`Peg`] in mm, from the top left corner of the frame. [`None`] if the
+    /// [`Blueprint::frame_size`] is not set.
+    pub fn peg_position_mm(&self, peg: &Peg) -> Option<(f64, f64)> {
+        let scale = self.mm_per_pixel()?;
+        Some((peg.x as f64 * scale, peg.y as f64 * scale))
+    }
+
+    /// Create the build [`Instructions`] of the [`Blueprint`], with the pegs numbered
+    /// sequentially around the frame.
+    ///
+    /// # Arguments
+    ///
+    /// * `steps_per_session`: Number of steps per session, sessions split the build into
+    ///   manageable chunks.
+    pub fn instructions(&self, steps_per_session: usize) -> Instructions {
+        Instructions::new(self, steps_per_session)
+    }
+
+    /// Convert the [`Blueprint`] to G-code for string art machines, moving to each peg in peg
+    /// order above the pegs and wrapping the thread around it. Multi [`Yarn`] blueprints pause
+    /// with a `M0` between strands. See [`gcode::simulate`] to read the peg order back.
+    ///
+    /// # Arguments
+    ///
+    /// * `config`: The [`GcodeConfig`], feed rates, heights and the transform from pixels to
+    ///   machine coordinates.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if neither the [`GcodeConfig::scale`] nor the
+    /// [`Blueprint::frame_size`] are set.
+    pub fn gcode(&self, config: &GcodeConfig) -> Result<String, Box<dyn Error>> {
+        gcode::generate(self, config)
+    }
+
+    /// Create the peg board [`Template`] of the [`Blueprint`], to drill or laser cut the frame.
+    /// [`None`] if the [`Blueprint::frame_size`] is not set.
+    pub fn template(&self) -> Option<Template> {
+        Template::from_blueprint(self)
+    }
+
+    /// Create the [`Animation`] of the build process, with the lines appearing in peg order.
+    ///
+    /// # Arguments
+    ///
+    /// * `yarn`: The [`Yarn`] to use to render the frames.
+    /// * `config`: The [`AnimationConfig`], number of lines per frame and frame rate.
+    pub fn animation(&self, yarn: &Yarn, config: AnimationConfig) -> Animation<'_> {
+        Animation::new(self, yarn, config)
+    }
+
+    /// Compute the quality [`Metrics`] of the [`Blueprint`] compared to its `source` image, see
+    /// [`Metrics::from_blueprint`].
     ///
     /// # Arguments
     ///
+    /// * `source`: The source image, in grayscale, rendered at its resolution.
     /// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`].
-    pub fn render_img(&self, yarn: &Yarn) -> Result<image::RgbaImage, Box<dyn Error>> {
-        let document = self.render_svg(yarn)?;
-        let svg_data = document.to_string();
-        let svg_tree = usvg::Tree::from_str(&svg_data, &usvg::Options::default())?;
+    pub fn score(&self, source: &image::GrayImage, yarn: &Yarn) -> Result<Metrics, Box<dyn Error>> {
+        Metrics::from_blueprint(source, self, yarn)
+    }
 
-        let render_width = (self.width as f64 * self.render_scale).round() as u32;
-        let render_height = (self.height as f64 * self.render_scale).round() as u32;
+    /// Compute the [`Stats`] of the [`Blueprint`], number of lines, peg usage, repeated segments,
+    /// thread lengths, ...
+    pub fn stats(&self) -> Stats {
+        Stats::new(self)
+    }
 
-        // divide the height into chunks to be processed in parallel
-        #[cfg(feature = "parallel")]
-        let num_chunks = rayon::current_num_threads();
-        #[cfg(not(feature = "parallel"))]
-        let num_chunks = 1;
+    /// Dimensions of the renders, the [`Blueprint`]'s dimensions scaled by the
+    /// [`Blueprint::render_scale`].
+    pub(crate) fn render_dimensions(&self) -> (u32, u32) {
+        (
+            (self.width as f64 * self.render_scale).round() as u32,
+            (self.height as f64 * self.render_scale).round() as u32,
+        )
+    }
 
-        let chunk_height = (render_height + num_chunks as u32 - 1) / num_chunks as u32;
+    /// The segments of thread with the [`Yarn`] they are rendered with, in render order. Single
+    /// [`Yarn`] blueprints use `yarn`, multi [`Yarn`] blueprints the [`Strand::yarn`].
+    pub(crate) fn yarn_segments(&self, yarn: &Yarn) -> Vec<(Yarn, Segment)> {
+        if self.strands.is_empty() {
+            segments(&self.peg_order, &self.wraps)
+                .map(|segment| (yarn.clone(), segment))
+                .collect()
+        } else {
+            self.strands
+                .iter()
+                .flat_map(|strand| {
+                    let yarn = strand.yarn(yarn);
+                    strand
+                        .segments()
+                        .map(move |segment| (yarn.clone(), segment))
+                })
+                .collect()
+        }
+    }
+
+    /// Render the [`Blueprint`] as a raster image.
+    ///
+    /// The lines are drawn directly onto a [`tiny_skia::Pixmap`] from the peg order, the output
+    /// is identical to [`Blueprint::render_img_svg`] without building and parsing a svg document.
+    ///
+    /// # Arguments
+    ///
+    /// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`].
+    pub fn render_img(&self, yarn: &Yarn) -> Result<image::RgbaImage, Box<dyn Error>> {
+        let (render_width, render_height) = self.render_dimensions();
+        info!("Render resolution: {render_width}x{render_height}");
+        let mut pixmap = tiny_skia::Pixmap::new(render_width, render_height)
+            .ok_or("Could not create a pixmap of the render dimensions.")?;
 
         let pbar = utils::spinner(!self.progress_bar).with_message("Rendering image");
         pbar.enable_steady_tick(Duration::from_millis(100));
+        self.draw_lines(&self.yarn_segments(yarn), true, &mut pixmap);
+        pbar.finish_and_clear();
 
-        // render each chunk in parallel
-        let chunks: Vec<tiny_skia::Pixmap> = utils::iter_or_par_iter!(0..num_chunks, into)
-            .map(|i| {
-                let start_y = i as u32 * chunk_height;
-                let end_y = ((i + 1) as u32 * chunk_height).min(render_height);
-
-                let mut pixmap = tiny_skia::Pixmap::new(render_width, end_y - start_y).unwrap();
+        Ok(pixmap_to_img(&pixmap))
+    }
 
-                let transform = tiny_skia::Transform::from_translate(0.0, -(start_y as f32));
-                render(&svg_tree, transform, &mut pixmap.as_mut());
-                pixmap
-            })
-            .collect();
+    /// Render the [`Blueprint`] as a raster image by rendering its svg, see
+    /// [`Blueprint::render_svg`], with [`resvg`]. Slower than [`Blueprint::render_img`] for large
+    /// numbers of lines.
+    ///
+    /// # Arguments
+    ///
+    /// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`].
+    pub fn render_img_svg(&self, yarn: &Yarn) -> Result<image::RgbaImage, Box<dyn Error>> {
+        let document = self.svg_document(yarn, false)?;
+        let (render_width, render_height) = self.render_dimensions();
+        let mut pixmap = tiny_skia::Pixmap::new(render_width, render_height)
+            .ok_or("Could not create a pixmap of the render dimensions.")?;
 
+        let pbar = utils::spinner(!self.progress_bar).with_message("Rendering image");
+        pbar.enable_steady_tick(Duration::from_millis(100));
+        render_document(&document, &mut pixmap)?;
         pbar.finish_and_clear();
 
-        // create the final image buffer
-        let mut final_pixmap = tiny_skia::Pixmap::new(render_width, render_height).unwrap();
+        Ok(pixmap_to_img(&pixmap))
+    }
 
-        // combine the chunks back into the final image
-        for (i, pixmap) in chunks.into_iter().enumerate() {
-            let start_y = i as u32 * chunk_height;
-            final_pixmap.draw_pixmap(
-                0,
-                start_y as i32,
-                pixmap.as_ref(),
-                &tiny_skia::PixmapPaint::default(),
-                tiny_skia::Transform::identity(),
-                None,
-            );
+    /// Draw the `lines`, with their [`Yarn`], on top of the content of the `pixmap`, with the
+    /// [`Blueprint::background`] if `background` is true.
+    ///
+    /// The lines are drawn the way [`resvg`] renders the paths of the svg document, see
+    /// [`Blueprint::svg_lines`]: each line is stroked into its own layer, which is then
+    /// composited with the [`Yarn::opacity`], so both renders are identical.
+    pub(crate) fn draw_lines(
+        &self,
+        lines: &[(Yarn, Segment)],
+        background: bool,
+        pixmap: &mut tiny_skia::Pixmap,
+    ) {
+        if let Some((bg_r, bg_g, bg_b)) = self.background.filter(|_| background) {
+            pixmap.fill(tiny_skia::Color::from_rgba8(bg_r, bg_g, bg_b, 255));
         }
 
-        // convert the final pixmap to an image::RgbaImage
-        let img =
-            image::ImageBuffer::from_vec(render_width, render_height, final_pixmap.data().to_vec())
-                .unwrap();
+        // the paths, their paint, stroke, layer opacity and the bounding box of their stroke
+        let scale = self.render_scale;
+        let paths: Vec<_> = lines
+            .iter()
+            .filter_map(|(yarn, (start, end))| {
+                let stroke = tiny_skia::Stroke {
+                    width: yarn.width,
+                    line_cap: tiny_skia::LineCap::Round,
+                    ..Default::default()
+                };
+                let opacity = (yarn.opacity as f32).clamp(0., 1.);
+                // like usvg, lines with an opacity of 1 don't need a layer
+                let layered = (opacity - 1.).abs() > 4. * f32::EPSILON;
+                let mut builder = tiny_skia::PathBuilder::new();
+                builder.move_to((start.0 * scale) as f32, (start.1 * scale) as f32);
+                builder.line_to((end.0 * scale) as f32, (end.1 * scale) as f32);
+                let path = builder.finish()?;
+                let bounds = path.stroke(&stroke, 1.)?.compute_tight_bounds()?;
+                let bounds = tiny_skia::NonZeroRect::from_ltrb(
+                    bounds.left(),
+                    bounds.top(),
+                    bounds.right(),
+                    bounds.bottom(),
+                )?;
+                let mut paint = tiny_skia::Paint::default();
+                let (r, g, b) = yarn.color;
+                paint.set_color_rgba8(r, g, b, 255);
+                Some((path, paint, stroke, layered.then_some(opacity), bounds))
+            })
+            .collect();
 
-        Ok(img)
+        render_chunks(pixmap, |chunk, transform| {
+            let (width, height) = (chunk.width(), chunk.height());
+            let chunk_rect = tiny_skia::IntRect::from_xywh(0, 0, width, height).unwrap();
+            // layers are limited to 5 times the size of the chunk, like resvg
+            let max_rect = tiny_skia::IntRect::from_xywh(
+                -(width as i32) * 2,
+                -(height as i32) * 2,
+                width * 5,
+                height * 5,
+            )
+            .unwrap();
+
+            for (path, paint, stroke, opacity, bounds) in &paths {
+                let Some(opacity) = *opacity else {
+                    chunk.stroke_path(path, paint, stroke, transform, None);
+                    continue;
+                };
+                let Some(bbox) = bounds.transform(transform) else {
+                    continue;
+                };
+                // expand the layer by 2px to not clip the anti-aliased pixels
+                let Some(layer_rect) = tiny_skia::IntRect::from_xywh(
+                    bbox.x().floor() as i32 - 2,
+                    bbox.y().floor() as i32 - 2,
+                    bbox.width().ceil() as u32 + 4,
+                    bbox.height().ceil() as u32 + 4,
+                )
+                .and_then(|rect| {
+                    tiny_skia::IntRect::from_ltrb(
+                        rect.left().max(max_rect.left()),
+                        rect.top().max(max_rect.top()),
+                        rect.right().min(max_rect.right()),
+                        rect.bottom().min(max_rect.bottom()),
+                    )
+                }) else {
+                    continue;
+                };
+                if layer_rect.intersect(&chunk_rect).is_none() {
+                    continue;
+                }
+
+                // the layer is drawn at its integer origin, which the subpixel shift of the group
+                // layers of resvg 0.43, in `render_group`, amounts to
+                let layer_transform = tiny_skia::Transform::from_translate(
+                    -layer_rect.x() as f32,
+                    -layer_rect.y() as f32,
+                )
+                .pre_concat(transform);
+                let Some(mut layer) =
+                    tiny_skia::Pixmap::new(layer_rect.width(), layer_rect.height())
+                else {
+                    continue;
+                };
+                layer.stroke_path(path, paint, stroke, layer_transform, None);
+                let layer_paint = tiny_skia::PixmapPaint {
+                    opacity,
+                    blend_mode: tiny_skia::BlendMode::SourceOver,
+                    quality: tiny_skia::FilterQuality::Nearest,
+                };
+                chunk.draw_pixmap(
+                    layer_rect.x(),
+                    layer_rect.y(),
+                    layer.as_ref(),
+                    &layer_paint,
+                    tiny_skia::Transform::identity(),
+                    None,
+                );
+            }
+        });
     }
 
-    /// Render the [`Blueprint`] as a svg.
+    /// Render the [`Blueprint`] as a svg. When the [`Blueprint::frame_size`] is set, the svg's
+    /// dimensions are in mm, e.g. `width="500mm"`.
     ///
     /// # Arguments
     ///
-    /// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`].
+    /// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`]. Multi [`Yarn`] blueprints are
+    ///   rendered with the [`Strand::yarn`] of each strand.
     pub fn render_svg(&self, yarn: &Yarn) -> Result<Document, Box<dyn Error>> {
-        let (r, g, b) = yarn.color;
-        let render_width = (self.width as f64 * self.render_scale).round() as u32;
-        let render_height = (self.height as f64 * self.render_scale).round() as u32;
+        self.svg_document(yarn, true)
+    }
+
+    /// Render the [`Blueprint`] as a svg, with dimensions in mm if `real_units` is true and the
+    /// [`Blueprint::frame_size`] is set, in pixels otherwise.
+    fn svg_document(&self, yarn: &Yarn, real_units: bool) -> Result<Document, Box<dyn Error>> {
+        let (render_width, render_height) = self.render_dimensions();
         info!("Render resolution: {render_width}x{render_height}");
 
-        let mut document = Document::new()
-            .set("viewbox", (0, 0, render_width, render_height))
-            .set("width", render_width)
-            .set("height", render_height);
+        let lines = self.yarn_segments(yarn);
+        let pbar =
+            utils::pbar(lines.len() as u64, !self.progress_bar)?.with_message("Rendering svg");
+        Ok(self.svg_lines(pbar.wrap_iter(lines.into_iter()), true, real_units))
+    }
 
-        if let Some((bg_r, bg_g, bg_b)) = self.background {
+    /// Create a svg document of the `lines`, with their [`Yarn`], with the
+    /// [`Blueprint::background`] if `background` is true, and dimensions in mm if `real_units` is
+    /// true and the [`Blueprint::frame_size`] is set, in pixels otherwise.
+    fn svg_lines(
+        &self,
+        lines: impl Iterator<Item = (Yarn, Segment)>,
+        background: bool,
+        real_units: bool,
+    ) -> Document {
+        let (render_width, render_height) = self.render_dimensions();
+        let mut document = Document::new().set("viewBox", (0, 0, render_width, render_height));
+        document = match self.frame_size.filter(|_| real_units) {
+            Some(frame_size) => {
+                let frame_height = frame_size * self.height as f64 / self.width as f64;
+                document
+                    .set("width", format!("{frame_size}mm"))
+                    .set("height", format!("{frame_height}mm"))
+            }
+            None => document
+                .set("width", render_width)
+                .set("height", render_height),
+        };
+
+        if let Some((bg_r, bg_g, bg_b)) = self.background.filter(|_| background) {
             let background = Rectangle::new()
                 .set("x", 0)
                 .set("y", 0)
@@ -202,19 +658,11 @@ impl Blueprint {
             document.append(background);
         }
 
-        let pbar = utils::pbar(self.peg_order.len() as u64 - 1, !self.progress_bar)?
-            .with_message("Rendering svg");
-
-        for (peg_a, peg_b) in pbar.wrap_iter(self.zip()) {
+        for (yarn, (start, end)) in lines {
+            let (r, g, b) = yarn.color;
             let data = Data::new()
-                .move_to((
-                    (peg_a.x as f64 * self.render_scale) as u32,
-                    (peg_a.y as f64 * self.render_scale) as u32,
-                ))
-                .line_to((
-                    (peg_b.x as f64 * self.render_scale) as u32,
-                    (peg_b.y as f64 * self.render_scale) as u32,
-                ));
+                .move_to((start.0 * self.render_scale, start.1 * self.render_scale))
+                .line_to((end.0 * self.render_scale, end.1 * self.render_scale));
             let path = PathSVG::new()
                 .set("fill", "none")
                 .set("stroke", format!("rgb({r}, {g}, {b})"))
@@ -224,7 +672,7 @@ impl Blueprint {
                 .set("d", data);
             document.append(path);
         }
-        Ok(document)
+        document
     }
 
     /// Render the [`Blueprint`].
@@ -254,9 +702,63 @@ impl Blueprint {
     }
 }
 
+/// Render a svg `document` on top of the content of the `pixmap`, see [`render_chunks`].
+pub(crate) fn render_document(
+    document: &Document,
+    pixmap: &mut tiny_skia::Pixmap,
+) -> Result<(), Box<dyn Error>> {
+    let svg_data = document.to_string();
+    let svg_tree = usvg::Tree::from_str(&svg_data, &usvg::Options::default())?;
+    render_chunks(pixmap, |chunk, transform| {
+        render(&svg_tree, transform, chunk)
+    });
+    Ok(())
+}
+
+/// Split the `pixmap` into horizontal chunks and call `render_chunk` on each chunk in parallel,
+/// with the transform from the `pixmap`'s coordinates to the chunk's.
+fn render_chunks<F>(pixmap: &mut tiny_skia::Pixmap, render_chunk: F)
+where
+    F: Fn(&mut tiny_skia::PixmapMut, tiny_skia::Transform) + Sync,
+{
+    let (width, height) = (pixmap.width(), pixmap.height());
+
+    // divide the height into chunks to be processed in parallel
+    #[cfg(feature = "parallel")]
+    let num_chunks = rayon::current_num_threads();
+    #[cfg(not(feature = "parallel"))]
+    let num_chunks = 1;
+
+    let chunk_height = height.div_ceil(num_chunks as u32).max(1);
+    let row_len = width as usize * 4;
+    let chunks: Vec<&mut [u8]> = pixmap
+        .data_mut()
+        .chunks_mut(chunk_height as usize * row_len)
+        .collect();
+
+    // render each chunk in parallel, directly into the pixmap's rows
+    utils::iter_or_par_iter!(chunks, into)
+        .enumerate()
+        .for_each(|(i, chunk)| {
+            let chunk_rows = (chunk.len() / row_len) as u32;
+            let mut chunk_pixmap = tiny_skia::PixmapMut::from_bytes(chunk, width, chunk_rows)
+                .expect("chunks should hold whole rows of pixels");
+            let start_y = i as u32 * chunk_height;
+            let transform = tiny_skia::Transform::from_translate(0.0, -(start_y as f32));
+            render_chunk(&mut chunk_pixmap, transform);
+        });
+}
+
+/// Convert a [`tiny_skia::Pixmap`] to an [`image::RgbaImage`].
+pub(crate) fn pixmap_to_img(pixmap: &tiny_skia::Pixmap) -> image::RgbaImage {
+    image::ImageBuffer::from_vec(pixmap.width(), pixmap.height(), pixmap.data().to_vec())
+        .expect("pixmap data should match its dimensions")
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::peg::shape;
     use std::fs;
     use std::path::PathBuf;
 
@@ -303,6 +805,29 @@ mod test {
         }
     }
 
+    #[test]
+    fn blueprint_link_pegs() {
+        let pegs = PegLayout::new(vec![Peg::new(0, 0), Peg::new(63, 0), Peg::new(63, 63)]);
+        // stale ids, the pegs are matched by coords
+        let mut bp = Blueprint::new(
+            vec![Peg { id: 5, ..pegs[2] }, Peg { id: 2, ..pegs[0] }],
+            64,
+            64,
+            None,
+            1.,
+            false,
+        );
+        bp.pegs = pegs.clone();
+        let bp_file = PathBuf::from(TEST_DIR).join("bp_link.json");
+        bp.to_file(&bp_file).unwrap();
+        let bp_read = Blueprint::from_file(&bp_file).unwrap();
+        let ids: Vec<usize> = bp_read.peg_order.iter().map(|peg| peg.id).collect();
+        assert_eq!(ids, vec![2, 0]);
+
+        bp.peg_order.push(Peg::new(10, 10));
+        assert!(bp.link_pegs().is_err());
+    }
+
     #[test]
     fn zip() {
         let bp = Blueprint::new(
@@ -315,4 +840,144 @@ mod test {
         );
         assert_eq!(bp.zip().len(), 1);
     }
+
+    #[test]
+    fn render_svg_strands() {
+        let bp = Blueprint::from_strands(
+            vec![
+                Strand::new((255, 0, 0), vec![Peg::new(0, 0), Peg::new(63, 63)]),
+                Strand::new(
+                    (0, 0, 255),
+                    vec![Peg::new(0, 63), Peg::new(63, 0), Peg::new(32, 0)],
+                ),
+            ],
+            64,
+            64,
+            Some((255, 255, 255)),
+            1.,
+            false,
+        );
+        let svg = bp.render_svg(&Yarn::default()).unwrap().to_string();
+        assert_eq!(svg.matches("rgb(255, 0, 0)").count(), 1);
+        assert_eq!(svg.matches("rgb(0, 0, 255)").count(), 2);
+
+        // the strands of a yarn keep its opacity and width
+        let mut bp = bp;
+        bp.strands[0] = Strand::from_yarn(
+            &Yarn::new(3., 0.7, (255, 0, 0)),
+            bp.strands[0].peg_order.clone(),
+        );
+        let svg = bp.render_svg(&Yarn::default()).unwrap().to_string();
+        assert_eq!(svg.matches("opacity=\"0.7\"").count(), 1);
+        assert_eq!(svg.matches("stroke-width=\"3\"").count(), 1);
+        assert_eq!(svg.matches("opacity=\"0.2\"").count(), 2);
+        let json = serde_json::to_string(&bp).unwrap();
+        let strands = serde_json::from_str::<Blueprint>(&json).unwrap().strands;
+        assert_eq!(
+            (strands[0].opacity, strands[0].width),
+            (Some(0.7), Some(3.))
+        );
+        assert_eq!((strands[1].opacity, strands[1].width), (None, None));
+    }
+
+    #[test]
+    fn render_img_matches_svg() {
+        let pegs = shape::circle((50, 40), 35, 24);
+        let peg_order = (0..60).map(|i| pegs[(i * 7) % pegs.len()]).collect();
+        let mut bp = Blueprint::new(peg_order, 100, 80, Some((240, 230, 220)), 1.5, false);
+        for (width, opacity) in [(1., 0.2), (2.5, 0.5), (0.5, 1.)] {
+            let yarn = Yarn::new(width, opacity, (20, 40, 60));
+            assert_eq!(
+                bp.render_img(&yarn).unwrap(),
+                bp.render_img_svg(&yarn).unwrap()
+            );
+        }
+
+        // multi yarn, transparent and wrapped around pegs with a radius
+        bp.background = None;
+        bp.strands = vec![
+            Strand::from_yarn(
+                &Yarn::new(2., 0.5, (255, 0, 0)),
+                bp.peg_order[..30].to_vec(),
+            ),
+            Strand::new((0, 0, 255), bp.peg_order[30..].to_vec()),
+        ];
+        for strand in &mut bp.strands {
+            strand.peg_order = strand
+                .peg_order
+                .iter()
+                .map(|peg| peg.with_radius(2.))
+                .collect();
+            strand.wraps = (0..strand.peg_order.len())
+                .map(|i| {
+                    if i % 3 == 0 {
+                        WrapDirection::Ccw
+                    } else {
+                        WrapDirection::Cw
+                    }
+                })
+                .collect();
+        }
+        let yarn = Yarn::default();
+        assert_eq!(
+            bp.render_img(&yarn).unwrap(),
+            bp.render_img_svg(&yarn).unwrap()
+        );
+    }
+
+    #[test]
+    fn frame_size() {
+        let mut bp = Blueprint::from_strands(
+            vec![
+                Strand::new((255, 0, 0), vec![Peg::new(0, 0), Peg::new(30, 40)]),
+                Strand::new((0, 0, 255), vec![Peg::new(0, 50), Peg::new(100, 50)]),
+            ],
+            100,
+            50,
+            None,
+            1.,
+            false,
+        );
+        assert_eq!(bp.segment_lengths(), vec![50., 100.]);
+        assert_eq!(bp.thread_length(), 150.);
+        assert!(bp.segment_lengths_mm().is_none());
+        assert!(bp.peg_position_mm(&bp.strands[0].peg_order[1]).is_none());
+
+        bp.frame_size = Some(500.);
+        assert_eq!(bp.mm_per_pixel(), Some(5.));
+        assert_eq!(bp.segment_lengths_mm(), Some(vec![250., 500.]));
+        assert_eq!(bp.thread_length_mm(), Some(750.));
+        assert_eq!(
+            bp.peg_position_mm(&bp.strands[0].peg_order[1]),
+            Some((150., 200.))
+        );
+
+        let svg = bp.render_svg(&Yarn::default()).unwrap().to_string();
+        assert!(svg.contains("width=\"500mm\""));
+        assert!(svg.contains("height=\"250mm\""));
+        assert!(svg.contains("viewBox=\"0 0 100 50\""));
+    }
+
+    #[test]
+    fn segments_wraps() {
+        let peg_a = Peg::new(0, 10).with_radius(2.);
+        let peg_b = Peg::new(20, 10).with_radius(2.);
+        let mut bp = Blueprint::new(vec![peg_a, peg_b, peg_a], 30, 20, None, 1., false);
+        // clockwise by default, along the top of the pegs then along their bottom
+        assert_eq!(
+            bp.segments().collect::<Vec<_>>(),
+            vec![((0., 8.), (20., 8.)), ((20., 12.), (0., 12.))]
+        );
+
+        bp.wraps = vec![WrapDirection::Cw, WrapDirection::Ccw, WrapDirection::Ccw];
+        let segments: Vec<_> = bp.segments().collect();
+        // crossing from the top of peg_a to the bottom of peg_b
+        assert!(segments[0].0 .1 < 10. && segments[0].1 .1 > 10.);
+        // then along the top of the pegs, going left counter-clockwise
+        assert_eq!(segments[1], ((20., 8.), (0., 8.)));
+        assert_eq!(bp.segment_lengths()[1], 20.);
+
+        let svg = bp.render_svg(&Yarn::default()).unwrap().to_string();
+        assert!(svg.contains("M20,8 L0,8"));
+    }
 }
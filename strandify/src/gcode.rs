@@ -0,0 +1,410 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Write as _;
+
+use crate::blueprint::Blueprint;
+use crate::instructions::{numbered_pegs, peg_numbers, strands};
+use crate::peg::{Peg, WrapDirection};
+
+/// Tolerance used to compare machine coordinates, in machine units.
+const TOLERANCE: f64 = 1e-2;
+
+#[derive(Debug, Clone)]
+/// Configuration of the G-code export of a [`Blueprint`], see [`Blueprint::gcode`].
+pub struct GcodeConfig {
+    /// Millimeters per pixel, defaults to the [`Blueprint::mm_per_pixel`]. Required if the
+    /// [`Blueprint::frame_size`] is not set, the G-code is in millimeters.
+    pub scale: Option<f64>,
+    /// Machine coordinates of the top left corner of the frame.
+    pub offset: (f64, f64),
+    /// Flip the y axis, image y axes point down while machine y axes usually point up.
+    pub flip_y: bool,
+    /// Feed rate while laying the thread, in machine units per minute.
+    pub feed_rate: f64,
+    /// Feed rate of the travel moves, in machine units per minute.
+    pub travel_feed_rate: f64,
+    /// Height of the tool while travelling and moving between pegs, above the head of the pegs
+    /// so that the thread clears the pegs in between.
+    pub travel_height: f64,
+    /// Height of the tool while wrapping the thread around a peg, below the head of the pegs.
+    pub work_height: f64,
+    /// Radius of the wrap-around move at each peg, in machine units.
+    pub wrap_radius: f64,
+    /// Command template replacing the default move and wrap-around of each peg, with the `{x}`,
+    /// `{y}`, `{peg}` and `{feed}` placeholders, e.g. `"WRAP X{x} Y{y}"`. Custom commands can't
+    /// be read back by [`simulate`].
+    pub peg_command: Option<String>,
+}
+
+impl Default for GcodeConfig {
+    fn default() -> Self {
+        Self {
+            scale: None,
+            offset: (0., 0.),
+            flip_y: true,
+            feed_rate: 3000.,
+            travel_feed_rate: 6000.,
+            travel_height: 10.,
+            work_height: 0.,
+            wrap_radius: 2.,
+            peg_command: None,
+        }
+    }
+}
+
+impl GcodeConfig {
+    /// Millimeters per pixel of the [`Blueprint`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if neither the scale nor the
+    /// [`Blueprint::frame_size`] are set.
+    fn scale(&self, blueprint: &Blueprint) -> Result<f64, Box<dyn Error>> {
+        self.scale
+            .or(blueprint.mm_per_pixel())
+            .ok_or_else(|| "G-code requires a scale or the blueprint's frame size.".into())
+    }
+
+    /// Convert a position in pixels to machine coordinates.
+    fn to_machine(&self, scale: f64, blueprint: &Blueprint, (x, y): (f64, f64)) -> (f64, f64) {
+        let y = if self.flip_y {
+            blueprint.height as f64 - y
+        } else {
+            y
+        };
+        (self.offset.0 + x * scale, self.offset.1 + y * scale)
+    }
+
+    /// Convert machine coordinates to a position in pixels.
+    fn to_pixels(&self, scale: f64, blueprint: &Blueprint, (x, y): (f64, f64)) -> (f64, f64) {
+        let (x, y) = ((x - self.offset.0) / scale, (y - self.offset.1) / scale);
+        if self.flip_y {
+            (x, blueprint.height as f64 - y)
+        } else {
+            (x, y)
+        }
+    }
+}
+
+/// Generate the G-code of a [`Blueprint`], see [`Blueprint::gcode`].
+pub(crate) fn generate(
+    blueprint: &Blueprint,
+    config: &GcodeConfig,
+) -> Result<String, Box<dyn Error>> {
+    let scale = config.scale(blueprint)?;
+    let pegs = numbered_pegs(blueprint);
+    let numbers = peg_numbers(&pegs);
+    let position = |peg: &Peg| config.to_machine(scale, blueprint, (peg.x as f64, peg.y as f64));
+
+    let strands = strands(blueprint);
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "; strandify string art, {} pegs, {} strands",
+        pegs.len(),
+        strands.len()
+    );
+    out.push_str("G21 ; millimeters\nG90 ; absolute positioning\n");
+    let _ = writeln!(out, "G0 Z{:.3}", config.travel_height);
+
+    for (strand_i, strand) in strands.iter().enumerate() {
+        let peg_order = &strand.peg_order;
+        let (r, g, b) = strand.color;
+        if peg_order.is_empty() {
+            continue;
+        }
+        if strand_i > 0 {
+            out.push_str("M0 ; change thread\n");
+        }
+        let _ = writeln!(
+            out,
+            "; strand {}, color #{r:02x}{g:02x}{b:02x}",
+            strand_i + 1
+        );
+
+        for (step, peg) in peg_order.iter().enumerate() {
+            let (x, y) = position(peg);
+            // every peg of the strands is numbered, see numbered_pegs
+            let number = numbers[&(peg.x, peg.y)];
+            let _ = writeln!(out, "; step {step}, peg {number}");
+
+            if let Some(template) = &config.peg_command {
+                let command = template
+                    .replace("{x}", &format!("{x:.3}"))
+                    .replace("{y}", &format!("{y:.3}"))
+                    .replace("{peg}", &number.to_string())
+                    .replace("{feed}", &format!("{:.0}", config.feed_rate));
+                let _ = writeln!(out, "{command}");
+                continue;
+            }
+
+            // approach the peg from the side the thread comes from, towards the next peg for the
+            // first peg, then wrap around it
+            let other = if step == 0 {
+                peg_order.get(1)
+            } else {
+                peg_order.get(step - 1)
+            };
+            let (dx, dy) = match other.map(position) {
+                Some((other_x, other_y)) => {
+                    let norm = (other_x - x).hypot(other_y - y);
+                    if norm > 0. {
+                        ((other_x - x) / norm, (other_y - y) / norm)
+                    } else {
+                        (0., 1.)
+                    }
+                }
+                None => (0., 1.),
+            };
+            let entry = (x + dx * config.wrap_radius, y + dy * config.wrap_radius);
+
+            if step == 0 {
+                let _ = writeln!(
+                    out,
+                    "G0 X{:.3} Y{:.3} F{:.0}",
+                    entry.0, entry.1, config.travel_feed_rate
+                );
+                let _ = writeln!(
+                    out,
+                    "G1 Z{:.3} F{:.0}",
+                    config.work_height, config.feed_rate
+                );
+            } else {
+                // lift the thread over the pegs in between, then lower it next to the peg
+                let _ = writeln!(
+                    out,
+                    "G1 Z{:.3} F{:.0}",
+                    config.travel_height, config.feed_rate
+                );
+                let _ = writeln!(
+                    out,
+                    "G1 X{:.3} Y{:.3} F{:.0}",
+                    entry.0, entry.1, config.feed_rate
+                );
+                let _ = writeln!(
+                    out,
+                    "G1 Z{:.3} F{:.0}",
+                    config.work_height, config.feed_rate
+                );
+            }
+            // full circle around the peg, in its wrap direction as seen on the image, which is
+            // mirrored on the machine when the y axis isn't flipped
+            let wrap = strand.wraps.get(step).copied().unwrap_or_default();
+            let arc = match (wrap, config.flip_y) {
+                (WrapDirection::Cw, true) | (WrapDirection::Ccw, false) => "G2",
+                (WrapDirection::Ccw, true) | (WrapDirection::Cw, false) => "G3",
+            };
+            let _ = writeln!(
+                out,
+                "{arc} X{:.3} Y{:.3} I{:.3} J{:.3}",
+                entry.0,
+                entry.1,
+                x - entry.0,
+                y - entry.1
+            );
+        }
+        let _ = writeln!(out, "G0 Z{:.3}", config.travel_height);
+    }
+    out.push_str("M2 ; end of program\n");
+    Ok(out)
+}
+
+/// Simulate the G-code generated by [`Blueprint::gcode`], and read back the peg order of each
+/// strand from the wrap-around moves.
+///
+/// Supports the `G0`, `G1`, `G2` and `G3` moves in absolute positioning, with arc centers
+/// relative to the start of the arcs. Full circles wrap around the [`Peg`] at their center, and
+/// `M0` program stops start a new strand.
+///
+/// # Arguments
+///
+/// * `gcode`: The G-code.
+/// * `blueprint`: The [`Blueprint`] the G-code was generated from, its pegs are matched to the
+///   wrap-around moves.
+/// * `config`: The [`GcodeConfig`] used to generate the G-code.
+///
+/// # Examples
+///
+/// ```
+/// use strandify::blueprint::Blueprint;
+/// use strandify::gcode::{simulate, GcodeConfig};
+/// use strandify::peg::Peg;
+/// let pegs = vec![Peg::new(0, 0), Peg::new(50, 100), Peg::new(100, 0)];
+/// let bp = Blueprint::new(pegs.clone(), 100, 100, None, 1., false);
+/// let config = GcodeConfig {
+///     scale: Some(0.5),
+///     ..Default::default()
+/// };
+/// let strands = simulate(&bp.gcode(&config).unwrap(), &bp, &config).unwrap();
+/// assert_eq!(strands.len(), 1);
+/// assert_eq!(strands[0].iter().map(|peg| (peg.x, peg.y)).collect::<Vec<_>>(),
+///            pegs.iter().map(|peg| (peg.x, peg.y)).collect::<Vec<_>>());
+/// ```
+pub fn simulate(
+    gcode: &str,
+    blueprint: &Blueprint,
+    config: &GcodeConfig,
+) -> Result<Vec<Vec<Peg>>, Box<dyn Error>> {
+    let scale = config.scale(blueprint)?;
+    let pegs = numbered_pegs(blueprint);
+    let find_peg = |center: (f64, f64)| -> Result<Peg, Box<dyn Error>> {
+        let (x, y) = config.to_pixels(scale, blueprint, center);
+        pegs.iter()
+            .map(|peg| (peg, (peg.x as f64 - x).hypot(peg.y as f64 - y)))
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+            .filter(|(_, dist)| *dist <= 1.)
+            .map(|(peg, _)| *peg)
+            .ok_or_else(|| format!("No peg at ({x:.1}, {y:.1}).").into())
+    };
+
+    let mut strands: Vec<Vec<Peg>> = vec![vec![]];
+    let mut position = (0., 0.);
+    let mut motion = 0;
+    for (line_i, line) in gcode.lines().enumerate() {
+        let line = line.split(';').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let mut words: HashMap<char, f64> = HashMap::new();
+        for word in line.split_whitespace() {
+            let mut chars = word.chars();
+            let letter = chars.next().ok_or("Empty word")?.to_ascii_uppercase();
+            let value: f64 = chars
+                .as_str()
+                .parse()
+                .map_err(|_| format!("Line {}: invalid word '{word}'.", line_i + 1))?;
+            if letter == 'M' && value == 0. {
+                strands.push(vec![]);
+            } else if letter == 'G' && value == 20. {
+                return Err(format!("Line {}: inches are not supported.", line_i + 1).into());
+            } else if letter == 'G' && value == 91. {
+                return Err(format!(
+                    "Line {}: relative positioning is not supported.",
+                    line_i + 1
+                )
+                .into());
+            } else if letter == 'G' && [0., 1., 2., 3.].contains(&value) {
+                motion = value as u8;
+            }
+            words.insert(letter, value);
+        }
+
+        if !words.contains_key(&'X') && !words.contains_key(&'Y') {
+            continue;
+        }
+        let target = (
+            words.get(&'X').copied().unwrap_or(position.0),
+            words.get(&'Y').copied().unwrap_or(position.1),
+        );
+        if motion == 2 || motion == 3 {
+            let center = (
+                position.0 + words.get(&'I').copied().unwrap_or(0.),
+                position.1 + words.get(&'J').copied().unwrap_or(0.),
+            );
+            let full_circle = (target.0 - position.0).abs() < TOLERANCE
+                && (target.1 - position.1).abs() < TOLERANCE;
+            if full_circle {
+                strands.last_mut().unwrap().push(find_peg(center)?);
+            }
+        }
+        position = target;
+    }
+
+    strands.retain(|strand| !strand.is_empty());
+    Ok(strands)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blueprint::Strand;
+    use crate::peg::PegLayout;
+
+    fn ids(pegs: &[Peg]) -> Vec<usize> {
+        pegs.iter().map(|peg| peg.id).collect()
+    }
+
+    #[test]
+    fn gcode_round_trip() {
+        let pegs = PegLayout::new([
+            Peg::new(0, 0),
+            Peg::new(100, 0),
+            Peg::new(100, 100),
+            Peg::new(0, 100),
+        ]);
+        let mut bp = Blueprint::from_strands(
+            vec![
+                Strand::new((255, 0, 0), vec![pegs[0], pegs[2], pegs[1], pegs[3]]),
+                Strand::new((0, 0, 255), vec![pegs[1], pegs[3], pegs[0]]),
+            ],
+            100,
+            100,
+            None,
+            1.,
+            false,
+        );
+        bp.frame_size = Some(400.);
+        bp.strands[1].wraps = vec![WrapDirection::Cw, WrapDirection::Ccw, WrapDirection::Cw];
+        let config = GcodeConfig {
+            offset: (10., 20.),
+            ..Default::default()
+        };
+        let gcode = bp.gcode(&config).unwrap();
+        assert_eq!(gcode.matches("\nG2 ").count(), 6);
+        assert_eq!(gcode.matches("\nG3 ").count(), 1);
+        assert_eq!(gcode.matches("\nM0").count(), 1);
+        // first peg, the top left one, at (10, 420), approached from the bottom right peg
+        assert!(gcode.contains(
+            "G0 X11.414 Y418.586 F6000\nG1 Z0.000 F3000\nG2 X11.414 Y418.586 I-1.414 J1.414\n"
+        ));
+        // lifted over the pegs in between, to the bottom right peg approached from the top left
+        assert!(gcode.contains(
+            "G1 Z10.000 F3000\nG1 X408.586 Y21.414 F3000\nG1 Z0.000 F3000\nG2 X408.586 Y21.414"
+        ));
+
+        let strands = simulate(&gcode, &bp, &config).unwrap();
+        assert_eq!(strands.len(), 2);
+        assert_eq!(ids(&strands[0]), ids(&bp.strands[0].peg_order));
+        assert_eq!(ids(&strands[1]), ids(&bp.strands[1].peg_order));
+
+        // the pegs don't match with an other transform
+        let other = GcodeConfig {
+            flip_y: false,
+            scale: Some(1.),
+            ..Default::default()
+        };
+        assert!(simulate(&gcode, &bp, &other).is_err());
+    }
+
+    #[test]
+    fn gcode_peg_command() {
+        let bp = Blueprint::new(
+            vec![Peg::new(0, 0), Peg::new(10, 5)],
+            10,
+            10,
+            None,
+            1.,
+            false,
+        );
+        // the G-code is in millimeters, the scale is required without a frame size
+        assert!(bp.gcode(&GcodeConfig::default()).is_err());
+        let config = GcodeConfig {
+            scale: Some(1.),
+            flip_y: false,
+            peg_command: Some("WRAP P{peg} X{x} Y{y} F{feed}".to_string()),
+            ..Default::default()
+        };
+        let gcode = bp.gcode(&config).unwrap();
+        assert!(gcode.contains("WRAP P2 X0.000 Y0.000 F3000\n"));
+        assert!(gcode.contains("WRAP P1 X10.000 Y5.000 F3000\n"));
+        assert!(!gcode.contains("G2 "));
+
+        // pegs missing from the frame's pegs are numbered after them
+        let mut bp = bp;
+        bp.pegs = PegLayout::new([Peg::new(10, 5), Peg::new(10, 10)]);
+        let gcode = bp.gcode(&config).unwrap();
+        assert!(gcode.contains("WRAP P3 X0.000 Y0.000 F3000\n"));
+        assert!(gcode.contains("WRAP P1 X10.000 Y5.000 F3000\n"));
+    }
+}
@@ -0,0 +1,812 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::f64::consts::PI;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use svg::node::element::{Circle, Line as LineSVG, Rectangle, Text};
+use svg::node::Text as TextNode;
+use svg::{Document, Node};
+
+use crate::blueprint::{Blueprint, Strand};
+use crate::peg::{Peg, WrapDirection};
+
+/// Width of the printable instruction sheet, in mm, A4 portrait.
+const SHEET_WIDTH: f64 = 210.;
+/// Margin of the printable instruction sheet, in mm.
+const SHEET_MARGIN: f64 = 10.;
+/// Height of a line of text on the printable instruction sheet, in mm.
+const SHEET_LINE_HEIGHT: f64 = 4.5;
+/// Height of the printable instruction sheet, in mm, A4 portrait.
+const SHEET_HEIGHT: f64 = 297.;
+/// Number of step columns of the printable instruction sheet.
+const SHEET_COLUMNS: usize = 5;
+
+/// The pages of the printable instruction sheet, filled from the top.
+struct Sheet {
+    /// The content of each page.
+    pages: Vec<Vec<Box<dyn Node>>>,
+    /// Vertical position on the last page, in mm.
+    y: f64,
+}
+
+impl Default for Sheet {
+    fn default() -> Self {
+        Self {
+            pages: vec![vec![]],
+            y: SHEET_MARGIN,
+        }
+    }
+}
+
+impl Sheet {
+    /// Start a new page if the content of `height` mm doesn't fit on the last one.
+    fn reserve(&mut self, height: f64) {
+        if self.y + height > SHEET_HEIGHT - SHEET_MARGIN && self.y > SHEET_MARGIN {
+            self.pages.push(vec![]);
+            self.y = SHEET_MARGIN;
+        }
+    }
+
+    /// Add a node to the last page.
+    fn push(&mut self, node: impl Node) {
+        self.pages.last_mut().unwrap().push(Box::new(node));
+    }
+
+    /// Add a line of monospace text to the last page.
+    fn text(&mut self, x: f64, y: f64, size: f64, content: String) {
+        self.push(
+            Text::new("")
+                .set("x", x)
+                .set("y", y)
+                .set("font-size", size)
+                .set("font-family", "monospace")
+                .add(TextNode::new(content)),
+        );
+    }
+
+    /// The A4 svg documents of the pages, numbered at the bottom.
+    fn documents(self) -> Vec<Document> {
+        let n_pages = self.pages.len();
+        self.pages
+            .into_iter()
+            .enumerate()
+            .map(|(page_i, nodes)| {
+                let mut document = Document::new()
+                    .set("viewBox", (0, 0, SHEET_WIDTH, SHEET_HEIGHT))
+                    .set("width", format!("{SHEET_WIDTH}mm"))
+                    .set("height", format!("{SHEET_HEIGHT}mm"));
+                document.append(
+                    Rectangle::new()
+                        .set("width", "100%")
+                        .set("height", "100%")
+                        .set("fill", "white"),
+                );
+                for node in nodes {
+                    document.append(node);
+                }
+                if n_pages > 1 {
+                    document.append(
+                        Text::new("")
+                            .set("x", SHEET_WIDTH - SHEET_MARGIN)
+                            .set("y", SHEET_HEIGHT - SHEET_MARGIN / 2.)
+                            .set("font-size", 3.)
+                            .set("font-family", "monospace")
+                            .set("text-anchor", "end")
+                            .add(TextNode::new(format!("{}/{n_pages}", page_i + 1))),
+                    );
+                }
+                document
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A step of the build [`Instructions`], connecting 2 pegs with the thread.
+pub struct Step {
+    /// Step number, starting at 1 for each [`StrandInstructions`].
+    pub number: usize,
+    /// Number of the peg the thread comes from, see [`Instructions::pegs`].
+    pub from: usize,
+    /// Number of the peg to wrap the thread around, see [`Instructions::pegs`].
+    pub to: usize,
+    /// Direction in which to wrap the thread around the `to` peg, [`None`] when the
+    /// [`Blueprint`] doesn't model the wrapping.
+    pub wrap: Option<WrapDirection>,
+    /// Length of thread used by the step, in mm if [`Instructions::millimeters`], in pixels
+    /// otherwise.
+    pub length: f64,
+}
+
+#[derive(Debug, Clone)]
+/// The build [`Instructions`] of a single thread.
+pub struct StrandInstructions {
+    /// Color of the thread.
+    pub color: (u8, u8, u8),
+    /// Number of the peg to tie the thread to, see [`Instructions::pegs`].
+    pub start: usize,
+    /// The [`Steps`](Step) to follow, in order.
+    pub steps: Vec<Step>,
+}
+
+impl StrandInstructions {
+    /// Total length of thread of this [`StrandInstructions`], see [`Step::length`].
+    pub fn length(&self) -> f64 {
+        self.steps.iter().map(|step| step.length).sum()
+    }
+
+    /// Iterate over the sessions, chunks of `steps_per_session` [`Steps`](Step).
+    pub fn sessions(&self, steps_per_session: usize) -> std::slice::Chunks<'_, Step> {
+        self.steps.chunks(steps_per_session.max(1))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Human readable build instructions of a [`Blueprint`], with the pegs numbered sequentially
+/// around the frame.
+pub struct Instructions {
+    /// The pegs, peg number `n` is at index `n - 1`. The pegs are numbered clockwise around the
+    /// frame, starting from the top, followed by the interior pegs row by row.
+    pub pegs: Vec<Peg>,
+    /// The instructions of each thread.
+    pub strands: Vec<StrandInstructions>,
+    /// Number of [`Steps`](Step) per session, sessions split the build into manageable chunks.
+    pub steps_per_session: usize,
+    /// Whether the lengths are in mm, when the [`Blueprint::frame_size`] is set, or in pixels.
+    pub millimeters: bool,
+}
+
+impl Instructions {
+    /// Create the build [`Instructions`] of a [`Blueprint`].
+    ///
+    /// # Arguments
+    ///
+    /// * `blueprint`: The [`Blueprint`], its [`Blueprint::pegs`] are numbered if present, then
+    ///   the pegs of its peg order missing from them, otherwise the pegs of its peg order are.
+    ///   The lengths are in mm if its [`Blueprint::frame_size`] is set.
+    /// * `steps_per_session`: Number of [`Steps`](Step) per session.
+    pub fn new(blueprint: &Blueprint, steps_per_session: usize) -> Self {
+        let strands = strands(blueprint);
+        let pegs = numbered_pegs(blueprint);
+        let numbers = peg_numbers(&pegs);
+        // every peg of the strands is numbered, see numbered_pegs
+        let number = |peg: &Peg| numbers[&(peg.x, peg.y)];
+        let scale = blueprint.mm_per_pixel().unwrap_or(1.);
+
+        let strands = strands
+            .into_iter()
+            .filter(|strand| !strand.peg_order.is_empty())
+            .map(|strand| StrandInstructions {
+                color: strand.color,
+                start: number(&strand.peg_order[0]),
+                steps: strand
+                    .zip()
+                    .zip(strand.segments())
+                    .enumerate()
+                    .map(|(index, ((peg_a, peg_b), (start, end)))| Step {
+                        number: index + 1,
+                        from: number(peg_a),
+                        to: number(peg_b),
+                        wrap: strand.wraps.get(index + 1).copied(),
+                        length: (start.0 - end.0).hypot(start.1 - end.1) * scale,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Self {
+            pegs,
+            strands,
+            steps_per_session: steps_per_session.max(1),
+            millimeters: blueprint.frame_size.is_some(),
+        }
+    }
+
+    /// Total number of [`Steps`](Step).
+    pub fn n_steps(&self) -> usize {
+        self.strands.iter().map(|strand| strand.steps.len()).sum()
+    }
+
+    /// Total length of thread, see [`Step::length`].
+    pub fn length(&self) -> f64 {
+        self.strands.iter().map(|strand| strand.length()).sum()
+    }
+
+    /// Format a length of thread with its unit, in m when [`Instructions::millimeters`].
+    fn format_length(&self, length: f64) -> String {
+        if self.millimeters {
+            format!("{:.2} m", length / 1000.)
+        } else {
+            format!("{length:.0} px")
+        }
+    }
+
+    /// Format the [`Instructions`] as a numbered step list.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "String art build instructions").unwrap();
+        writeln!(
+            out,
+            "Pegs: {}, numbered clockwise around the frame starting from the top, then the \
+             interior pegs row by row",
+            self.pegs.len()
+        )
+        .unwrap();
+        writeln!(out, "Steps: {}", self.n_steps()).unwrap();
+        writeln!(out, "Thread length: {}", self.format_length(self.length())).unwrap();
+
+        for (strand_i, strand) in self.strands.iter().enumerate() {
+            let (r, g, b) = strand.color;
+            writeln!(out).unwrap();
+            writeln!(
+                out,
+                "Thread {}: color #{r:02x}{g:02x}{b:02x}, {} steps, {}",
+                strand_i + 1,
+                strand.steps.len(),
+                self.format_length(strand.length())
+            )
+            .unwrap();
+            writeln!(out, "Tie the thread to peg {}", strand.start).unwrap();
+            for (session_i, session) in strand.sessions(self.steps_per_session).enumerate() {
+                writeln!(out).unwrap();
+                writeln!(
+                    out,
+                    "Session {} (steps {}-{})",
+                    session_i + 1,
+                    session[0].number,
+                    session[session.len() - 1].number
+                )
+                .unwrap();
+                for step in session {
+                    write!(out, "{:>6}: {} -> {}", step.number, step.from, step.to).unwrap();
+                    if let Some(wrap) = step.wrap {
+                        write!(out, " ({wrap})").unwrap();
+                    }
+                    writeln!(out).unwrap();
+                }
+            }
+        }
+        out
+    }
+
+    /// Format the [`Instructions`] as CSV, with one row per [`Step`].
+    pub fn to_csv(&self) -> String {
+        let unit = if self.millimeters { "mm" } else { "px" };
+        let wrapping = self
+            .strands
+            .iter()
+            .any(|strand| strand.steps.iter().any(|step| step.wrap.is_some()));
+        let mut out = format!("thread,color,session,step,from,to,length_{unit}");
+        out.push_str(if wrapping { ",wrap\n" } else { "\n" });
+        for (strand_i, strand) in self.strands.iter().enumerate() {
+            let (r, g, b) = strand.color;
+            for (session_i, session) in strand.sessions(self.steps_per_session).enumerate() {
+                for step in session {
+                    write!(
+                        out,
+                        "{},#{r:02x}{g:02x}{b:02x},{},{},{},{},{:.1}",
+                        strand_i + 1,
+                        session_i + 1,
+                        step.number,
+                        step.from,
+                        step.to,
+                        step.length
+                    )
+                    .unwrap();
+                    match step.wrap {
+                        Some(wrap) if wrapping => writeln!(out, ",{wrap}").unwrap(),
+                        _ if wrapping => writeln!(out, ",").unwrap(),
+                        _ => writeln!(out).unwrap(),
+                    }
+                }
+            }
+        }
+        out
+    }
+
+    /// Render the [`Instructions`] as printable A4 svg pages, with a map of the peg numbers
+    /// followed by the step list, the sessions too long for a page continue on the next one.
+    pub fn render_svg_pages(&self) -> Vec<Document> {
+        let content_width = SHEET_WIDTH - 2. * SHEET_MARGIN;
+        let mut sheet = Sheet::default();
+
+        sheet.y += 6.;
+        sheet.text(
+            SHEET_MARGIN,
+            sheet.y,
+            6.,
+            "String art build instructions".to_string(),
+        );
+        sheet.y += SHEET_LINE_HEIGHT + 1.;
+        sheet.text(
+            SHEET_MARGIN,
+            sheet.y,
+            3.,
+            format!(
+                "{} pegs, {} steps, thread length {}",
+                self.pegs.len(),
+                self.n_steps(),
+                self.format_length(self.length())
+            ),
+        );
+        sheet.y += SHEET_LINE_HEIGHT;
+
+        // peg map, scaled to fit the content width
+        if !self.pegs.is_empty() {
+            let (min_x, max_x, min_y, max_y) = self.pegs.iter().fold(
+                (u32::MAX, 0, u32::MAX, 0),
+                |(min_x, max_x, min_y, max_y), peg| {
+                    (
+                        min_x.min(peg.x),
+                        max_x.max(peg.x),
+                        min_y.min(peg.y),
+                        max_y.max(peg.y),
+                    )
+                },
+            );
+            let map_size = content_width * 0.8;
+            let scale = map_size / (max_x - min_x).max(max_y - min_y).max(1) as f64;
+            sheet.reserve((max_y - min_y) as f64 * scale + 16.);
+            let offset_x = SHEET_MARGIN + (content_width - (max_x - min_x) as f64 * scale) / 2.;
+            let offset_y = sheet.y + 8.;
+            for (index, peg) in self.pegs.iter().enumerate() {
+                let cx = offset_x + (peg.x - min_x) as f64 * scale;
+                let cy = offset_y + (peg.y - min_y) as f64 * scale;
+                sheet.push(
+                    Circle::new()
+                        .set("cx", cx)
+                        .set("cy", cy)
+                        .set("r", 0.6)
+                        .set("fill", "black"),
+                );
+                sheet.text(cx + 0.8, cy - 0.8, 1.8, (index + 1).to_string());
+            }
+            sheet.y = offset_y + (max_y - min_y) as f64 * scale + 8.;
+        }
+
+        let column_width = content_width / SHEET_COLUMNS as f64;
+        // height of a session header and its first row of steps
+        let session_height = 2. * SHEET_LINE_HEIGHT + 1.;
+        for (strand_i, strand) in self.strands.iter().enumerate() {
+            let (r, g, b) = strand.color;
+            sheet.reserve(SHEET_LINE_HEIGHT + 2. + session_height);
+            sheet.y += SHEET_LINE_HEIGHT;
+            sheet.push(
+                Rectangle::new()
+                    .set("x", SHEET_MARGIN)
+                    .set("y", sheet.y - 3.)
+                    .set("width", 3.)
+                    .set("height", 3.)
+                    .set("fill", format!("rgb({r}, {g}, {b})"))
+                    .set("stroke", "black")
+                    .set("stroke-width", 0.2),
+            );
+            sheet.text(
+                SHEET_MARGIN + 5.,
+                sheet.y,
+                4.,
+                format!(
+                    "Thread {}: {} steps, {}, tie to peg {}",
+                    strand_i + 1,
+                    strand.steps.len(),
+                    self.format_length(strand.length()),
+                    strand.start
+                ),
+            );
+            sheet.y += 2.;
+
+            for (session_i, session) in strand.sessions(self.steps_per_session).enumerate() {
+                let mut steps = session;
+                let mut title = format!("Session {}", session_i + 1);
+                while !steps.is_empty() {
+                    sheet.reserve(session_height);
+                    sheet.y += SHEET_LINE_HEIGHT + 1.;
+                    sheet.push(
+                        LineSVG::new()
+                            .set("x1", SHEET_MARGIN)
+                            .set("y1", sheet.y - 4.)
+                            .set("x2", SHEET_WIDTH - SHEET_MARGIN)
+                            .set("y2", sheet.y - 4.)
+                            .set("stroke", "black")
+                            .set("stroke-width", 0.2),
+                    );
+                    sheet.text(SHEET_MARGIN, sheet.y, 3.5, title.clone());
+                    title = format!("Session {} (continued)", session_i + 1);
+
+                    // the steps fitting on the page, in columns
+                    let free_rows =
+                        ((SHEET_HEIGHT - SHEET_MARGIN - sheet.y) / SHEET_LINE_HEIGHT) as usize;
+                    let (page_steps, rest) =
+                        steps.split_at(steps.len().min(free_rows.max(1) * SHEET_COLUMNS));
+                    let rows = page_steps.len().div_ceil(SHEET_COLUMNS);
+                    for (index, step) in page_steps.iter().enumerate() {
+                        let column = index / rows;
+                        let row = index % rows;
+                        sheet.text(
+                            SHEET_MARGIN + column as f64 * column_width,
+                            sheet.y + (row + 1) as f64 * SHEET_LINE_HEIGHT,
+                            3.,
+                            match step.wrap {
+                                Some(wrap) => format!(
+                                    "{:>5}: {:>4} → {} {wrap}",
+                                    step.number, step.from, step.to
+                                ),
+                                None => {
+                                    format!("{:>5}: {:>4} → {}", step.number, step.from, step.to)
+                                }
+                            },
+                        );
+                    }
+                    sheet.y += rows as f64 * SHEET_LINE_HEIGHT + 2.;
+                    steps = rest;
+                }
+            }
+        }
+        sheet.documents()
+    }
+
+    /// Write the [`Instructions`] to file.
+    ///
+    /// # Arguments:
+    ///
+    /// * `path`: Output file path, `txt` for a step list, `csv`, or `svg` for printable A4 pages,
+    ///   written to `path` when they fit on a single page, to `<name>_1.svg`, `<name>_2.svg`, ...
+    ///   otherwise.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .ok_or("Could not determine extension.")?
+            .to_string_lossy();
+        match extension.as_ref() {
+            "txt" => std::fs::write(path, self.to_text())?,
+            "csv" => std::fs::write(path, self.to_csv())?,
+            "svg" => {
+                let pages = self.render_svg_pages();
+                if let [page] = pages.as_slice() {
+                    svg::save(path, page)?;
+                } else {
+                    let stem = path
+                        .file_stem()
+                        .ok_or("Could not determine file name.")?
+                        .to_string_lossy();
+                    for (page_i, page) in pages.iter().enumerate() {
+                        svg::save(
+                            path.with_file_name(format!("{stem}_{}.svg", page_i + 1)),
+                            page,
+                        )?;
+                    }
+                }
+            }
+            _ => return Err(format!("Unsupported instructions format '{extension}'.").into()),
+        }
+        Ok(())
+    }
+}
+
+/// The [`Strands`](Strand) of the [`Blueprint`], single [`Yarn`](crate::peg::Yarn) blueprints are a
+/// single black thread.
+pub(crate) fn strands(blueprint: &Blueprint) -> Vec<Strand> {
+    if blueprint.strands.is_empty() {
+        let mut strand = Strand::new((0, 0, 0), blueprint.peg_order.clone());
+        strand.wraps = blueprint.wraps.clone();
+        vec![strand]
+    } else {
+        blueprint.strands.clone()
+    }
+}
+
+/// The [`Pegs`](Peg) of the [`Blueprint`] in numbering order, its [`Blueprint::pegs`] if present,
+/// followed by the pegs of its peg order missing from them in order of first visit, otherwise the
+/// pegs of its peg order. Every peg of the peg order is numbered.
+pub(crate) fn numbered_pegs(blueprint: &Blueprint) -> Vec<Peg> {
+    let strands = strands(blueprint);
+    let visited = strands.iter().flat_map(|strand| &strand.peg_order);
+    if blueprint.pegs.is_empty() {
+        return frame_order(visited);
+    }
+    let mut pegs = frame_order(&blueprint.pegs);
+    let mut numbered: HashSet<(u32, u32)> = pegs.iter().map(|peg| (peg.x, peg.y)).collect();
+    pegs.extend(visited.filter(|peg| numbered.insert((peg.x, peg.y))));
+    pegs
+}
+
+/// The number of each of the [`numbered_pegs`], by coordinates, starting at 1.
+pub(crate) fn peg_numbers(pegs: &[Peg]) -> HashMap<(u32, u32), usize> {
+    pegs.iter()
+        .enumerate()
+        .map(|(index, peg)| ((peg.x, peg.y), index + 1))
+        .collect()
+}
+
+/// Deduplicate the [`Pegs`](Peg) and number the perimeter pegs clockwise around their centroid,
+/// starting from the top, followed by the interior pegs row by row, see [`on_perimeter`].
+pub(crate) fn frame_order<'a>(pegs: impl IntoIterator<Item = &'a Peg>) -> Vec<Peg> {
+    let mut unique: HashMap<(u32, u32), Peg> = HashMap::new();
+    for peg in pegs {
+        unique.entry((peg.x, peg.y)).or_insert(*peg);
+    }
+    let unique: Vec<Peg> = unique.into_values().collect();
+    let (mut pegs, mut interior) = (vec![], vec![]);
+    for (peg, perimeter) in unique.iter().zip(on_perimeter(&unique)) {
+        if perimeter {
+            pegs.push(*peg);
+        } else {
+            interior.push(*peg);
+        }
+    }
+    if pegs.is_empty() {
+        return interior;
+    }
+
+    let n_pegs = pegs.len() as f64;
+    let center_x = pegs.iter().map(|peg| peg.x as f64).sum::<f64>() / n_pegs;
+    let center_y = pegs.iter().map(|peg| peg.y as f64).sum::<f64>() / n_pegs;
+    let key = |peg: &Peg| {
+        let (dx, dy) = (peg.x as f64 - center_x, peg.y as f64 - center_y);
+        // the y axis points down, the angle is 0 at the top and increases clockwise
+        let angle = dx.atan2(-dy).rem_euclid(2. * PI);
+        (angle, dx * dx + dy * dy, peg.x, peg.y)
+    };
+    pegs.sort_by(|a, b| key(a).partial_cmp(&key(b)).unwrap());
+    interior.sort_by_key(|peg| (peg.y, peg.x));
+    pegs.extend(interior);
+    pegs
+}
+
+/// Whether each of the `pegs` is on the perimeter of the frame: within a quarter of the spacing
+/// of the pegs of its convex hull, and at least 1.5px, of the hull's edges. The other pegs are
+/// interior pegs, e.g. of a [`shape::hex_grid`](crate::peg::shape::hex_grid).
+fn on_perimeter(pegs: &[Peg]) -> Vec<bool> {
+    let points: Vec<(f64, f64)> = pegs
+        .iter()
+        .map(|peg| (peg.x as f64, peg.y as f64))
+        .collect();
+    let hull = convex_hull(&points);
+    if hull.len() < 3 {
+        return vec![true; pegs.len()];
+    }
+
+    // the median distance from the hull's vertices to their nearest peg
+    let mut spacings: Vec<f64> = hull
+        .iter()
+        .map(|&(x, y)| {
+            points
+                .iter()
+                .filter(|&&point| point != (x, y))
+                .map(|&(px, py)| (px - x).hypot(py - y))
+                .fold(f64::INFINITY, f64::min)
+        })
+        .collect();
+    spacings.sort_by(|a, b| a.total_cmp(b));
+    let tolerance = (spacings[spacings.len() / 2] / 4.).max(1.5);
+
+    let edges: Vec<_> = hull.iter().zip(hull.iter().cycle().skip(1)).collect();
+    points
+        .iter()
+        .map(|&(x, y)| {
+            edges.iter().any(|&(&(ax, ay), &(bx, by))| {
+                let (dx, dy) = (bx - ax, by - ay);
+                let t = (((x - ax) * dx + (y - ay) * dy) / (dx * dx + dy * dy)).clamp(0., 1.);
+                (x - ax - t * dx).hypot(y - ay - t * dy) <= tolerance
+            })
+        })
+        .collect()
+}
+
+/// Vertices of the convex hull of the `points`, without the collinear points of its edges.
+fn convex_hull(points: &[(f64, f64)]) -> Vec<(f64, f64)> {
+    let mut points = points.to_vec();
+    points.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    points.dedup();
+    if points.len() < 3 {
+        return points;
+    }
+    let cross = |o: (f64, f64), a: (f64, f64), b: (f64, f64)| {
+        (a.0 - o.0) * (b.1 - o.1) - (a.1 - o.1) * (b.0 - o.0)
+    };
+    // monotone chain, the lower then the upper hull
+    let mut hull: Vec<(f64, f64)> = Vec::with_capacity(2 * points.len());
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && cross(hull[hull.len() - 2], hull[hull.len() - 1], point) <= 0.
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // the last point starts the other half
+        hull.pop();
+    }
+    hull
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::peg::shape;
+
+    fn square_blueprint() -> Blueprint {
+        // corners of a square, listed counter clockwise from the top left
+        let top_left = Peg::new(0, 0);
+        let bottom_left = Peg::new(0, 10);
+        let bottom_right = Peg::new(10, 10);
+        let top_right = Peg::new(10, 0);
+        Blueprint::new(
+            vec![bottom_left, top_right, top_left, bottom_right, bottom_left],
+            10,
+            10,
+            None,
+            1.,
+            false,
+        )
+    }
+
+    #[test]
+    fn test_frame_order() {
+        let pegs = vec![
+            Peg::new(5, 10),
+            Peg::new(0, 5),
+            Peg::new(5, 0),
+            Peg::new(10, 5),
+            Peg::new(5, 0),
+        ];
+        let ordered: Vec<_> = frame_order(&pegs)
+            .iter()
+            .map(|peg| (peg.x, peg.y))
+            .collect();
+        assert_eq!(ordered, vec![(5, 0), (10, 5), (5, 10), (0, 5)]);
+    }
+
+    #[test]
+    fn frame_order_interior() {
+        // a jittered circle around a hex grid
+        let mut rng = crate::utils::seeded_rng(Some(3));
+        let circle: Vec<Peg> = shape::circle((100, 100), 90, 60)
+            .into_iter()
+            .map(|peg| peg.with_jitter(1, &mut rng))
+            .collect();
+        let interior: Vec<Peg> = shape::hex_grid((30, 30), (140, 140), 20.)
+            .into_iter()
+            .filter(|peg| (peg.x as f64 - 100.).hypot(peg.y as f64 - 100.) < 80.)
+            .collect();
+        let pegs: Vec<Peg> = interior.iter().chain(&circle).copied().collect();
+        let ordered = frame_order(&pegs);
+        assert_eq!(ordered.len(), pegs.len());
+
+        // the perimeter pegs first, clockwise from the top
+        let coords = |pegs: &[Peg]| pegs.iter().map(|peg| (peg.x, peg.y)).collect::<Vec<_>>();
+        assert_eq!(
+            coords(&ordered[..circle.len()]),
+            coords(&frame_order(&circle))
+        );
+        let top = circle.iter().min_by_key(|peg| peg.y).unwrap();
+        assert!(ordered[0].y <= top.y + 2);
+        // then the interior pegs, row by row
+        let mut rows = interior.clone();
+        rows.sort_by_key(|peg| (peg.y, peg.x));
+        assert_eq!(coords(&ordered[circle.len()..]), coords(&rows));
+    }
+
+    #[test]
+    fn instructions_steps() {
+        let instructions = Instructions::new(&square_blueprint(), 3);
+        // top right, bottom right, bottom left, top left
+        assert_eq!(instructions.pegs.len(), 4);
+        assert_eq!((instructions.pegs[0].x, instructions.pegs[0].y), (10, 0));
+        assert_eq!(instructions.strands.len(), 1);
+
+        let strand = &instructions.strands[0];
+        assert_eq!(strand.start, 3);
+        let steps: Vec<_> = strand
+            .steps
+            .iter()
+            .map(|step| (step.from, step.to))
+            .collect();
+        assert_eq!(steps, vec![(3, 1), (1, 4), (4, 2), (2, 3)]);
+        assert_eq!(strand.steps[3].number, 4);
+        assert_eq!(strand.sessions(3).len(), 2);
+        assert!((instructions.length() - (2. * 200_f64.sqrt() + 20.)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn instructions_unknown_pegs() {
+        let mut bp = square_blueprint();
+        bp.pegs = [(0, 0), (10, 0), (10, 10)]
+            .into_iter()
+            .map(|(x, y)| Peg::new(x, y))
+            .collect();
+        let instructions = Instructions::new(&bp, 3);
+        // the bottom left peg is missing from the frame's pegs, numbered after them
+        assert_eq!(instructions.pegs.len(), 4);
+        assert_eq!((instructions.pegs[3].x, instructions.pegs[3].y), (0, 10));
+        assert_eq!(instructions.strands[0].start, 4);
+    }
+
+    #[test]
+    fn instructions_formats() {
+        let instructions = Instructions::new(&square_blueprint(), 3);
+
+        let text = instructions.to_text();
+        assert!(text.contains("Tie the thread to peg 3"));
+        assert!(text.contains("Session 2 (steps 4-4)"));
+        assert!(text.contains("     1: 3 -> 1"));
+
+        let csv = instructions.to_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "thread,color,session,step,from,to,length_px");
+        assert_eq!(lines[4], "1,#000000,2,4,2,3,10.0");
+
+        let pages = instructions.render_svg_pages();
+        assert_eq!(pages.len(), 1);
+        let svg = pages[0].to_string();
+        assert!(svg.contains("width=\"210mm\""));
+        assert!(svg.contains("height=\"297mm\""));
+        assert!(svg.contains("Session 2"));
+
+        // long sessions continue on the next pages
+        let mut bp = square_blueprint();
+        bp.peg_order = bp.peg_order.iter().cycle().take(2001).copied().collect();
+        let pages = Instructions::new(&bp, 1000).render_svg_pages();
+        assert!(pages.len() > 2);
+        let svg: String = pages.iter().map(|page| page.to_string()).collect();
+        assert_eq!(svg.matches("height=\"297mm\"").count(), pages.len());
+        assert_eq!(svg.matches(" → ").count(), 2000);
+        assert!(svg.contains("Session 2 (continued)"));
+        assert!(svg.contains(&format!("{}/{}", pages.len(), pages.len())));
+    }
+
+    #[test]
+    fn instructions_wraps() {
+        let mut bp = square_blueprint();
+        bp.wraps = vec![
+            WrapDirection::Cw,
+            WrapDirection::Ccw,
+            WrapDirection::Cw,
+            WrapDirection::Cw,
+            WrapDirection::Ccw,
+        ];
+        let instructions = Instructions::new(&bp, 10);
+        let wraps: Vec<_> = instructions.strands[0]
+            .steps
+            .iter()
+            .map(|step| step.wrap)
+            .collect();
+        assert_eq!(
+            wraps,
+            vec![
+                Some(WrapDirection::Ccw),
+                Some(WrapDirection::Cw),
+                Some(WrapDirection::Cw),
+                Some(WrapDirection::Ccw)
+            ]
+        );
+
+        assert!(instructions.to_text().contains("     1: 3 -> 1 (ccw)\n"));
+        let csv = instructions.to_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines[0], "thread,color,session,step,from,to,length_px,wrap");
+        assert_eq!(lines[4], "1,#000000,1,4,2,3,10.0,ccw");
+
+        // without wrapping
+        let instructions = Instructions::new(&square_blueprint(), 10);
+        assert!(instructions.strands[0].steps[0].wrap.is_none());
+    }
+
+    #[test]
+    fn instructions_millimeters() {
+        let mut bp = square_blueprint();
+        bp.frame_size = Some(1000.);
+        let instructions = Instructions::new(&bp, 3);
+        assert!(instructions.millimeters);
+        assert_eq!(instructions.strands[0].steps[3].length, 1000.);
+        assert!(instructions.to_text().contains("Thread length: 4.83 m"));
+        assert!(instructions
+            .to_csv()
+            .ends_with("1,#000000,2,4,2,3,1000.0\n"));
+    }
+}
@@ -1,7 +1,16 @@
 #![doc =include_str!("../README.md")]
 
+pub mod animation;
 pub mod blueprint;
+pub mod gcode;
+pub mod instructions;
 pub mod line;
+pub mod line_cache;
+pub mod loss;
+pub mod metrics;
 pub mod pather;
 pub mod peg;
+pub mod stats;
+pub mod template;
+pub mod tune;
 pub mod utils;
@@ -1,4 +1,8 @@
 use image;
+
+use crate::loss::{LineSample, LossFunction, MeanLoss};
+use crate::peg::Yarn;
+use crate::utils;
 use std::iter::zip;
 
 #[derive(Debug)]
@@ -8,6 +12,9 @@ pub struct Line {
     pub x: Vec<u32>,
     /// Y coordinates of the pixels in the line.
     pub y: Vec<u32>,
+    /// Fraction of each pixel covered by the line, in [0, 1]. [`None`] when the pixels are fully
+    /// covered.
+    pub coverage: Option<Vec<f32>>,
     /// The distance of the line in pixels.
     pub dist: u32,
 }
@@ -16,7 +23,25 @@ impl Line {
     /// Creates a new [`Line`].
     pub fn new(x: Vec<u32>, y: Vec<u32>, dist: u32) -> Self {
         assert_eq!(x.len(), y.len(), "`x` and `y` should have the same length");
-        Self { x, y, dist }
+        Self {
+            x,
+            y,
+            coverage: None,
+            dist,
+        }
+    }
+
+    /// Creates a new anti-aliased [`Line`], with per pixel coverage.
+    pub fn with_coverage(x: Vec<u32>, y: Vec<u32>, coverage: Vec<f32>, dist: u32) -> Self {
+        assert_eq!(
+            x.len(),
+            coverage.len(),
+            "`x` and `coverage` should have the same length"
+        );
+        Self {
+            coverage: Some(coverage),
+            ..Self::new(x, y, dist)
+        }
     }
 
     /// Returns the length of this [`Line`].
@@ -43,16 +68,20 @@ impl Line {
     /// }
     /// assert_eq!(line.zip().len(), 2);
     /// ```
-    pub fn zip(&self) -> std::iter::Zip<std::slice::Iter<u32>, std::slice::Iter<u32>> {
+    pub fn zip(&self) -> std::iter::Zip<std::slice::Iter<'_, u32>, std::slice::Iter<'_, u32>> {
         zip(&self.x, &self.y)
     }
 
     /// Returns the copy of this [`Line`].
     pub fn copy(&self) -> Self {
-        Self::new(self.x.clone(), self.y.clone(), self.dist)
+        Self {
+            coverage: self.coverage.clone(),
+            ..Self::new(self.x.clone(), self.y.clone(), self.dist)
+        }
     }
 
-    /// Compute the loss of this [`Line`] over the provided single channel [`image::ImageBuffer`].
+    /// Compute the loss of this [`Line`] over the provided single channel [`image::ImageBuffer`],
+    /// using the [`MeanLoss`].
     ///
     /// # Arguments:
     ///
@@ -62,30 +91,334 @@ impl Line {
     ///
     /// * `f64`: The loss of this [`Line`].
     pub fn loss(&self, image: &image::ImageBuffer<image::Luma<u8>, Vec<u8>>) -> f64 {
-        self.zip()
-            .map(|(x, y)| image.get_pixel(*x, *y))
-            .fold(0.0, |acc, &pixel| acc + (pixel.0[0] as f64))
-            / (255. * self.len() as f64)
+        MeanLoss.loss(&self.sample(image, None), 0.)
+    }
+
+    /// Iterate over the pixel coordinates and coverage of this [`Line`], see
+    /// [`LinePixels::pixels`].
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32, f64)> + '_ {
+        <Self as LinePixels>::pixels(self)
+    }
+
+    /// Sample the pixels of this [`Line`], see [`LinePixels::sample`].
+    pub fn sample(
+        &self,
+        image: &image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+        weights: Option<&image::ImageBuffer<image::Luma<u8>, Vec<u8>>>,
+    ) -> LineSample {
+        <Self as LinePixels>::sample(self, image, weights)
+    }
+
+    /// Draw this [`Line`] on the image, see [`LinePixels::draw`].
+    pub fn draw(
+        &self,
+        image: &mut image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+        line_opacity: f64,
+        line_color: f64,
+    ) {
+        <Self as LinePixels>::draw(self, image, line_opacity, line_color)
+    }
+
+    /// Compute the loss of drawing this [`Line`] with a colored [`Yarn`], see
+    /// [`LinePixels::color_loss`].
+    pub fn color_loss(
+        &self,
+        canvas: &image::RgbImage,
+        target: &image::RgbImage,
+        yarn: &Yarn,
+        weights: Option<&image::GrayImage>,
+    ) -> f64 {
+        <Self as LinePixels>::color_loss(self, canvas, target, yarn, weights)
+    }
+
+    /// Draw this [`Line`] on an RGB image with a colored [`Yarn`], see
+    /// [`LinePixels::draw_color`].
+    pub fn draw_color(&self, image: &mut image::RgbImage, yarn: &Yarn) {
+        <Self as LinePixels>::draw_color(self, image, yarn)
+    }
+}
+
+/// Pixels of a rasterized line between 2 [`Pegs`](crate::peg::Peg), with their coverage.
+/// Implemented by the owned [`Line`] and by the [`LineRef`] borrowed from a
+/// [`LineCache`](crate::line_cache::LineCache).
+pub trait LinePixels {
+    /// Iterate over the pixel coordinates and coverage, in [0, 1], of the line.
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32, f64)> + '_;
+
+    /// The distance of the line in pixels.
+    fn dist(&self) -> u32;
+
+    /// Sample the pixels of this line over the provided single channel [`image::ImageBuffer`].
+    ///
+    /// # Arguments:
+    ///
+    /// * `image`: The [`image::ImageBuffer`] to sample.
+    /// * `weights`: Optional weight map, with the same dimensions as `image`. Each pixel is
+    ///   blended towards white, which means no yarn is required, with the opposite of its weight.
+    ///   Pixels with a weight of 0 are counted in [`LineSample::masked`].
+    ///
+    /// Each pixel contributes to the sample in proportion to its coverage.
+    ///
+    /// # Returns:
+    ///
+    /// * [`LineSample`]: The summary of the pixel values, used to compute the line's loss with a
+    ///   [`LossFunction`].
+    fn sample(
+        &self,
+        image: &image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+        weights: Option<&image::ImageBuffer<image::Luma<u8>, Vec<u8>>>,
+    ) -> LineSample {
+        let width = image.width() as usize;
+        let indices = self
+            .pixels()
+            .map(|(x, y, coverage)| (y as usize * width + x as usize, coverage));
+        sample_indices(indices, image, weights, self.dist())
     }
 
-    /// Draw the [`Line`] on the image, with alpha compositing.
+    /// Draw the line on the image, with alpha compositing. The opacity of the line is scaled
+    /// by the coverage of each pixel.
     ///
     /// # Arguments:
     ///
     /// * `image`: the [`image::ImageBuffer`] to draw the line on, should be single channel.
     /// * `line_opacity`: the opacity of the line.
     /// * `line_color`: the grey scale color of the line.
-    pub fn draw(
+    fn draw(
         &self,
         image: &mut image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
         line_opacity: f64,
         line_color: f64,
     ) {
-        self.zip().for_each(|(x, y)| {
-            let pixel = image.get_pixel_mut(*x, *y);
-            pixel.0[0] = ((1. - line_opacity) * pixel.0[0] as f64 + line_color)
-                .round()
-                .min(255.0) as u8;
+        let width = image.width() as usize;
+        let indices = self
+            .pixels()
+            .map(|(x, y, coverage)| (y as usize * width + x as usize, coverage));
+        draw_indices(indices, image, line_opacity, line_color);
+    }
+
+    /// Compute the loss of drawing this line with a colored [`Yarn`] over an RGB canvas.
+    ///
+    /// # Arguments:
+    ///
+    /// * `canvas`: The [`image::RgbImage`] the lines are drawn on.
+    /// * `target`: The [`image::RgbImage`] the canvas should match.
+    /// * `yarn`: The [`Yarn`] to draw the line with.
+    /// * `weights`: Optional weight map, scales the change in color distance of each pixel.
+    ///
+    /// # Returns:
+    ///
+    /// * `f64`: The mean change in color distance to `target` along the line, normalized to
+    ///   [-1, 1], negative values mean the line brings the canvas closer to the target.
+    fn color_loss(
+        &self,
+        canvas: &image::RgbImage,
+        target: &image::RgbImage,
+        yarn: &Yarn,
+        weights: Option<&image::GrayImage>,
+    ) -> f64 {
+        let (loss, count) = self
+            .pixels()
+            .map(|(x, y, coverage)| {
+                let pixel = canvas.get_pixel(x, y).0;
+                let target_pixel = target.get_pixel(x, y).0;
+                let (r, g, b) = (target_pixel[0], target_pixel[1], target_pixel[2]);
+                let drawn = blend(pixel, yarn, coverage);
+                let weight =
+                    weights.map_or(1., |weights| weights.get_pixel(x, y).0[0] as f64 / 255.);
+                (
+                    coverage
+                        * weight
+                        * (utils::color_dist(drawn, (r, g, b))
+                            - utils::color_dist(pixel, (r, g, b))),
+                    coverage,
+                )
+            })
+            .fold((0., 0.), |(loss_acc, count_acc), (loss, count)| {
+                (loss_acc + loss, count_acc + count)
+            });
+        loss / (255. * 3_f64.sqrt() * count)
+    }
+
+    /// Draw the line on an RGB image with a colored [`Yarn`], with alpha compositing.
+    ///
+    /// # Arguments:
+    ///
+    /// * `image`: the [`image::RgbImage`] to draw the line on.
+    /// * `yarn`: the [`Yarn`] to draw the line with.
+    fn draw_color(&self, image: &mut image::RgbImage, yarn: &Yarn) {
+        self.pixels().for_each(|(x, y, coverage)| {
+            let pixel = image.get_pixel_mut(x, y);
+            pixel.0 = blend(pixel.0, yarn, coverage);
         });
     }
 }
+
+impl LinePixels for Line {
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32, f64)> + '_ {
+        self.zip().enumerate().map(|(i, (x, y))| {
+            let coverage = self
+                .coverage
+                .as_ref()
+                .map_or(1., |coverage| coverage[i] as f64);
+            (*x, *y, coverage)
+        })
+    }
+
+    fn dist(&self) -> u32 {
+        self.dist
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A line borrowed from a [`LineCache`](crate::line_cache::LineCache).
+pub struct LineRef<'a> {
+    /// Linear indices, `y * image_width + x`, of the pixels in the line.
+    pub indices: &'a [u32],
+    /// Coverage of the pixels, quantized to [0, 255]. [`None`] when the pixels are fully covered.
+    pub coverage: Option<&'a [u8]>,
+    /// Width of the image the line was rasterized for.
+    pub image_width: u32,
+    /// The distance of the line in pixels.
+    pub dist: u32,
+}
+
+impl LineRef<'_> {
+    /// Returns the number of pixels of this [`LineRef`].
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    /// Returns true if this [`LineRef`] has no pixels.
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Iterate over the linear pixel indices and coverage, in [0, 1], of this [`LineRef`].
+    fn indexed_pixels(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.indices.iter().enumerate().map(|(i, index)| {
+            let coverage = self
+                .coverage
+                .map_or(1., |coverage| coverage[i] as f64 / 255.);
+            (*index as usize, coverage)
+        })
+    }
+
+    /// Convert to an owned [`Line`].
+    pub fn to_line(&self) -> Line {
+        let (x, y) = self
+            .pixels()
+            .map(|(x, y, _)| (x, y))
+            .unzip::<u32, u32, Vec<u32>, Vec<u32>>();
+        match self.coverage {
+            Some(coverage) => Line::with_coverage(
+                x,
+                y,
+                coverage.iter().map(|c| *c as f32 / 255.).collect(),
+                self.dist,
+            ),
+            None => Line::new(x, y, self.dist),
+        }
+    }
+}
+
+impl LinePixels for LineRef<'_> {
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32, f64)> + '_ {
+        self.indexed_pixels().map(|(index, coverage)| {
+            let index = index as u32;
+            (index % self.image_width, index / self.image_width, coverage)
+        })
+    }
+
+    fn dist(&self) -> u32 {
+        self.dist
+    }
+
+    // the linear indices address the image buffers directly
+    fn sample(
+        &self,
+        image: &image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+        weights: Option<&image::ImageBuffer<image::Luma<u8>, Vec<u8>>>,
+    ) -> LineSample {
+        sample_indices(self.indexed_pixels(), image, weights, self.dist)
+    }
+
+    fn draw(
+        &self,
+        image: &mut image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+        line_opacity: f64,
+        line_color: f64,
+    ) {
+        draw_indices(self.indexed_pixels(), image, line_opacity, line_color);
+    }
+}
+
+/// Sample the pixels at the linear indices of `image`, with their coverage, see
+/// [`LinePixels::sample`].
+fn sample_indices(
+    indices: impl Iterator<Item = (usize, f64)>,
+    image: &image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+    weights: Option<&image::ImageBuffer<image::Luma<u8>, Vec<u8>>>,
+    dist: u32,
+) -> LineSample {
+    let (pixels, weights) = (image.as_raw(), weights.map(|weights| weights.as_raw()));
+    let (sum, sum_squares, count, covered_residuals, masked) = indices.fold(
+        (0.0, 0.0, 0.0, 0.0, 0.0),
+        |(sum, sum_squares, count, covered_residuals, masked), (index, coverage)| {
+            let mut value = pixels[index] as f64 / 255.;
+            let mut is_masked = 0.;
+            if let Some(weights) = weights {
+                let weight = weights[index] as f64 / 255.;
+                value = weight * value + (1. - weight);
+                if weight == 0. {
+                    is_masked = 1.;
+                }
+            }
+            let residual = coverage * (1. - value);
+            (
+                sum + coverage * value,
+                sum_squares + coverage * value * value,
+                count + coverage,
+                covered_residuals + residual * residual,
+                masked + is_masked,
+            )
+        },
+    );
+    LineSample {
+        sum,
+        sum_squares,
+        count,
+        covered_residuals,
+        masked,
+        dist,
+    }
+}
+
+/// Draw the pixels at the linear indices of `image`, with their coverage, see
+/// [`LinePixels::draw`].
+fn draw_indices(
+    indices: impl Iterator<Item = (usize, f64)>,
+    image: &mut image::ImageBuffer<image::Luma<u8>, Vec<u8>>,
+    line_opacity: f64,
+    line_color: f64,
+) {
+    let pixels: &mut [u8] = image;
+    indices.for_each(|(index, coverage)| {
+        let pixel = &mut pixels[index];
+        *pixel = ((1. - coverage * line_opacity) * *pixel as f64 + coverage * line_color)
+            .round()
+            .min(255.0) as u8;
+    });
+}
+
+/// Alpha composite the [`Yarn`]'s color over a pixel, with the [`Yarn::opacity`] scaled by the
+/// pixel's coverage.
+fn blend(pixel: [u8; 3], yarn: &Yarn, coverage: f64) -> [u8; 3] {
+    let (r, g, b) = yarn.color;
+    let opacity = coverage * yarn.opacity;
+    let mix = |value: u8, color: u8| {
+        ((1. - opacity) * value as f64 + opacity * color as f64)
+            .round()
+            .clamp(0., 255.) as u8
+    };
+    [mix(pixel[0], r), mix(pixel[1], g), mix(pixel[2], b)]
+}
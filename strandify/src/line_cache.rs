@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::line::{Line, LineRef};
+
+/// Magic bytes at the start of the [`LineCache`] files.
+const MAGIC: &[u8; 8] = b"STRNDLC\0";
+/// Version of the [`LineCache`] file format.
+const FORMAT_VERSION: u32 = 1;
+/// Number of values read at once by [`read_values`].
+const READ_CHUNK: usize = 1 << 14;
+/// Number of shards of the [`ShardedLineCache`].
+const LAZY_SHARDS: usize = 16;
+
+/// Returns the number of [`Peg`](crate::peg::Peg) pairs between `n_pegs` pegs.
+pub fn n_pairs(n_pegs: usize) -> usize {
+    n_pegs * n_pegs.saturating_sub(1) / 2
+}
+
+/// Dense index of the pair of [`Pegs`](crate::peg::Peg) at indices `peg_a` and `peg_b`, among
+/// `n_pegs` pegs. The pairs are ordered by their lowest index first.
+///
+/// # Examples
+///
+/// ```
+/// use strandify::line_cache::pair_index;
+/// assert_eq!(pair_index(0, 1, 4), 0);
+/// assert_eq!(pair_index(3, 0, 4), 2);
+/// assert_eq!(pair_index(1, 2, 4), 3);
+/// assert_eq!(pair_index(2, 3, 4), 5);
+/// ```
+pub fn pair_index(peg_a: usize, peg_b: usize, n_pegs: usize) -> usize {
+    let (a, b) = if peg_a < peg_b {
+        (peg_a, peg_b)
+    } else {
+        (peg_b, peg_a)
+    };
+    a * n_pegs - a * (a + 1) / 2 + b - a - 1
+}
+
+#[derive(Debug, Clone, Default)]
+/// Compact cache of the [`Lines`](Line) between all the pairs of [`Pegs`](crate::peg::Peg).
+///
+/// The pixels of all the lines are stored in a flat arena, as linear pixel indices, and looked up
+/// with the dense [`pair_index`] of the pegs' indices.
+pub struct LineCache {
+    /// Number of [`Pegs`](crate::peg::Peg).
+    n_pegs: usize,
+    /// Width of the image the lines are rasterized for.
+    image_width: u32,
+    /// Whether the lines hold per pixel coverage.
+    with_coverage: bool,
+    /// Pixels of the pair at index `i` are at `offsets[i]..offsets[i + 1]` in the arena.
+    offsets: Vec<usize>,
+    /// Distance of each line, [`u32::MAX`] for the pairs without a line.
+    dists: Vec<u32>,
+    /// Linear indices, `y * image_width + x`, of the pixels of all the lines.
+    pixels: Vec<u32>,
+    /// Coverage of the pixels of all the lines, quantized to [0, 255], empty when
+    /// [`LineCache::with_coverage`] is false.
+    coverage: Vec<u8>,
+    /// Number of lines in the cache.
+    n_lines: usize,
+}
+
+impl LineCache {
+    /// Creates a new empty [`LineCache`].
+    ///
+    /// # Arguments
+    ///
+    /// * `n_pegs`: Number of [`Pegs`](crate::peg::Peg).
+    /// * `image_width`: Width of the image the lines are rasterized for.
+    /// * `with_coverage`: Whether to store the per pixel [`Line::coverage`].
+    pub fn new(n_pegs: usize, image_width: u32, with_coverage: bool) -> Self {
+        Self {
+            n_pegs,
+            image_width,
+            with_coverage,
+            offsets: vec![0],
+            ..Default::default()
+        }
+    }
+
+    /// Append the [`Line`] of the next peg pair, in [`pair_index`] order, [`None`] if the pair has
+    /// no line.
+    pub(crate) fn push(&mut self, line: Option<&Line>) {
+        match line {
+            Some(line) => {
+                self.pixels
+                    .extend(line.zip().map(|(x, y)| y * self.image_width + x));
+                if self.with_coverage {
+                    match &line.coverage {
+                        Some(coverage) => self.coverage.extend(
+                            coverage
+                                .iter()
+                                .map(|c| (c.clamp(0., 1.) * 255.).round() as u8),
+                        ),
+                        None => self
+                            .coverage
+                            .extend(std::iter::repeat_n(u8::MAX, line.len())),
+                    }
+                }
+                self.dists.push(line.dist);
+                self.n_lines += 1;
+            }
+            None => self.dists.push(u32::MAX),
+        }
+        self.offsets.push(self.pixels.len());
+    }
+
+    /// Append the lines of another [`LineCache`], which holds the following peg pairs.
+    pub(crate) fn append(&mut self, other: LineCache) {
+        let offset = self.pixels.len();
+        self.offsets
+            .extend(other.offsets.into_iter().skip(1).map(|o| o + offset));
+        self.dists.extend(other.dists);
+        self.pixels.extend(other.pixels);
+        self.coverage.extend(other.coverage);
+        self.n_lines += other.n_lines;
+    }
+
+    /// Release the unused capacity of the arena, once all the lines are appended.
+    pub(crate) fn shrink_to_fit(&mut self) {
+        self.offsets.shrink_to_fit();
+        self.dists.shrink_to_fit();
+        self.pixels.shrink_to_fit();
+        self.coverage.shrink_to_fit();
+    }
+
+    /// Get the line between the [`Pegs`](crate::peg::Peg) at indices `peg_a` and `peg_b`.
+    pub fn get(&self, peg_a: usize, peg_b: usize) -> Option<LineRef<'_>> {
+        if peg_a == peg_b || peg_a >= self.n_pegs || peg_b >= self.n_pegs {
+            return None;
+        }
+        let index = pair_index(peg_a, peg_b, self.n_pegs);
+        let dist = *self.dists.get(index)?;
+        if dist == u32::MAX {
+            return None;
+        }
+        let range = self.offsets[index]..self.offsets[index + 1];
+        Some(LineRef {
+            indices: &self.pixels[range.clone()],
+            coverage: self.with_coverage.then(|| &self.coverage[range]),
+            image_width: self.image_width,
+            dist,
+        })
+    }
+
+    /// Returns the number of lines in the [`LineCache`].
+    pub fn len(&self) -> usize {
+        self.n_lines
+    }
+
+    /// Returns true if the [`LineCache`] holds no lines.
+    pub fn is_empty(&self) -> bool {
+        self.n_lines == 0
+    }
+
+    /// Write the [`LineCache`] in a little endian binary format.
+    ///
+    /// # Arguments
+    ///
+    /// * `writer`: Where to write the [`LineCache`].
+    /// * `key`: Identifies the parameters the lines were computed with, checked when reading the
+    ///   [`LineCache`] back, see [`Pather::line_cache_key`](crate::pather::Pather::line_cache_key).
+    pub fn write<W: Write>(&self, writer: &mut W, key: u64) -> Result<(), Box<dyn Error>> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        writer.write_all(&key.to_le_bytes())?;
+        writer.write_all(&(self.n_pegs as u64).to_le_bytes())?;
+        writer.write_all(&self.image_width.to_le_bytes())?;
+        writer.write_all(&[self.with_coverage as u8])?;
+        writer.write_all(&(self.pixels.len() as u64).to_le_bytes())?;
+        for offset in &self.offsets {
+            writer.write_all(&(*offset as u64).to_le_bytes())?;
+        }
+        for dist in &self.dists {
+            writer.write_all(&dist.to_le_bytes())?;
+        }
+        for pixel in &self.pixels {
+            writer.write_all(&pixel.to_le_bytes())?;
+        }
+        writer.write_all(&self.coverage)?;
+        Ok(())
+    }
+
+    /// Read a [`LineCache`] written with [`LineCache::write`].
+    ///
+    /// # Arguments
+    ///
+    /// * `reader`: Where to read the [`LineCache`] from.
+    /// * `key`: The key the [`LineCache`] was written with, see [`LineCache::write`].
+    /// * `n_pegs`: Expected number of [`Pegs`](crate::peg::Peg).
+    /// * `image_dimensions`: Width and height of the image the lines are rasterized for.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the data is not a valid [`LineCache`], if it was
+    /// written with a different `key`, or if its pegs or pixels don't fit `n_pegs` and
+    /// `image_dimensions`.
+    pub fn read<R: Read>(
+        reader: &mut R,
+        key: u64,
+        n_pegs: usize,
+        (image_width, image_height): (u32, u32),
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err("Not a line cache file.".into());
+        }
+        let version = u32::from_le_bytes(read_bytes(reader)?);
+        if version != FORMAT_VERSION {
+            return Err(format!("Unsupported line cache format version {version}.").into());
+        }
+        if u64::from_le_bytes(read_bytes(reader)?) != key {
+            return Err(
+                "Line cache was computed for different pegs, image dimensions or line parameters."
+                    .into(),
+            );
+        }
+        let file_n_pegs = u64::from_le_bytes(read_bytes(reader)?) as usize;
+        if file_n_pegs != n_pegs {
+            return Err(format!("Line cache has {file_n_pegs} pegs, expected {n_pegs}.").into());
+        }
+        let file_image_width = u32::from_le_bytes(read_bytes(reader)?);
+        if file_image_width != image_width {
+            return Err(format!(
+                "Line cache is for an image of width {file_image_width}, expected {image_width}."
+            )
+            .into());
+        }
+        let with_coverage = read_bytes::<_, 1>(reader)?[0] != 0;
+        let n_pixels = u64::from_le_bytes(read_bytes(reader)?) as usize;
+        let n_pairs = n_pairs(n_pegs);
+
+        let offsets = read_values(reader, n_pairs + 1, |bytes| {
+            u64::from_le_bytes(bytes) as usize
+        })?;
+        let dists = read_values(reader, n_pairs, u32::from_le_bytes)?;
+        if offsets.first() != Some(&0)
+            || offsets.last() != Some(&n_pixels)
+            || offsets.windows(2).any(|window| window[0] > window[1])
+        {
+            return Err("Invalid line cache offsets.".into());
+        }
+        let pixels = read_values(reader, n_pixels, u32::from_le_bytes)?;
+        let image_size = image_width as u64 * image_height as u64;
+        if pixels.iter().any(|pixel| *pixel as u64 >= image_size) {
+            return Err("Line cache pixels are outside of the image.".into());
+        }
+        let coverage = if with_coverage {
+            read_values(reader, n_pixels, u8::from_le_bytes)?
+        } else {
+            vec![]
+        };
+
+        Ok(Self {
+            n_pegs,
+            image_width,
+            with_coverage,
+            offsets,
+            n_lines: dists.iter().filter(|dist| **dist != u32::MAX).count(),
+            dists,
+            pixels,
+            coverage,
+        })
+    }
+
+    /// Write the [`LineCache`] to a binary file, see [`LineCache::write`].
+    pub fn to_file<P: AsRef<Path>>(&self, file_path: P, key: u64) -> Result<(), Box<dyn Error>> {
+        let mut writer = BufWriter::new(File::create(file_path)?);
+        self.write(&mut writer, key)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Read a [`LineCache`] from a binary file, see [`LineCache::read`].
+    pub fn from_file<P: AsRef<Path>>(
+        file_path: P,
+        key: u64,
+        n_pegs: usize,
+        image_dimensions: (u32, u32),
+    ) -> Result<Self, Box<dyn Error>> {
+        Self::read(
+            &mut BufReader::new(File::open(file_path)?),
+            key,
+            n_pegs,
+            image_dimensions,
+        )
+    }
+
+    /// Approximate memory used by the [`LineCache`], in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.offsets.capacity() * std::mem::size_of::<usize>()
+            + self.dists.capacity() * std::mem::size_of::<u32>()
+            + self.pixels.capacity() * std::mem::size_of::<u32>()
+            + self.coverage.capacity()
+    }
+}
+
+/// Read `N` bytes.
+fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N], Box<dyn Error>> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Read `n` values of `N` little endian bytes, in chunks of [`READ_CHUNK`] values. The values are
+/// allocated as they are read, a corrupt length fails at the end of the data.
+fn read_values<R: Read, T, const N: usize>(
+    reader: &mut R,
+    n: usize,
+    from_bytes: impl Fn([u8; N]) -> T,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let mut values = Vec::with_capacity(n.min(READ_CHUNK));
+    let mut buffer = vec![0; N * READ_CHUNK.min(n)];
+    let mut remaining = n;
+    while remaining > 0 {
+        let chunk = &mut buffer[..N * READ_CHUNK.min(remaining)];
+        reader.read_exact(chunk)?;
+        values.extend(
+            chunk
+                .chunks_exact(N)
+                .map(|bytes| from_bytes(bytes.try_into().unwrap())),
+        );
+        remaining -= chunk.len() / N;
+    }
+    Ok(values)
+}
+
+#[derive(Debug, Default)]
+/// Bounded least recently used cache of [`Lines`](Line), keyed by their [`pair_index`]. Used by
+/// the [`Pather`](crate::pather::Pather) when computing the lines on demand, see
+/// [`LineMode::Lazy`](crate::pather::LineMode::Lazy).
+pub(crate) struct LruLineCache {
+    /// Lines and the tick at which they were last used.
+    lines: HashMap<usize, (Arc<Line>, u64)>,
+    /// Incremented at each access.
+    tick: u64,
+}
+
+impl LruLineCache {
+    /// Get the [`Line`] with key `key`, marking it as recently used.
+    pub(crate) fn get(&mut self, key: usize) -> Option<Arc<Line>> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.lines.get_mut(&key).map(|(line, last_used)| {
+            *last_used = tick;
+            line.clone()
+        })
+    }
+
+    /// Insert a [`Line`], when the cache holds `capacity` lines the least recently used half is
+    /// evicted first, which amortizes the eviction over the following inserts.
+    pub(crate) fn insert(&mut self, key: usize, line: Arc<Line>, capacity: usize) {
+        if capacity == 0 {
+            return;
+        }
+        if self.lines.len() >= capacity {
+            let mut ticks: Vec<u64> = self.lines.values().map(|(_, tick)| *tick).collect();
+            let keep = capacity / 2;
+            let evict = ticks.len() - keep;
+            let (_, threshold, _) = ticks.select_nth_unstable(evict - 1);
+            let threshold = *threshold;
+            self.lines.retain(|_, (_, tick)| *tick > threshold);
+        }
+        self.tick += 1;
+        self.lines.insert(key, (line, self.tick));
+    }
+}
+
+#[derive(Debug)]
+/// [`LruLineCache`] split into shards by key, each behind its own lock, so that the threads
+/// computing the lines on demand rarely wait on each other and evictions only lock a shard.
+pub(crate) struct ShardedLineCache {
+    shards: Vec<Mutex<LruLineCache>>,
+}
+
+impl Default for ShardedLineCache {
+    fn default() -> Self {
+        Self {
+            shards: (0..LAZY_SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+}
+
+impl ShardedLineCache {
+    /// The shard holding the key `key`.
+    fn shard(&self, key: usize) -> &Mutex<LruLineCache> {
+        &self.shards[key % self.shards.len()]
+    }
+
+    /// Get the [`Line`] with key `key`, see [`LruLineCache::get`].
+    pub(crate) fn get(&self, key: usize) -> Option<Arc<Line>> {
+        self.shard(key).lock().unwrap().get(key)
+    }
+
+    /// Insert a [`Line`], each shard holds at most its share of `capacity` lines, see
+    /// [`LruLineCache::insert`].
+    pub(crate) fn insert(&self, key: usize, line: Arc<Line>, capacity: usize) {
+        self.shard(key)
+            .lock()
+            .unwrap()
+            .insert(key, line, capacity.div_ceil(self.shards.len()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::line::LinePixels;
+
+    #[test]
+    fn test_pair_index() {
+        let n_pegs = 5;
+        let mut indices = vec![];
+        for a in 0..n_pegs {
+            for b in a + 1..n_pegs {
+                assert_eq!(pair_index(a, b, n_pegs), pair_index(b, a, n_pegs));
+                indices.push(pair_index(a, b, n_pegs));
+            }
+        }
+        assert_eq!(indices, (0..n_pairs(n_pegs)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn line_cache_get() {
+        // 3 pegs: pairs (0, 1), (0, 2), (1, 2)
+        let mut cache = LineCache::new(3, 10, false);
+        cache.push(Some(&Line::new(vec![0, 1], vec![0, 0], 1)));
+        cache.push(None);
+        let mut row = LineCache::new(3, 10, false);
+        row.push(Some(&Line::new(vec![2, 3, 4], vec![1, 2, 3], 3)));
+        cache.append(row);
+
+        assert_eq!(cache.len(), 2);
+        assert!(cache.get(0, 2).is_none());
+        assert!(cache.get(1, 1).is_none());
+        assert!(cache.get(1, 3).is_none());
+        let line = cache.get(1, 0).unwrap();
+        assert_eq!(line.dist, 1);
+        assert_eq!(
+            line.pixels().collect::<Vec<_>>(),
+            vec![(0, 0, 1.), (1, 0, 1.)]
+        );
+        let line = cache.get(2, 1).unwrap();
+        assert_eq!(line.dist, 3);
+        assert_eq!(line.to_line().x, vec![2, 3, 4]);
+        assert_eq!(line.to_line().y, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn line_cache_coverage() {
+        let mut cache = LineCache::new(2, 10, true);
+        cache.push(Some(&Line::with_coverage(
+            vec![0, 1],
+            vec![5, 5],
+            vec![1., 0.5],
+            1,
+        )));
+        let line = cache.get(0, 1).unwrap();
+        let coverage: Vec<f64> = line.pixels().map(|(_, _, c)| c).collect();
+        assert_eq!(coverage[0], 1.);
+        assert!((coverage[1] - 0.5).abs() < 0.01);
+    }
+
+    #[test]
+    fn line_cache_write_read() {
+        let mut cache = LineCache::new(3, 10, true);
+        cache.push(Some(&Line::with_coverage(
+            vec![0, 1],
+            vec![5, 5],
+            vec![1., 0.5],
+            1,
+        )));
+        cache.push(None);
+        cache.push(Some(&Line::new(vec![2, 3, 4], vec![1, 2, 3], 3)));
+
+        let mut bytes = vec![];
+        cache.write(&mut bytes, 42).unwrap();
+        let read = LineCache::read(&mut bytes.as_slice(), 42, 3, (10, 10)).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read.offsets, cache.offsets);
+        assert_eq!(read.dists, cache.dists);
+        assert_eq!(read.pixels, cache.pixels);
+        assert_eq!(read.coverage, cache.coverage);
+
+        // different key
+        assert!(LineCache::read(&mut bytes.as_slice(), 43, 3, (10, 10)).is_err());
+        // different pegs or image
+        assert!(LineCache::read(&mut bytes.as_slice(), 42, 4, (10, 10)).is_err());
+        assert!(LineCache::read(&mut bytes.as_slice(), 42, 3, (12, 10)).is_err());
+        // pixels outside of the image, the last pixel is at y = 5
+        assert!(LineCache::read(&mut bytes.as_slice(), 42, 3, (10, 5)).is_err());
+        // truncated
+        assert!(LineCache::read(&mut &bytes[..bytes.len() - 1], 42, 3, (10, 10)).is_err());
+        // not a line cache
+        assert!(LineCache::read(&mut b"not a line cache".as_slice(), 42, 3, (10, 10)).is_err());
+    }
+
+    #[test]
+    fn lru_line_cache() {
+        let mut cache = LruLineCache::default();
+        let line = Arc::new(Line::new(vec![0], vec![0], 1));
+        cache.insert(0, line.clone(), 0);
+        assert!(cache.lines.is_empty());
+
+        for key in 0..4 {
+            cache.insert(key, line.clone(), 4);
+        }
+        assert_eq!(cache.lines.len(), 4);
+        // key 0 becomes the most recently used
+        assert!(cache.get(0).is_some());
+        cache.insert(4, line.clone(), 4);
+        // the 2 least recently used lines are evicted
+        assert_eq!(cache.lines.len(), 3);
+        assert!(cache.get(0).is_some());
+        assert!(cache.get(3).is_some());
+        assert!(cache.get(4).is_some());
+        assert!(cache.get(1).is_none());
+    }
+
+    #[test]
+    fn sharded_line_cache() {
+        let cache = ShardedLineCache::default();
+        let line = Arc::new(Line::new(vec![0], vec![0], 1));
+        for key in 0..LAZY_SHARDS * 4 {
+            cache.insert(key, line.clone(), LAZY_SHARDS * 4);
+        }
+        assert!((0..LAZY_SHARDS * 4).all(|key| cache.get(key).is_some()));
+        // the shard of key 0 is full, its least recently used half is evicted
+        cache.insert(LAZY_SHARDS * 4, line.clone(), LAZY_SHARDS * 4);
+        assert!(cache.get(LAZY_SHARDS * 4).is_some());
+        assert!(cache.get(0).is_none());
+        // the other shards are untouched
+        assert!(cache.get(1).is_some());
+    }
+}
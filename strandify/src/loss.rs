@@ -0,0 +1,179 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+/// Summary of the pixels under a [`Line`](crate::line::Line), from which a [`LossFunction`]
+/// computes the loss of the line.
+pub struct LineSample {
+    /// Sum of the pixel values, normalized to [0, 1].
+    pub sum: f64,
+    /// Sum of the squared pixel values, normalized to [0, 1].
+    pub sum_squares: f64,
+    /// Number of pixels in the line.
+    pub count: f64,
+    /// Sum of the squared residuals, `(1 - value)^2` the darkness the pixels still require,
+    /// weighted by the square of the pixel coverage.
+    pub covered_residuals: f64,
+    /// Number of pixels in the line with a weight of 0, see
+    /// [`Pather::weight_mask`](crate::pather::Pather::weight_mask).
+    pub masked: f64,
+    /// The distance of the line in pixels.
+    pub dist: u32,
+}
+
+impl LineSample {
+    /// Returns the mean pixel value of this [`LineSample`].
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count
+    }
+}
+
+/// Computes the loss of a line, the [`Pather`](crate::pather::Pather) connects the
+/// [`Pegs`](crate::peg::Peg) with the lowest loss.
+///
+/// The pathing algorithms work on an image which is lightened each time a line is drawn, dark
+/// pixels are the ones which still require yarn.
+pub trait LossFunction: Debug + Send + Sync {
+    /// Compute the loss of a line.
+    ///
+    /// # Arguments
+    ///
+    /// * `sample`: The [`LineSample`] of the pixels under the line.
+    /// * `opacity`: The opacity with which the line will be drawn.
+    fn loss(&self, sample: &LineSample, opacity: f64) -> f64;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Mean pixel brightness along the line, in [0, 1].
+pub struct MeanLoss;
+
+impl LossFunction for MeanLoss {
+    fn loss(&self, sample: &LineSample, _opacity: f64) -> f64 {
+        sample.mean()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Mean squared pixel brightness along the line, in [0, 1]. Penalizes lines crossing bright
+/// pixels more than [`MeanLoss`]. Only depends on the current pixels, see [`ImprovementLoss`] to
+/// account for the change brought by the line.
+pub struct MeanSquareLoss;
+
+impl LossFunction for MeanSquareLoss {
+    fn loss(&self, sample: &LineSample, _opacity: f64) -> f64 {
+        sample.sum_squares / sample.count
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Change of the squared error along the line between before and after drawing it, divided by
+/// the number of pixels, negative for improvements. The error of a pixel is its squared residual,
+/// see [`LineSample::covered_residuals`], scaled by the weight mask through the sampled values.
+/// Favours the lines which improve the image the most.
+pub struct ImprovementLoss;
+
+impl LossFunction for ImprovementLoss {
+    fn loss(&self, sample: &LineSample, opacity: f64) -> f64 {
+        // drawing a pixel v with coverage c gives v' = (1 - c * opacity) * v + c * opacity, its
+        // residual (1 - v)^2 becomes (1 - c * opacity)^2 * (1 - v)^2, a change of
+        // (c^2 * opacity^2 - 2 * c * opacity) * (1 - v)^2
+        let residuals = sample.count - 2. * sample.sum + sample.sum_squares;
+        (opacity.powi(2) * sample.covered_residuals - 2. * opacity * residuals) / sample.count
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Sum of the pixel brightness along the line divided by the length of the line, rather than its
+/// number of pixels. Compensates for diagonal lines being rasterized with fewer pixels per unit
+/// of length.
+pub struct LengthNormalizedLoss;
+
+impl LossFunction for LengthNormalizedLoss {
+    fn loss(&self, sample: &LineSample, _opacity: f64) -> f64 {
+        sample.sum / sample.dist.max(1) as f64
+    }
+}
+
+/// Names of the built-in [`LossFunctions`](LossFunction), see [`from_name`].
+pub const LOSS_NAMES: [&str; 4] = ["mean", "mean-square", "improvement", "length-normalized"];
+
+/// Get a built-in [`LossFunction`] from its name.
+///
+/// # Arguments
+///
+/// * `name`: One of [`LOSS_NAMES`].
+pub fn from_name(name: &str) -> Result<Arc<dyn LossFunction>, String> {
+    match name {
+        "mean" => Ok(Arc::new(MeanLoss)),
+        "mean-square" => Ok(Arc::new(MeanSquareLoss)),
+        "improvement" => Ok(Arc::new(ImprovementLoss)),
+        "length-normalized" => Ok(Arc::new(LengthNormalizedLoss)),
+        _ => Err(format!("Unrecognized loss function '{name}'")),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample() -> LineSample {
+        // pixels: 0., 0.5, 1.
+        LineSample {
+            sum: 1.5,
+            sum_squares: 1.25,
+            count: 3.,
+            covered_residuals: 1.25,
+            masked: 0.,
+            dist: 6,
+        }
+    }
+
+    #[test]
+    fn mean_loss() {
+        assert_eq!(MeanLoss.loss(&sample(), 0.1), 0.5);
+    }
+
+    #[test]
+    fn mean_square_loss() {
+        assert_eq!(MeanSquareLoss.loss(&sample(), 0.1), 1.25 / 3.);
+    }
+
+    #[test]
+    fn improvement_loss() {
+        // fully opaque lines remove all the error: (1 + 0.25 + 0) / 3
+        assert_eq!(ImprovementLoss.loss(&sample(), 1.), -1.25 / 3.);
+        assert_eq!(ImprovementLoss.loss(&sample(), 0.), 0.);
+        let white = LineSample {
+            sum: 2.,
+            sum_squares: 2.,
+            count: 2.,
+            covered_residuals: 0.,
+            masked: 0.,
+            dist: 1,
+        };
+        assert_eq!(ImprovementLoss.loss(&white, 0.5), 0.);
+        // a black pixel half covered by an opaque line: its residual goes from 1 to 0.25
+        let partial = LineSample {
+            sum: 0.,
+            sum_squares: 0.,
+            count: 0.5,
+            covered_residuals: 0.25,
+            masked: 0.,
+            dist: 1,
+        };
+        assert_eq!(ImprovementLoss.loss(&partial, 1.), -0.75 / 0.5);
+    }
+
+    #[test]
+    fn length_normalized_loss() {
+        assert_eq!(LengthNormalizedLoss.loss(&sample(), 0.1), 0.25);
+    }
+
+    #[test]
+    fn loss_from_name() {
+        for name in LOSS_NAMES {
+            assert!(from_name(name).is_ok());
+        }
+        assert!(from_name("unknown").is_err());
+    }
+}
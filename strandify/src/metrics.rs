@@ -0,0 +1,319 @@
+use std::error::Error;
+
+use image::{GrayImage, RgbImage};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::blueprint::Blueprint;
+use crate::peg::Yarn;
+use crate::utils;
+
+/// Standard deviation of the gaussian window of the [`ssim`].
+pub const SSIM_SIGMA: f64 = 1.5;
+
+/// Standard deviation of the gaussian blur of the [`Metrics::blurred_mse`], in pixels, see
+/// [`blurred_mse`] for other values.
+pub const BLUR_SIGMA: f64 = 2.;
+
+/// Upper bound of the [`psnr`] in dB, reached by identical images, whose PSNR is infinite and
+/// couldn't be written to json.
+pub const MAX_PSNR: f64 = 100.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// Quality [`Metrics`] of a render compared to its source image, both in grayscale.
+///
+/// The pixel values are normalized to [0, 1] before being compared.
+pub struct Metrics {
+    /// Mean squared error, lower is better, in [0, 1].
+    pub mse: f64,
+    /// Peak signal to noise ratio in dB, higher is better, at most [`MAX_PSNR`] which identical
+    /// images reach.
+    pub psnr: f64,
+    /// Mean structural similarity index, higher is better, at most 1.
+    pub ssim: f64,
+    /// Mean squared error of the images blurred with a gaussian of [`BLUR_SIGMA`], lower is
+    /// better, in [0, 1]. Approximates how the string art looks from a distance, where the
+    /// individual lines blend together.
+    pub blurred_mse: f64,
+}
+
+impl Metrics {
+    /// Compute the [`Metrics`] of a `render` compared to the `source` image.
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: The source image.
+    /// * `render`: The render, with the same dimensions as the `source`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use image::GrayImage;
+    /// use strandify::metrics::Metrics;
+    ///
+    /// let img = GrayImage::from_fn(16, 16, |x, y| image::Luma([(x * y) as u8]));
+    /// let metrics = Metrics::compare(&img, &img).unwrap();
+    /// assert_eq!(metrics.mse, 0.);
+    /// assert!((metrics.ssim - 1.).abs() < 1e-9);
+    /// ```
+    pub fn compare(source: &GrayImage, render: &GrayImage) -> Result<Self, Box<dyn Error>> {
+        if source.dimensions() != render.dimensions() {
+            return Err(format!(
+                "Render dimensions {:?} don't match the source image dimensions {:?}.",
+                render.dimensions(),
+                source.dimensions()
+            )
+            .into());
+        }
+        let (width, height) = source.dimensions();
+        let source = normalize(source);
+        let render = normalize(render);
+
+        let mse = mse(&source, &render);
+        Ok(Self {
+            mse,
+            psnr: psnr(mse),
+            ssim: ssim(&source, &render, width, height),
+            blurred_mse: blurred_mse(&source, &render, width, height, BLUR_SIGMA),
+        })
+    }
+
+    /// Compute the [`Metrics`] of a [`Blueprint`] compared to its `source` image. The
+    /// [`Blueprint`] is rendered at the resolution of the `source`, see [`render_grayscale`].
+    ///
+    /// # Arguments
+    ///
+    /// * `source`: The source image, with the same aspect ratio as the [`Blueprint`].
+    /// * `blueprint`: The [`Blueprint`] to score.
+    /// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`].
+    pub fn from_blueprint(
+        source: &GrayImage,
+        blueprint: &Blueprint,
+        yarn: &Yarn,
+    ) -> Result<Self, Box<dyn Error>> {
+        let render = render_grayscale(blueprint, yarn, source.width(), source.height())?;
+        Self::compare(source, &render)
+    }
+}
+
+/// Render a [`Blueprint`] in grayscale at the given resolution, ignoring its
+/// [`Blueprint::render_scale`]. Transparent backgrounds are rendered on white.
+///
+/// # Arguments
+///
+/// * `blueprint`: The [`Blueprint`] to render.
+/// * `yarn`: The [`Yarn`] to use to render the [`Blueprint`].
+/// * `width`: Width of the render, the height is scaled accordingly and must match `height`.
+/// * `height`: Height of the render.
+pub fn render_grayscale(
+    blueprint: &Blueprint,
+    yarn: &Yarn,
+    width: u32,
+    height: u32,
+) -> Result<GrayImage, Box<dyn Error>> {
+    let bp = Blueprint {
+        render_scale: width as f64 / blueprint.width as f64,
+        progress_bar: false,
+        ..blueprint.clone()
+    };
+    let (render_width, render_height) = bp.render_dimensions();
+    if (render_width, render_height) != (width, height) {
+        return Err(format!(
+            "Blueprint dimensions {}x{} don't match the aspect ratio of {width}x{height}.",
+            blueprint.width, blueprint.height
+        )
+        .into());
+    }
+
+    let render = bp.render_img(yarn)?;
+    // the render is premultiplied, composite it on white
+    let rgb = RgbImage::from_fn(width, height, |x, y| {
+        let [r, g, b, a] = render.get_pixel(x, y).0;
+        let background = 255 - a;
+        image::Rgb([r + background, g + background, b + background])
+    });
+    Ok(image::imageops::grayscale(&rgb))
+}
+
+/// Mean squared error between two images of the same dimensions, with normalized pixel values.
+pub fn mse(image_a: &[f64], image_b: &[f64]) -> f64 {
+    let sum: f64 = image_a
+        .iter()
+        .zip(image_b)
+        .map(|(a, b)| (a - b).powi(2))
+        .sum();
+    sum / image_a.len().max(1) as f64
+}
+
+/// Peak signal to noise ratio in dB from the [`mse`] of images with normalized pixel values,
+/// capped at [`MAX_PSNR`].
+pub fn psnr(mse: f64) -> f64 {
+    (-10. * mse.log10()).min(MAX_PSNR)
+}
+
+/// Mean structural similarity index between two images of the same dimensions, with normalized
+/// pixel values. The local statistics are computed over a gaussian window of [`SSIM_SIGMA`].
+pub fn ssim(image_a: &[f64], image_b: &[f64], width: u32, height: u32) -> f64 {
+    const C1: f64 = 0.01 * 0.01;
+    const C2: f64 = 0.03 * 0.03;
+
+    let blur = |image: &[f64]| gaussian_blur(image, width, height, SSIM_SIGMA);
+    let squared = |image: &[f64], other: &[f64]| -> Vec<f64> {
+        image.iter().zip(other).map(|(a, b)| a * b).collect()
+    };
+    let mean_a = blur(image_a);
+    let mean_b = blur(image_b);
+    let mean_aa = blur(&squared(image_a, image_a));
+    let mean_bb = blur(&squared(image_b, image_b));
+    let mean_ab = blur(&squared(image_a, image_b));
+
+    let sum: f64 = (0..mean_a.len())
+        .map(|i| {
+            let (mu_a, mu_b) = (mean_a[i], mean_b[i]);
+            let var_a = mean_aa[i] - mu_a * mu_a;
+            let var_b = mean_bb[i] - mu_b * mu_b;
+            let covar = mean_ab[i] - mu_a * mu_b;
+            ((2. * mu_a * mu_b + C1) * (2. * covar + C2))
+                / ((mu_a * mu_a + mu_b * mu_b + C1) * (var_a + var_b + C2))
+        })
+        .sum();
+    sum / mean_a.len().max(1) as f64
+}
+
+/// [`mse`] between two images of the same dimensions, with normalized pixel values, after
+/// blurring them with a gaussian of standard deviation `sigma`.
+pub fn blurred_mse(image_a: &[f64], image_b: &[f64], width: u32, height: u32, sigma: f64) -> f64 {
+    mse(
+        &gaussian_blur(image_a, width, height, sigma),
+        &gaussian_blur(image_b, width, height, sigma),
+    )
+}
+
+/// Convert an image to a buffer of pixel values normalized to [0, 1].
+fn normalize(image: &GrayImage) -> Vec<f64> {
+    image.as_raw().iter().map(|&v| v as f64 / 255.).collect()
+}
+
+/// Separable gaussian blur of standard deviation `sigma`, the edge pixels are repeated outside
+/// of the image.
+fn gaussian_blur(image: &[f64], width: u32, height: u32, sigma: f64) -> Vec<f64> {
+    let (width, height) = (width as usize, height as usize);
+    let radius = (3. * sigma).ceil() as isize;
+    let kernel: Vec<f64> = (-radius..=radius)
+        .map(|i| (-((i * i) as f64) / (2. * sigma * sigma)).exp())
+        .collect();
+    let kernel_sum: f64 = kernel.iter().sum();
+    let kernel: Vec<f64> = kernel.iter().map(|k| k / kernel_sum).collect();
+
+    let convolve = |get: &dyn Fn(usize) -> f64, len: usize, i: usize| -> f64 {
+        kernel
+            .iter()
+            .enumerate()
+            .map(|(k, weight)| {
+                let j = (i as isize + k as isize - radius).clamp(0, len as isize - 1);
+                weight * get(j as usize)
+            })
+            .sum()
+    };
+
+    let horizontal: Vec<Vec<f64>> = utils::iter_or_par_iter!(0..height, into)
+        .map(|y| {
+            let row = &image[y * width..(y + 1) * width];
+            (0..width)
+                .map(|x| convolve(&|j| row[j], width, x))
+                .collect()
+        })
+        .collect();
+    let vertical: Vec<Vec<f64>> = utils::iter_or_par_iter!(0..height, into)
+        .map(|y| {
+            (0..width)
+                .map(|x| convolve(&|j| horizontal[j][x], height, y))
+                .collect()
+        })
+        .collect();
+    vertical.concat()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::peg::Peg;
+
+    fn gradient(width: u32, height: u32) -> GrayImage {
+        GrayImage::from_fn(width, height, |x, y| {
+            image::Luma([((x + y) * 255 / (width + height - 2)) as u8])
+        })
+    }
+
+    #[test]
+    fn metrics_identical() {
+        let img = gradient(20, 10);
+        let metrics = Metrics::compare(&img, &img).unwrap();
+        assert_eq!(metrics.mse, 0.);
+        assert_eq!(metrics.psnr, MAX_PSNR);
+        assert!((metrics.ssim - 1.).abs() < 1e-12);
+        assert!(metrics.blurred_mse < 1e-24);
+        // the metrics of identical images round trip through json
+        let json = serde_json::to_string(&metrics).unwrap();
+        assert_eq!(serde_json::from_str::<Metrics>(&json).unwrap(), metrics);
+    }
+
+    #[test]
+    fn metrics_different() {
+        let black = GrayImage::new(8, 8);
+        let white = GrayImage::from_pixel(8, 8, image::Luma([255]));
+        let metrics = Metrics::compare(&black, &white).unwrap();
+        assert_eq!(metrics.mse, 1.);
+        assert_eq!(metrics.psnr, 0.);
+        assert!(metrics.ssim < 1e-3);
+        assert!((metrics.blurred_mse - 1.).abs() < 1e-12);
+
+        // closer images score better
+        let gray = GrayImage::from_pixel(8, 8, image::Luma([128]));
+        let closer = Metrics::compare(&black, &gray).unwrap();
+        assert!(closer.mse < metrics.mse);
+        assert!(closer.psnr > metrics.psnr);
+        assert!(closer.ssim > metrics.ssim);
+
+        assert!(Metrics::compare(&black, &GrayImage::new(8, 9)).is_err());
+    }
+
+    #[test]
+    fn blur_reduces_line_error() {
+        // a thin dark line on white compared to a uniform gray of the same mean
+        let line = GrayImage::from_fn(21, 21, |x, _| image::Luma([if x == 10 { 0 } else { 255 }]));
+        let gray = GrayImage::from_pixel(21, 21, image::Luma([243]));
+        let metrics = Metrics::compare(&gray, &line).unwrap();
+        assert!(metrics.blurred_mse < metrics.mse / 2.);
+    }
+
+    #[test]
+    fn gaussian_blur_constant() {
+        let img = vec![0.5; 30];
+        let blurred = gaussian_blur(&img, 6, 5, 1.5);
+        assert!(blurred.iter().all(|v| (v - 0.5).abs() < 1e-12));
+    }
+
+    #[test]
+    fn blueprint_metrics() {
+        let bp = Blueprint::new(
+            vec![Peg::new(0, 0), Peg::new(31, 0), Peg::new(31, 15)],
+            32,
+            16,
+            None,
+            3.,
+            false,
+        );
+        let yarn = Yarn::new(1., 1., (0, 0, 0));
+        // rendered at the source resolution, regardless of the render scale
+        let render = render_grayscale(&bp, &yarn, 64, 32).unwrap();
+        assert_eq!(render.dimensions(), (64, 32));
+        assert_eq!(render.get_pixel(32, 16).0, [255]);
+        assert!(render.get_pixel(32, 0).0[0] < 255);
+
+        let metrics = Metrics::from_blueprint(&render, &bp, &yarn).unwrap();
+        assert_eq!(metrics.mse, 0.);
+        assert!(bp.score(&GrayImage::new(64, 31), &yarn).is_err());
+    }
+}
@@ -1,25 +1,30 @@
 use log::warn;
 use log::{debug, info};
-use std::collections::HashMap;
 use std::error::Error;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
-use image::GrayImage;
-#[cfg(feature = "parallel")]
-use indicatif::ParallelProgressIterator;
-#[cfg(not(feature = "parallel"))]
-use indicatif::ProgressIterator;
-use itertools::Itertools;
+use image::{GrayImage, RgbImage};
+use indicatif::ProgressBar;
+use itertools::{Either, Itertools};
 #[cfg(feature = "parallel")]
 use rayon::prelude::*;
 #[cfg(feature = "parallel")]
-use rayon::ThreadPoolBuilder;
+use rayon::{ThreadPool, ThreadPoolBuilder};
 
-use crate::blueprint::Blueprint;
-use crate::line::Line;
-use crate::peg::{Peg, Yarn};
+use crate::blueprint::{Blueprint, Strand};
+use crate::line::{Line, LinePixels, LineRef};
+use crate::line_cache::{self, LineCache, ShardedLineCache};
+use crate::loss::{LineSample, LossFunction, MeanLoss};
+use crate::peg::{Peg, PegLayout, WrapDirection, Yarn};
 use crate::utils;
 
+/// Number of nodes whose lines are computed before being appended to the
+/// [`Pather::line_cache`], see [`Pather::populate_line_cache`].
+const LINE_CACHE_CHUNK: usize = 64;
+
 #[derive(Debug, Clone)]
 /// Pathing algorithm early stopping configuration.
 pub struct EarlyStopConfig {
@@ -36,6 +41,55 @@ impl Default for EarlyStopConfig {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+/// Token to cancel a running pathing algorithm, e.g. from another thread. Clones share the same
+/// cancellation state.
+///
+/// # Examples
+///
+/// ```
+/// use strandify::pather::CancellationToken;
+///
+/// let token = CancellationToken::new();
+/// let clone = token.clone();
+/// clone.cancel();
+/// assert!(token.is_cancelled());
+/// ```
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Creates a new [`CancellationToken`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the pathing algorithms using this [`CancellationToken`]. They stop before their
+    /// next iteration and return the [`Blueprint`] computed so far.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if [`CancellationToken::cancel`] was called.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// How the [`Pather`] gets the [`Lines`](Line) between the [`Pegs`](Peg).
+pub enum LineMode {
+    /// Rasterize all the lines up front, with [`Pather::populate_line_cache`]. Fastest, but the
+    /// memory usage grows with the square of the number of [`Pegs`](Peg).
+    #[default]
+    Eager,
+    /// Rasterize the lines on demand while pathing, which makes very large numbers of
+    /// [`Pegs`](Peg) feasible at the cost of speed.
+    Lazy {
+        /// Maximum number of lines to keep in a least recently used cache, 0 disables the cache.
+        cache_capacity: usize,
+    },
+}
+
 #[derive(Debug, Clone)]
 /// Pathing algorithm configuration.
 pub struct PatherConfig {
@@ -50,11 +104,56 @@ pub struct PatherConfig {
     pub start_peg_radius: u32,
     /// Don't connect [`Pegs`](Peg) within distance, in pixels.
     pub skip_peg_within: u32,
+    /// Don't draw lines passing within this distance, in pixels, of the edge of another
+    /// [`Peg`], e.g. the interior pegs of a [`shape::hex_grid`](crate::peg::shape::hex_grid)
+    /// the thread would catch on. The distance is measured from the line joining the
+    /// [`Peg`] centers. The [`Pegs`](Peg) this close to either end of the line are ignored, the
+    /// thread passes by them whichever way it leaves. [`None`] lets the lines cross the
+    /// [`Pegs`](Peg).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::pather::{Pather, PatherConfig};
+    /// use strandify::peg::Peg;
+    ///
+    /// let pegs = vec![Peg::new(10, 50), Peg::new(90, 50), Peg::new(50, 50), Peg::new(50, 10)];
+    /// let config = PatherConfig {
+    ///     iterations: 20,
+    ///     peg_clearance: Some(2.),
+    ///     ..Default::default()
+    /// };
+    /// let mut pather = Pather::new(image::GrayImage::new(100, 100), pegs, config);
+    /// let bp = pather.compute().unwrap();
+    /// // the thread never goes from the left peg to the right peg, over the middle one
+    /// assert!(bp.zip().all(|(a, b)| a.x.abs_diff(b.x) != 80));
+    /// ```
+    pub peg_clearance: Option<f32>,
     /// Beam search width, larger values will be lead to more accurate paths at the expense of
-    /// compute time.
+    /// compute time. [`Pather::compute_multi`] is greedy and requires a width of 1.
     pub beam_width: usize,
     /// Display progress bar.
     pub progress_bar: bool,
+    /// The [`LossFunction`] used to score the lines, see [`crate::loss`] for the built-in ones.
+    pub loss: Arc<dyn LossFunction>,
+    /// Never draw lines crossing pixels with a weight of 0 in the [`Pather::weight_mask`].
+    pub exclude_masked: bool,
+    /// Rasterize the lines with [anti-aliasing](Peg::line_to_aa), which honours fractional
+    /// [`Yarn::width`] values and matches the [`Blueprint`] renders more closely, instead of the
+    /// [Bresenham algorithm](Peg::line_to).
+    pub anti_aliasing: bool,
+    /// Whether to precompute the lines or to compute them on demand, see [`LineMode`].
+    pub line_mode: LineMode,
+    /// Model the thread wrapping around the [`Peg::radius`], clockwise or counter-clockwise.
+    /// The lines join the [tangent points](Peg::tangent_points) of the [`Pegs`](Peg) and the
+    /// pather also chooses the [`WrapDirection`] around each [`Peg`], doubling the number of
+    /// lines.
+    pub wrapping: bool,
+    /// Token to cancel the pathing, the [`Blueprint`] computed so far is returned.
+    pub cancellation_token: Option<CancellationToken>,
+    /// Wall-clock time budget of the pathing, the [`Blueprint`] computed so far is returned when
+    /// the budget runs out. Doesn't include populating the [`Pather::line_cache`].
+    pub time_limit: Option<Duration>,
 }
 
 impl PatherConfig {
@@ -74,8 +173,16 @@ impl PatherConfig {
             early_stop,
             start_peg_radius,
             skip_peg_within,
+            peg_clearance: None,
             progress_bar,
             beam_width,
+            loss: Arc::new(MeanLoss),
+            exclude_masked: false,
+            anti_aliasing: false,
+            line_mode: LineMode::default(),
+            wrapping: false,
+            cancellation_token: None,
+            time_limit: None,
         }
     }
 }
@@ -88,8 +195,16 @@ impl Default for PatherConfig {
             early_stop: EarlyStopConfig::default(),
             start_peg_radius: 5,
             skip_peg_within: 0,
+            peg_clearance: None,
             progress_bar: false,
             beam_width: 1,
+            loss: Arc::new(MeanLoss),
+            exclude_masked: false,
+            anti_aliasing: false,
+            line_mode: LineMode::default(),
+            wrapping: false,
+            cancellation_token: None,
+            time_limit: None,
         }
     }
 }
@@ -125,29 +240,84 @@ impl Ord for BeamState {
     }
 }
 
+/// A [`Line`] used by the [`Pather`], either borrowed from the [`Pather::line_cache`] or computed
+/// on demand.
+enum PatherLine<'a> {
+    Cached(LineRef<'a>),
+    Computed(Arc<Line>),
+}
+
+impl LinePixels for PatherLine<'_> {
+    fn pixels(&self) -> impl Iterator<Item = (u32, u32, f64)> + '_ {
+        match self {
+            PatherLine::Cached(line) => Either::Left(line.pixels()),
+            PatherLine::Computed(line) => Either::Right(line.pixels()),
+        }
+    }
+
+    fn dist(&self) -> u32 {
+        match self {
+            PatherLine::Cached(line) => line.dist(),
+            PatherLine::Computed(line) => line.dist(),
+        }
+    }
+
+    fn sample(&self, image: &GrayImage, weights: Option<&GrayImage>) -> LineSample {
+        match self {
+            PatherLine::Cached(line) => LinePixels::sample(line, image, weights),
+            PatherLine::Computed(line) => LinePixels::sample(line.as_ref(), image, weights),
+        }
+    }
+
+    fn draw(&self, image: &mut GrayImage, line_opacity: f64, line_color: f64) {
+        match self {
+            PatherLine::Cached(line) => LinePixels::draw(line, image, line_opacity, line_color),
+            PatherLine::Computed(line) => {
+                LinePixels::draw(line.as_ref(), image, line_opacity, line_color)
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 /// The line pathing algorithm.
 pub struct Pather {
     /// Input grayscale image.
     pub image: GrayImage,
-    /// Array of [`Pegs`](Peg) to use to compute the path.
-    pub pegs: Vec<Peg>,
+    /// [`PegLayout`] to use to compute the path, the [`Pegs`](Peg) of the path are referenced by
+    /// their index in the layout.
+    pub pegs: PegLayout,
     /// Pathing algorithm configuration.
     pub config: PatherConfig,
     /// Holds the pixel coords of all the lines, run [Pather::populate_line_cache] to populate the
-    /// cache.
-    pub line_cache: HashMap<(usize, usize), Line>,
+    /// cache. Shared with the [`Pathers`](Pather) created with [`Pather::with_config`].
+    pub line_cache: Arc<LineCache>,
+    /// Optional grayscale weight map, with the same dimensions as [`Pather::image`]. Bright pixels
+    /// are important and attract lines, dark pixels are less important, pixels with a weight of 0
+    /// are treated as not requiring any yarn, see [`PatherConfig::exclude_masked`] to forbid lines
+    /// from crossing them.
+    pub weight_mask: Option<GrayImage>,
+    /// Recently used lines, when computing the lines on demand.
+    lazy_lines: ShardedLineCache,
+    /// Whether the line between each pair of [`Pegs`](Peg) respects the
+    /// [`PatherConfig::peg_clearance`], indexed by [`line_cache::pair_index`]. Computed on first
+    /// use, and again when the [`Pather::line_cache`] is populated.
+    clear_lines: OnceLock<Vec<bool>>,
 }
 
 impl Pather {
-    /// Creates a new [`Pather`].
-    pub fn new(img: GrayImage, pegs: Vec<Peg>, config: PatherConfig) -> Self {
-        let line_cache = HashMap::new();
+    /// Creates a new [`Pather`], `pegs` is either a [`PegLayout`] or [`Pegs`](Peg) whose ids are
+    /// replaced by their index.
+    pub fn new(img: GrayImage, pegs: impl Into<PegLayout>, config: PatherConfig) -> Self {
+        let line_cache = Arc::default();
         Self {
             image: img,
-            pegs,
+            pegs: pegs.into(),
             config,
             line_cache,
+            weight_mask: None,
+            lazy_lines: ShardedLineCache::default(),
+            clear_lines: OnceLock::new(),
         }
     }
 
@@ -158,49 +328,423 @@ impl Pather {
     /// This function will return an error if [`image::open`] fails to open the image file.
     pub fn from_image_file(
         image_path: PathBuf,
-        pegs: Vec<Peg>,
+        pegs: impl Into<PegLayout>,
         config: PatherConfig,
     ) -> Result<Self, Box<dyn Error>> {
         let img = image::open(image_path)?.into_luma8();
         Ok(Self::new(img, pegs, config))
     }
 
+    /// Create a [`Pather`] with the same image, [`Pegs`](Peg) and [`Pather::weight_mask`], and a
+    /// different `config`. The [`Pather::line_cache`] is shared when the line parameters of both
+    /// configs match, see [`Pather::line_cache_key`], e.g. to path with different
+    /// [`Yarn::opacity`] or [`PatherConfig::iterations`] without recomputing the lines.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::pather::{Pather, PatherConfig};
+    /// use strandify::peg::shape;
+    ///
+    /// let image = image::GrayImage::from_pixel(100, 100, image::Luma([128]));
+    /// let pegs = shape::circle((50, 50), 45, 20);
+    /// let mut pather = Pather::new(image, pegs, PatherConfig::default());
+    /// pather.populate_line_cache().unwrap();
+    ///
+    /// let mut config = pather.config.clone();
+    /// config.iterations = 10;
+    /// config.yarn.opacity = 0.5;
+    /// let other = pather.with_config(config);
+    /// assert!(std::sync::Arc::ptr_eq(&pather.line_cache, &other.line_cache));
+    /// ```
+    pub fn with_config(&self, config: PatherConfig) -> Self {
+        let mut pather = Self {
+            image: self.image.clone(),
+            pegs: self.pegs.clone(),
+            config,
+            line_cache: Arc::default(),
+            weight_mask: self.weight_mask.clone(),
+            lazy_lines: ShardedLineCache::default(),
+            clear_lines: OnceLock::new(),
+        };
+        if pather.line_cache_key() == self.line_cache_key() {
+            pather.line_cache = self.line_cache.clone();
+        }
+        pather
+    }
+
+    /// Number of sides of each [`Peg`] the thread can wrap around, 2 when
+    /// [`PatherConfig::wrapping`].
+    fn sides(&self) -> usize {
+        if self.config.wrapping {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Number of nodes of the path, the [`Peg`] and [`WrapDirection`] combinations.
+    fn n_nodes(&self) -> usize {
+        self.pegs.len() * self.sides()
+    }
+
+    /// Index of the node of a [`Peg`] index and [`WrapDirection`].
+    fn node(&self, peg: usize, direction: WrapDirection) -> usize {
+        match (self.config.wrapping, direction) {
+            (true, WrapDirection::Ccw) => 2 * peg + 1,
+            (true, WrapDirection::Cw) => 2 * peg,
+            (false, _) => peg,
+        }
+    }
+
+    /// Index of the [`Peg`] of a node.
+    fn node_peg(&self, node: usize) -> usize {
+        node / self.sides()
+    }
+
+    /// [`WrapDirection`] of a node.
+    fn node_direction(&self, node: usize) -> WrapDirection {
+        if self.config.wrapping && node % 2 == 1 {
+            WrapDirection::Ccw
+        } else {
+            WrapDirection::Cw
+        }
+    }
+
+    /// The [`Pegs`](Peg) of a node order.
+    fn node_pegs(&self, nodes: &[usize]) -> Vec<Peg> {
+        nodes
+            .iter()
+            .map(|&node| self.pegs[self.node_peg(node)])
+            .collect()
+    }
+
+    /// The [`WrapDirections`](WrapDirection) of a node order, empty unless
+    /// [`PatherConfig::wrapping`].
+    fn node_wraps(&self, nodes: &[usize]) -> Vec<WrapDirection> {
+        if !self.config.wrapping {
+            return vec![];
+        }
+        nodes
+            .iter()
+            .map(|&node| self.node_direction(node))
+            .collect()
+    }
+
+    /// Rasterize the [`Line`] of the thread going from the [`Peg`] of node `node_a` to the
+    /// [`Peg`] of node `node_b`, according to the [`PatherConfig`].
+    fn compute_line(&self, node_a: usize, node_b: usize) -> Line {
+        let min_max = Some((0, self.image.width() - 1, 0, self.image.height() - 1));
+        let (peg_a, peg_b) = (
+            &self.pegs[self.node_peg(node_a)],
+            &self.pegs[self.node_peg(node_b)],
+        );
+        if !self.config.wrapping {
+            return if self.config.anti_aliasing {
+                peg_a.line_to_aa(peg_b, self.config.yarn.width, min_max)
+            } else {
+                peg_a.line_to(peg_b, self.config.yarn.width.round() as u32, min_max)
+            };
+        }
+
+        let (direction_a, direction_b) = (self.node_direction(node_a), self.node_direction(node_b));
+        if self.config.anti_aliasing {
+            peg_a.tangent_line_to(
+                direction_a,
+                peg_b,
+                direction_b,
+                self.config.yarn.width,
+                min_max,
+            )
+        } else {
+            // snap the tangent points to the pixel grid
+            let (start, end) = peg_a.tangent_points(direction_a, peg_b, direction_b);
+            let snap = |peg: &Peg, (x, y): (f64, f64)| Peg {
+                x: x.round().max(0.) as u32,
+                y: y.round().max(0.) as u32,
+                ..*peg
+            };
+            snap(peg_a, start).line_to(
+                &snap(peg_b, end),
+                self.config.yarn.width.round() as u32,
+                min_max,
+            )
+        }
+    }
+
+    /// Key of the [`Pather::line_cache`] entry of the thread arriving at node `node`. Lines are
+    /// cached between the departure and arrival nodes, and the thread going from peg A wrapped
+    /// clockwise to peg B wrapped clockwise is the thread going from peg B wrapped
+    /// counter-clockwise to peg A wrapped counter-clockwise.
+    fn arrival_key(&self, node: usize) -> usize {
+        if self.config.wrapping {
+            node ^ 1
+        } else {
+            node
+        }
+    }
+
     /// Populate the [Pather::line_cache] with the pixel coords of all the lines between the [`Peg`] pairs.
     pub fn populate_line_cache(&mut self) -> Result<(), Box<dyn Error>> {
         info!("Populating line cache");
+        self.clear_lines = OnceLock::new();
+        if let Some(clearance) = self.config.peg_clearance {
+            self.clear_lines(clearance);
+        }
 
-        let peg_combinations = self
-            .pegs
-            .iter()
-            .tuple_combinations()
-            .filter(|(peg_a, peg_b)| peg_a.dist_to(peg_b) >= self.config.skip_peg_within)
-            .collect_vec();
-
-        let pbar = utils::pbar(peg_combinations.len() as u64, !self.config.progress_bar)?
+        let n_nodes = self.n_nodes();
+        let pbar = utils::pbar(n_nodes as u64, !self.config.progress_bar)?
             .with_message("Populating line cache");
 
-        let key_line_pixels = utils::iter_or_par_iter!(peg_combinations)
-            .progress_with(pbar)
-            .map(|(peg_a, peg_b)| {
-                (
-                    utils::hash_key(peg_a, peg_b),
-                    peg_a.line_to(
-                        peg_b,
-                        self.config.yarn.width.round() as u32,
-                        Some((0, self.image.width() - 1, 0, self.image.height() - 1)),
-                    ),
-                )
-            })
-            .collect::<Vec<((usize, usize), Line)>>();
+        // each node's lines are packed separately, to avoid holding all the lines in memory, and
+        // appended chunk by chunk, to only hold a chunk of rows besides the line cache
+        let mut line_cache = LineCache::new(n_nodes, self.image.width(), self.config.anti_aliasing);
+        for chunk_start in (0..n_nodes).step_by(LINE_CACHE_CHUNK) {
+            let chunk = chunk_start..(chunk_start + LINE_CACHE_CHUNK).min(n_nodes);
+            let rows = utils::iter_or_par_iter!(chunk, into)
+                .map(|node_a| {
+                    let mut row =
+                        LineCache::new(n_nodes, self.image.width(), self.config.anti_aliasing);
+                    for key_b in node_a + 1..n_nodes {
+                        let line = self
+                            .line_allowed(self.node_peg(node_a), self.node_peg(key_b))
+                            .then(|| self.compute_line(node_a, self.arrival_key(key_b)));
+                        row.push(line.as_ref());
+                    }
+                    pbar.inc(1);
+                    row
+                })
+                .collect::<Vec<_>>();
+            for row in rows {
+                line_cache.append(row);
+            }
+        }
+        pbar.finish();
+        line_cache.shrink_to_fit();
+        self.line_cache = Arc::new(line_cache);
+        debug!("# line cache entries: {}", self.line_cache.len());
+        debug!(
+            "line cache memory usage: {:.1} MB",
+            self.line_cache.memory_usage() as f64 / 1e6
+        );
+        Ok(())
+    }
+
+    /// Key identifying the lines computed by this [`Pather`], derived from the [`Peg`] positions,
+    /// and radii when they matter, the image dimensions and the line parameters of the
+    /// [`PatherConfig`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::pather::{Pather, PatherConfig};
+    /// use strandify::peg::shape;
+    ///
+    /// let image = image::GrayImage::new(100, 100);
+    /// let config = PatherConfig {
+    ///     peg_clearance: Some(2.),
+    ///     ..Default::default()
+    /// };
+    /// let pather = Pather::new(image.clone(), shape::circle((50, 50), 45, 20), config.clone());
+    /// let pegs = shape::circle((50, 50), 45, 20)
+    ///     .into_iter()
+    ///     .map(|peg| peg.with_radius(3.))
+    ///     .collect::<Vec<_>>();
+    /// let other = Pather::new(image, pegs, config);
+    /// assert_ne!(pather.line_cache_key(), other.line_cache_key());
+    /// ```
+    pub fn line_cache_key(&self) -> u64 {
+        let mut bytes = vec![];
+        bytes.extend(self.image.width().to_le_bytes());
+        bytes.extend(self.image.height().to_le_bytes());
+        bytes.extend(self.config.yarn.width.to_bits().to_le_bytes());
+        bytes.push(self.config.anti_aliasing as u8);
+        bytes.extend(self.config.skip_peg_within.to_le_bytes());
+        match self.config.peg_clearance {
+            Some(clearance) => {
+                bytes.push(1);
+                bytes.extend(clearance.to_bits().to_le_bytes());
+            }
+            None => bytes.push(0),
+        }
+        bytes.push(self.config.wrapping as u8);
+        for peg in &self.pegs {
+            bytes.extend(peg.x.to_le_bytes());
+            bytes.extend(peg.y.to_le_bytes());
+        }
+        // the radii shape the wrapped lines and widen the peg clearance
+        if self.config.wrapping || self.config.peg_clearance.is_some() {
+            for peg in &self.pegs {
+                bytes.extend(peg.radius.unwrap_or(0.).to_bits().to_le_bytes());
+            }
+        }
+        utils::fnv1a_hash(&bytes)
+    }
 
-        for (key, line) in key_line_pixels {
-            self.line_cache.insert(key, line);
+    /// Save the [`Pather::line_cache`] to a binary file, to be reused with
+    /// [`Pather::load_line_cache`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the [`Pather::line_cache`] is empty or if the file
+    /// can't be written.
+    pub fn save_line_cache<P: AsRef<Path>>(&self, file_path: P) -> Result<(), Box<dyn Error>> {
+        if self.line_cache.is_empty() {
+            return Err("Line cache is empty, run 'populate_line_cache'.".into());
         }
+        info!("Saving line cache to {:?}", file_path.as_ref());
+        self.line_cache.to_file(file_path, self.line_cache_key())
+    }
+
+    /// Load the [`Pather::line_cache`] from a file written by [`Pather::save_line_cache`].
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if the file can't be read, or if it was saved for
+    /// different [`Pegs`](Peg), image dimensions or line parameters, see
+    /// [`Pather::line_cache_key`].
+    pub fn load_line_cache<P: AsRef<Path>>(&mut self, file_path: P) -> Result<(), Box<dyn Error>> {
+        info!("Loading line cache from {:?}", file_path.as_ref());
+        self.line_cache = Arc::new(LineCache::from_file(
+            file_path,
+            self.line_cache_key(),
+            self.n_nodes(),
+            self.image.dimensions(),
+        )?);
         debug!("# line cache entries: {}", self.line_cache.len());
         Ok(())
     }
 
-    /// Get starting peg by taking the [`Peg`] located on the darkest pixel.
+    /// Check that the lines are available, either in the [`Pather::line_cache`] or computed on
+    /// demand, in which case the lines respecting the [`PatherConfig::peg_clearance`] are
+    /// determined up front.
+    fn check_line_cache(&self) -> Result<(), Box<dyn Error>> {
+        if self.config.line_mode == LineMode::Eager && self.line_cache.is_empty() {
+            return Err("Line cache is empty, run 'populate_line_cache'.".into());
+        }
+        if let (true, Some(clearance)) = (self.line_cache.is_empty(), self.config.peg_clearance) {
+            self.clear_lines(clearance);
+        }
+        Ok(())
+    }
+
+    /// Get the [`Line`] of the thread going from node `node_a` to node `node_b`, see
+    /// [`Pather::node`], from the [`Pather::line_cache`] when populated, otherwise computed on
+    /// demand in [`LineMode::Lazy`].
+    fn get_line(&self, node_a: usize, node_b: usize) -> Option<PatherLine<'_>> {
+        let key_b = self.arrival_key(node_b);
+        if !self.line_cache.is_empty() {
+            return self.line_cache.get(node_a, key_b).map(PatherLine::Cached);
+        }
+        let LineMode::Lazy { cache_capacity } = self.config.line_mode else {
+            return None;
+        };
+        let (peg_a, peg_b) = (self.node_peg(node_a), self.node_peg(node_b));
+        if peg_a.max(peg_b) >= self.pegs.len() || !self.line_allowed(peg_a, peg_b) {
+            return None;
+        }
+        // rasterize the line the way round the line cache stores it, its pixels depend on the
+        // direction it's drawn in
+        let (from, to) = if node_a < key_b {
+            (node_a, node_b)
+        } else {
+            (key_b, self.arrival_key(node_a))
+        };
+        if cache_capacity == 0 {
+            return Some(PatherLine::Computed(Arc::new(self.compute_line(from, to))));
+        }
+
+        let key = line_cache::pair_index(node_a, key_b, self.n_nodes());
+        if let Some(line) = self.lazy_lines.get(key) {
+            return Some(PatherLine::Computed(line));
+        }
+        // rasterize without holding the lock of the shard
+        let line = Arc::new(self.compute_line(from, to));
+        self.lazy_lines.insert(key, line.clone(), cache_capacity);
+        Some(PatherLine::Computed(line))
+    }
+
+    /// Whether the [`Pegs`](Peg) at index `peg_a` and `peg_b` can be connected, they must be
+    /// distinct, further apart than [`PatherConfig::skip_peg_within`] and the line joining them
+    /// must respect the [`PatherConfig::peg_clearance`].
+    fn line_allowed(&self, peg_a: usize, peg_b: usize) -> bool {
+        if peg_a == peg_b
+            || self.pegs[peg_a].dist_to(&self.pegs[peg_b]) < self.config.skip_peg_within
+        {
+            return false;
+        }
+        let Some(clearance) = self.config.peg_clearance else {
+            return true;
+        };
+        self.clear_lines(clearance)[line_cache::pair_index(peg_a, peg_b, self.pegs.len())]
+    }
+
+    /// The [`Pather::clear_lines`] for `clearance`, computed if needed. Called before pathing,
+    /// outside of the parallel iterators, as a thread computing them in parallel could pick up
+    /// another line check waiting for them.
+    fn clear_lines(&self, clearance: f32) -> &[bool] {
+        let n_pegs = self.pegs.len();
+        self.clear_lines.get_or_init(|| {
+            utils::iter_or_par_iter!(0..n_pegs, into)
+                .map(|peg_a| {
+                    (peg_a + 1..n_pegs)
+                        .map(|peg_b| self.line_clear(peg_a, peg_b, clearance))
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+                .concat()
+        })
+    }
+
+    /// Whether the line joining the [`Pegs`](Peg) at index `peg_a` and `peg_b` passes further
+    /// than `clearance` from the other [`Pegs`](Peg), see [`PatherConfig::peg_clearance`].
+    fn line_clear(&self, peg_a: usize, peg_b: usize, clearance: f32) -> bool {
+        let (a, b) = (&self.pegs[peg_a], &self.pegs[peg_b]);
+        let (ax, ay) = (a.x as f64, a.y as f64);
+        let (dx, dy) = (b.x as f64 - ax, b.y as f64 - ay);
+        let length_sq = dx * dx + dy * dy;
+        !self.pegs.iter().enumerate().any(|(index, peg)| {
+            if index == peg_a || index == peg_b || length_sq == 0. {
+                return false;
+            }
+            let min_dist = (clearance + peg.radius.unwrap_or(0.)) as f64;
+            // the thread passes near the pegs around its ends whichever way it goes, only the
+            // pegs it crosses on the way count
+            let near = |end: &Peg| {
+                let reach = min_dist + end.radius.unwrap_or(0.) as f64;
+                (peg.x as f64 - end.x as f64).hypot(peg.y as f64 - end.y as f64) < reach
+            };
+            if near(a) || near(b) {
+                return false;
+            }
+            let (px, py) = (peg.x as f64 - ax, peg.y as f64 - ay);
+            let t = (px * dx + py * dy) / length_sq;
+            0. < t && t < 1. && (px - t * dx).hypot(py - t * dy) < min_dist
+        })
+    }
+
+    /// Check that the [`Pather::weight_mask`] matches the [`Pather::image`].
+    fn check_weight_mask(&self) -> Result<(), Box<dyn Error>> {
+        match &self.weight_mask {
+            Some(mask) if mask.dimensions() != self.image.dimensions() => {
+                Err("Weight mask and image dimensions differ.".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Compute the loss of a [`Line`] over the `image`, returns [`None`] if the [`Line`] is
+    /// excluded by the [`Pather::weight_mask`].
+    fn line_loss(&self, line: &impl LinePixels, image: &GrayImage) -> Option<f64> {
+        let sample = line.sample(image, self.weight_mask.as_ref());
+        if self.config.exclude_masked && sample.masked > 0. {
+            return None;
+        }
+        Some(self.config.loss.loss(&sample, self.config.yarn.opacity))
+    }
+
+    /// Get starting peg by taking the [`Peg`] located on the darkest pixel, taking into account the
+    /// [`Pather::weight_mask`].
     fn get_start_peg(&self, radius: u32) -> usize {
         let peg_avgs: Vec<u32> = self
             .pegs
@@ -212,7 +756,14 @@ impl Pather {
                     .into_iter()
                     .zip(y_coords)
                     .map(|(x, y)| match self.image.get_pixel_checked(x, y) {
-                        Some(pixel) => pixel[0],
+                        Some(pixel) => match &self.weight_mask {
+                            // unimportant pixels are treated as white
+                            Some(mask) => {
+                                let weight = mask.get_pixel(x, y)[0] as f64 / 255.;
+                                (weight * pixel[0] as f64 + (1. - weight) * 255.).round() as u8
+                            }
+                            None => pixel[0],
+                        },
                         None => 0,
                     })
                     .collect();
@@ -224,6 +775,25 @@ impl Pather {
         peg_avgs.iter().position_min().unwrap_or(0)
     }
 
+    /// Returns true if the pathing should stop before iteration `iter_i`, because the
+    /// [`PatherConfig::cancellation_token`] was cancelled or the `deadline`, derived from the
+    /// [`PatherConfig::time_limit`], has passed.
+    fn interrupted(&self, deadline: Option<Instant>, iter_i: usize) -> bool {
+        if let Some(token) = &self.config.cancellation_token {
+            if token.is_cancelled() {
+                info!("Pathing cancelled at iteration {iter_i}");
+                return true;
+            }
+        }
+        if let Some(deadline) = deadline {
+            if Instant::now() >= deadline {
+                info!("Time limit reached at iteration {iter_i}");
+                return true;
+            }
+        }
+        false
+    }
+
     fn early_stop(&self, count: &mut u32, loss: f64) -> bool {
         match self.config.early_stop.loss_threshold {
             Some(early_stop_count) => {
@@ -243,212 +813,782 @@ impl Pather {
         }
     }
 
+    /// Map the [`Pegs`](Peg) of a peg order and their [`WrapDirections`](WrapDirection),
+    /// clockwise when missing, to their nodes, see [`Pather::node`]. The [`Pegs`](Peg) are
+    /// matched to the [`Pather::pegs`] by id, or by coordinates, see [`PegLayout::find`].
+    fn node_indices(
+        &self,
+        peg_order: &[Peg],
+        wraps: &[WrapDirection],
+    ) -> Result<Vec<usize>, Box<dyn Error>> {
+        peg_order
+            .iter()
+            .enumerate()
+            .map(|(index, peg)| {
+                let peg_i = self.pegs.find(peg).map(|peg| peg.id).ok_or_else(|| {
+                    format!(
+                        "Peg at ({}, {}) is not one of the pather's pegs.",
+                        peg.x, peg.y
+                    )
+                })?;
+                Ok(self.node(peg_i, wraps.get(index).copied().unwrap_or_default()))
+            })
+            .collect()
+    }
+
+    /// Get the node order, see [`Pather::node`], and the work image to start pathing from. When
+    /// `peg_order` is empty, starts from the [starting peg](Pather::get_start_peg), otherwise the
+    /// lines of `peg_order`, wrapped in the `wraps` directions, are replayed onto the work image.
+    fn initial_state(
+        &self,
+        peg_order: &[Peg],
+        wraps: &[WrapDirection],
+    ) -> Result<(Vec<usize>, GrayImage), Box<dyn Error>> {
+        let mut work_img = self.image.clone();
+        if peg_order.is_empty() {
+            let start_peg = self.get_start_peg(self.config.start_peg_radius);
+            debug!("Starting peg: {:?}", self.pegs[start_peg]);
+            return Ok((vec![self.node(start_peg, WrapDirection::Cw)], work_img));
+        }
+
+        let peg_order = self.node_indices(peg_order, wraps)?;
+        info!("Replaying {} lines", peg_order.len() - 1);
+        let line_color = 255. * self.config.yarn.opacity;
+        for (&peg_a, &peg_b) in peg_order.iter().tuple_windows() {
+            match self.get_line(peg_a, peg_b) {
+                Some(line) => line.draw(&mut work_img, self.config.yarn.opacity, line_color),
+                // the line might not be in the cache, e.g. with a different skip_peg_within
+                None if self.node_peg(peg_a) != self.node_peg(peg_b) => self
+                    .compute_line(peg_a, peg_b)
+                    .draw(&mut work_img, self.config.yarn.opacity, line_color),
+                None => {}
+            }
+        }
+        Ok((peg_order, work_img))
+    }
+
+    /// Create a [`PatherSteps`] iterator running the pathing algorithm one line at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `peg_order`: The [`Pegs`](Peg) of a previous path to continue from, see
+    ///   [`Pather::compute_greedy_from`]. Starts from scratch when empty.
+    /// * `wraps`: The [`WrapDirections`](WrapDirection) of `peg_order`, clockwise when missing.
+    /// * `beam_width`: Beam search width, 1 uses the greedy algorithm.
+    fn path_steps(
+        &self,
+        peg_order: &[Peg],
+        wraps: &[WrapDirection],
+        beam_width: usize,
+    ) -> Result<PatherSteps<'_>, Box<dyn Error>> {
+        self.check_line_cache()?;
+        self.check_weight_mask()?;
+        if beam_width == 0 {
+            return Err("Beam width should be at least 1.".into());
+        }
+        let (peg_order, work_img) = self.initial_state(peg_order, wraps)?;
+
+        let pbar = utils::pbar(self.config.iterations as u64, !self.config.progress_bar)?
+            .with_message("Computing blueprint");
+
+        Ok(PatherSteps {
+            pather: self,
+            beam_width,
+            beam: vec![BeamState {
+                peg_order,
+                loss: 0.,
+                image: work_img,
+            }],
+            iteration: 0,
+            early_stop_count: 0,
+            done: false,
+            deadline: self.config.time_limit.map(|limit| Instant::now() + limit),
+            pbar,
+            // use a ThreadPool to reduce overhead
+            #[cfg(feature = "parallel")]
+            pool: Arc::new(ThreadPoolBuilder::new().build()?),
+        })
+    }
+
+    /// Run the pathing algorithm one line at a time, with the [greedy](Pather::compute_greedy)
+    /// algorithm when [`PatherConfig::beam_width`] equals 1 and the
+    /// [beam search](Pather::compute_beam) algorithm otherwise.
+    ///
+    /// The [`Pather::line_cache`] should be populated, unless using [`LineMode::Lazy`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::pather::{Pather, PatherConfig};
+    /// use strandify::peg::shape;
+    ///
+    /// let image = image::GrayImage::from_pixel(100, 100, image::Luma([128]));
+    /// let pegs = shape::circle((50, 50), 45, 20);
+    /// let config = PatherConfig {
+    ///     iterations: 10,
+    ///     ..Default::default()
+    /// };
+    /// let mut pather = Pather::new(image, pegs, config);
+    /// pather.populate_line_cache().unwrap();
+    ///
+    /// let mut steps = pather.steps().unwrap();
+    /// for step in steps.by_ref().take(5) {
+    ///     println!("{:?} -> {:?}: {}", step.from, step.peg, step.loss);
+    /// }
+    /// // the work image holds the lines drawn so far
+    /// let _work_image = steps.work_image();
+    /// let blueprint = steps.into_blueprint();
+    /// assert_eq!(blueprint.peg_order.len(), 6);
+    /// ```
+    pub fn steps(&self) -> Result<PatherSteps<'_>, Box<dyn Error>> {
+        self.steps_from(&[])
+    }
+
+    /// Run the pathing algorithm one line at a time, continuing from an existing `peg_order`,
+    /// see [`Pather::steps`] and [`Pather::compute_greedy_from`].
+    pub fn steps_from(&self, peg_order: &[Peg]) -> Result<PatherSteps<'_>, Box<dyn Error>> {
+        self.path_steps(peg_order, &[], self.config.beam_width)
+    }
+
     /// Run a greedy line pathing algorithm and construct a [`Blueprint`].
     pub fn compute_greedy(&self) -> Result<Blueprint, Box<dyn Error>> {
-        if self.line_cache.is_empty() {
-            return Err("Line cache is empty, run 'populate_line_cache'.".into());
-        };
+        self.compute_greedy_from(&[])
+    }
+
+    /// Run the greedy line pathing algorithm, continuing from an existing `peg_order`, for
+    /// [`PatherConfig::iterations`] more lines. The lines of `peg_order` are drawn onto the work
+    /// image before pathing resumes from its last [`Peg`].
+    ///
+    /// # Arguments
+    ///
+    /// * `peg_order`: The [`Pegs`](Peg) of a previous path, e.g. [`Blueprint::peg_order`], which
+    ///   should be among the [`Pather::pegs`]. Starts from scratch when empty.
+    ///
+    /// # Returns
+    ///
+    /// * [`Blueprint`]: The [`Blueprint`] holding `peg_order` followed by the new [`Pegs`](Peg).
+    pub fn compute_greedy_from(&self, peg_order: &[Peg]) -> Result<Blueprint, Box<dyn Error>> {
+        let mut steps = self.path_steps(peg_order, &[], 1)?;
+        steps.by_ref().for_each(drop);
+        Ok(steps.into_blueprint())
+    }
+
+    /// Run a beam search based line pathing algorithm and construct a [`Blueprint`].
+    pub fn compute_beam(&self) -> Result<Blueprint, Box<dyn Error>> {
+        self.compute_beam_from(&[])
+    }
+
+    /// Run the beam search based line pathing algorithm, continuing from an existing
+    /// `peg_order`, see [`Pather::compute_greedy_from`].
+    pub fn compute_beam_from(&self, peg_order: &[Peg]) -> Result<Blueprint, Box<dyn Error>> {
+        let mut steps = self.path_steps(peg_order, &[], self.config.beam_width)?;
+        steps.by_ref().for_each(drop);
+        Ok(steps.into_blueprint())
+    }
+
+    /// Get the starting [`Peg`] of each [`Yarn`] of the `palette` by taking the [`Peg`] located
+    /// on the pixels closest to the [`Yarn::color`].
+    fn get_start_pegs_multi(&self, image: &RgbImage, palette: &[Yarn], radius: u32) -> Vec<usize> {
+        palette
+            .iter()
+            .map(|yarn| {
+                let peg_dists: Vec<f64> = self
+                    .pegs
+                    .iter()
+                    .map(|peg| {
+                        let (x_coords, y_coords) = peg.around(radius);
+                        let dists: Vec<f64> = x_coords
+                            .into_iter()
+                            .zip(y_coords)
+                            .filter_map(|(x, y)| image.get_pixel_checked(x, y))
+                            .map(|pixel| utils::color_dist(pixel.0, yarn.color))
+                            .collect();
+                        dists.iter().sum::<f64>() / dists.len().max(1) as f64
+                    })
+                    .collect();
+                peg_dists
+                    .iter()
+                    .position_min_by(|a, b| a.total_cmp(b))
+                    .unwrap_or(0)
+            })
+            .collect()
+    }
+
+    /// Run a greedy multi [`Yarn`] line pathing algorithm over an RGB image and construct a
+    /// [`Blueprint`] holding one [`Strand`] per [`Yarn`] of the `palette`.
+    ///
+    /// The lines are laid on a simulated white canvas. At each iteration, every [`Yarn`]
+    /// proposes its best line starting from its last [`Peg`], and the line which most reduces the
+    /// color distance between the canvas and `image` is laid. The line losses are the mean change
+    /// in color distance along the line, negative values are improvements.
+    ///
+    /// The [`Yarn::color`] and [`Yarn::opacity`] of the `palette` are used to simulate the
+    /// lines, the line width is taken from [`PatherConfig::yarn`].
+    ///
+    /// # Arguments
+    ///
+    /// * `image`: The RGB image, should have the same dimensions as [`Pather::image`].
+    /// * `palette`: The [`Yarns`](Yarn) to use, one [`Strand`] is created for each.
+    pub fn compute_multi(
+        &self,
+        image: &RgbImage,
+        palette: &[Yarn],
+    ) -> Result<Blueprint, Box<dyn Error>> {
+        self.check_line_cache()?;
+        if palette.is_empty() {
+            return Err("Palette is empty.".into());
+        }
+        if self.config.beam_width > 1 {
+            return Err("Beam search is not supported with a palette.".into());
+        }
+        if image.dimensions() != self.image.dimensions() {
+            return Err("RGB image and pather image dimensions differ.".into());
+        }
+        self.check_weight_mask()?;
 
         let pbar = utils::pbar(self.config.iterations as u64, !self.config.progress_bar)?
             .with_message("Computing blueprint");
 
-        let line_color = 255. * self.config.yarn.opacity;
-
+        let deadline = self.config.time_limit.map(|limit| Instant::now() + limit);
+        // whether each line crosses masked pixels, indexed like the lazy lines and determined the
+        // first time the line is considered, the color losses don't sample the weight mask
+        let n_nodes = self.n_nodes();
+        let masked_lines: Vec<OnceLock<bool>> = if self.config.exclude_masked {
+            (0..n_nodes * n_nodes.saturating_sub(1) / 2)
+                .map(|_| OnceLock::new())
+                .collect()
+        } else {
+            Vec::new()
+        };
         let compute = || {
-            let start_peg = &self.pegs[self.get_start_peg(self.config.start_peg_radius)];
-            debug!("Starting peg: {start_peg:?}");
-            let mut peg_order = vec![start_peg];
-            let mut work_img = self.image.clone();
-            let mut last_peg = start_peg;
-            let mut last_last_peg = last_peg;
+            let mut peg_orders: Vec<Vec<usize>> = self
+                .get_start_pegs_multi(image, palette, self.config.start_peg_radius)
+                .into_iter()
+                .map(|peg| vec![self.node(peg, WrapDirection::Cw)])
+                .collect();
+            debug!("Starting pegs: {peg_orders:?}");
+            let mut canvas =
+                RgbImage::from_pixel(image.width(), image.height(), image::Rgb([255, 255, 255]));
             let mut early_stop_count: u32 = 0;
 
             'iter: for iter_i in pbar.wrap_iter(0..self.config.iterations) {
-                let (min_loss, min_peg, min_line) = utils::iter_or_par_iter!(self.pegs)
-                    .filter(|peg| peg.id != last_peg.id && peg.id != last_last_peg.id)
-                    .filter_map(|peg| {
-                        let line = self.line_cache.get(&utils::hash_key(last_peg, peg))?;
-                        let loss = line.loss(&work_img);
-                        Some((loss, peg, line))
+                if self.interrupted(deadline, iter_i) {
+                    break 'iter;
+                }
+                let Some((min_loss, min_yarn, min_peg, min_line)) = palette
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(yarn_i, yarn)| {
+                        let peg_order = &peg_orders[yarn_i];
+                        let last_peg = peg_order[peg_order.len() - 1];
+                        let last_last_peg = peg_order[peg_order.len().saturating_sub(2)];
+                        let (last, last_last) =
+                            (self.node_peg(last_peg), self.node_peg(last_last_peg));
+                        utils::iter_or_par_iter!(0..self.n_nodes(), into)
+                            .filter(|&node| {
+                                self.node_peg(node) != last && self.node_peg(node) != last_last
+                            })
+                            .filter_map(|peg| {
+                                let line = self.get_line(last_peg, peg)?;
+                                if self.config.exclude_masked {
+                                    let key = line_cache::pair_index(
+                                        last_peg,
+                                        self.arrival_key(peg),
+                                        n_nodes,
+                                    );
+                                    let masked = masked_lines[key].get_or_init(|| {
+                                        line.sample(&self.image, self.weight_mask.as_ref()).masked
+                                            > 0.
+                                    });
+                                    if *masked {
+                                        return None;
+                                    }
+                                }
+                                let loss = line.color_loss(
+                                    &canvas,
+                                    image,
+                                    yarn,
+                                    self.weight_mask.as_ref(),
+                                );
+                                Some((loss, yarn_i, peg, line))
+                            })
+                            .min_by(|(loss1, ..), (loss2, ..)| {
+                                loss1
+                                    .partial_cmp(loss2)
+                                    .unwrap_or(std::cmp::Ordering::Equal)
+                            })
                     })
-                    .min_by(|(loss1, _, _), (loss2, _, _)| {
+                    .min_by(|(loss1, ..), (loss2, ..)| {
                         loss1
                             .partial_cmp(loss2)
                             .unwrap_or(std::cmp::Ordering::Equal)
                     })
-                    .unwrap();
+                else {
+                    info!("No more valid lines at iteration {iter_i}");
+                    break 'iter;
+                };
                 if self.early_stop(&mut early_stop_count, min_loss) {
                     info!("Early stopping at iteration {iter_i}");
                     break 'iter;
                 }
 
-                debug!("line {:?} -> {:?}: {min_loss:?}", last_peg.id, min_peg.id);
-                peg_order.push(min_peg);
-                last_last_peg = last_peg;
-                last_peg = min_peg;
-
-                min_line.draw(&mut work_img, self.config.yarn.opacity, line_color);
+                debug!(
+                    "yarn {min_yarn} line {:?} -> {min_peg:?}: {min_loss:?}",
+                    peg_orders[min_yarn].last().unwrap()
+                );
+                peg_orders[min_yarn].push(min_peg);
+                min_line.draw_color(&mut canvas, &palette[min_yarn]);
             }
-            peg_order
+            peg_orders
         };
 
-        let order;
+        let orders;
         #[cfg(feature = "parallel")]
         {
             let pool = ThreadPoolBuilder::new().build()?;
-            order = pool.install(compute);
+            orders = pool.install(compute);
         }
 
         #[cfg(not(feature = "parallel"))]
         {
-            order = compute();
+            orders = compute();
         }
 
-        Ok(Blueprint::from_refs(
-            order,
+        let mut blueprint = Blueprint::from_strands(
+            orders
+                .into_iter()
+                .zip(palette)
+                .map(|(order, yarn)| {
+                    // the lines were simulated with the width of the pather's yarn
+                    let yarn = Yarn::new(self.config.yarn.width, yarn.opacity, yarn.color);
+                    let mut strand = Strand::from_yarn(&yarn, self.node_pegs(&order));
+                    strand.wraps = self.node_wraps(&order);
+                    strand
+                })
+                .collect(),
             self.image.width(),
             self.image.height(),
             Some((255, 255, 255)),
             1.,
             self.config.progress_bar,
-        ))
+        );
+        blueprint.pegs = self.pegs.clone();
+        Ok(blueprint)
     }
 
-    /// Run a beam search based line pathing algorithm and construct a [`Blueprint`].
-    pub fn compute_beam(&self) -> Result<Blueprint, Box<dyn Error>> {
-        if self.line_cache.is_empty() {
-            return Err("Line cache is empty, run 'populate_line_cache'.".into());
-        };
+    /// Populate the [`Pather::line_cache`] if it is empty and [`PatherConfig::line_mode`] is
+    /// [`LineMode::Eager`].
+    fn ensure_line_cache(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.config.line_mode == LineMode::Eager && self.line_cache.is_empty() {
+            warn!("Line cache is empty, populating it.");
+            self.populate_line_cache()?;
+        }
+        Ok(())
+    }
 
-        let start_peg = self.get_start_peg(self.config.start_peg_radius);
-        debug!("Starting peg: {start_peg:?}");
+    /// Run the pathing algorithm. Will use the [greedy](Pather::compute_greedy) algorithm when
+    /// [`PatherConfig::beam_width`] equals 1 and the [beam search](Pather::compute_beam) algorithm
+    /// otherwise.
+    ///
+    /// If [`Pather::line_cache`] is empty and [`PatherConfig::line_mode`] is [`LineMode::Eager`],
+    /// will [populate](Pather::populate_line_cache) it.
+    pub fn compute(&mut self) -> Result<Blueprint, Box<dyn Error>> {
+        self.resume(&[])
+    }
 
-        let pbar = utils::pbar(self.config.iterations as u64, !self.config.progress_bar)?
-            .with_message("Computing blueprint");
+    /// Continue the pathing algorithm from an existing `peg_order`, for
+    /// [`PatherConfig::iterations`] more lines, see [`Pather::compute`] and
+    /// [`Pather::compute_greedy_from`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use strandify::blueprint::Blueprint;
+    /// use strandify::pather::{Pather, PatherConfig};
+    /// use strandify::peg::shape;
+    ///
+    /// let previous = Blueprint::from_file("previous.json").unwrap();
+    /// let image = image::open("input.png").unwrap().into_luma8();
+    /// let pegs = shape::circle((250, 250), 240, 288);
+    /// let mut pather = Pather::new(image, pegs, PatherConfig::default());
+    /// let blueprint = pather.resume(&previous.peg_order).unwrap();
+    /// ```
+    pub fn resume(&mut self, peg_order: &[Peg]) -> Result<Blueprint, Box<dyn Error>> {
+        self.resume_wrapped(peg_order, &[])
+    }
 
-        let line_color = 255. * self.config.yarn.opacity;
+    /// Continue the pathing algorithm from an existing `peg_order` wrapped around the
+    /// [`Pegs`](Peg) in the `wraps` directions, e.g. [`Blueprint::wraps`], see
+    /// [`Pather::resume`] and [`PatherConfig::wrapping`]. Missing directions are clockwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::pather::{Pather, PatherConfig};
+    /// use strandify::peg::shape;
+    ///
+    /// let image = image::GrayImage::from_pixel(100, 100, image::Luma([128]));
+    /// let pegs = shape::circle((50, 50), 45, 20)
+    ///     .into_iter()
+    ///     .map(|peg| peg.with_radius(2.))
+    ///     .collect::<Vec<_>>();
+    /// let config = PatherConfig {
+    ///     iterations: 10,
+    ///     wrapping: true,
+    ///     ..Default::default()
+    /// };
+    /// let mut pather = Pather::new(image, pegs, config);
+    /// let previous = pather.compute().unwrap();
+    /// assert_eq!(previous.wraps.len(), previous.peg_order.len());
+    ///
+    /// let blueprint = pather
+    ///     .resume_wrapped(&previous.peg_order, &previous.wraps)
+    ///     .unwrap();
+    /// assert_eq!(blueprint.peg_order.len(), 21);
+    /// assert_eq!(&blueprint.wraps[..11], &previous.wraps[..]);
+    /// ```
+    pub fn resume_wrapped(
+        &mut self,
+        peg_order: &[Peg],
+        wraps: &[WrapDirection],
+    ) -> Result<Blueprint, Box<dyn Error>> {
+        self.ensure_line_cache()?;
+        if self.config.beam_width > 1 {
+            info!("Using beam search algorithm.");
+        } else {
+            info!("Using greedy algorithm.");
+        }
+        let mut steps = self.path_steps(peg_order, wraps, self.config.beam_width)?;
+        steps.by_ref().for_each(drop);
+        Ok(steps.into_blueprint())
+    }
 
-        let mut beam = vec![BeamState {
-            peg_order: vec![start_peg],
-            loss: 0.,
-            image: self.image.clone(),
-        }];
+    /// Run the pathing algorithm, like [`Pather::compute`], calling `callback` with each
+    /// [`PatherStep`] and the current work image, see [`Pather::steps`].
+    ///
+    /// # Arguments
+    ///
+    /// * `callback`: Called after each line, returning `false` stops the pathing early.
+    ///
+    /// # Returns
+    ///
+    /// * [`Blueprint`]: The [`Blueprint`] of the lines computed until the end of the pathing or
+    ///   until `callback` returned `false`.
+    pub fn compute_with_callback<F>(&mut self, mut callback: F) -> Result<Blueprint, Box<dyn Error>>
+    where
+        F: FnMut(&PatherStep, &GrayImage) -> bool,
+    {
+        self.ensure_line_cache()?;
+        let mut steps = self.steps()?;
+        while let Some(step) = steps.next() {
+            if !callback(&step, steps.work_image()) {
+                info!(
+                    "Pathing stopped by callback at iteration {}",
+                    step.iteration
+                );
+                break;
+            }
+        }
+        Ok(steps.into_blueprint())
+    }
+}
 
-        // use a ThreadPool to reduce overhead
-        let beam_width_index = self.config.beam_width - 1;
+#[derive(Debug, Clone)]
+/// A line chosen by the pathing algorithm, yielded by [`PatherSteps`].
+pub struct PatherStep {
+    /// The iteration at which the line was chosen, starting at 0.
+    pub iteration: usize,
+    /// The [`Peg`] the line starts from.
+    pub from: Peg,
+    /// The chosen [`Peg`], where the line ends.
+    pub peg: Peg,
+    /// The [`WrapDirection`] around the chosen [`Peg`], always clockwise unless
+    /// [`PatherConfig::wrapping`].
+    pub wrap: WrapDirection,
+    /// The loss of the line.
+    pub loss: f64,
+}
 
-        let compute = |beam: &mut Vec<BeamState>| {
-            let mut early_stop_count = 0;
+#[derive(Debug)]
+/// Iterator running the pathing algorithm one line at a time, created with [`Pather::steps`].
+///
+/// Yields a [`PatherStep`] for each line until [`PatherConfig::iterations`] lines are computed,
+/// the pathing early stops, no valid line remains, the [`PatherConfig::cancellation_token`] is
+/// cancelled or the [`PatherConfig::time_limit`] is reached. Dropping the iterator early cancels
+/// the pathing, the lines computed so far can still be turned into a [`Blueprint`] with
+/// [`PatherSteps::into_blueprint`].
+///
+/// With beam search, the steps follow the best path of the beam, which can change from one step
+/// to the next, [`PatherSteps::peg_order`] holds the current best path.
+pub struct PatherSteps<'a> {
+    pather: &'a Pather,
+    beam_width: usize,
+    beam: Vec<BeamState>,
+    iteration: usize,
+    early_stop_count: u32,
+    done: bool,
+    deadline: Option<Instant>,
+    pbar: ProgressBar,
+    #[cfg(feature = "parallel")]
+    pool: Arc<ThreadPool>,
+}
 
-            'iter: for iter_i in pbar.wrap_iter(0..self.config.iterations) {
-                let mut candidates: Vec<_> = utils::iter_or_par_iter!(beam)
-                    .flat_map(|beam_state| {
-                        let last_peg = &self.pegs[*beam_state.peg_order.last().unwrap()];
-                        let last_last_peg = &self.pegs[*beam_state
-                            .peg_order
-                            .get(beam_state.peg_order.len().saturating_sub(2))
-                            .unwrap_or(beam_state.peg_order.last().unwrap())];
-
-                        utils::iter_or_par_iter!(self.pegs)
-                            .enumerate()
-                            .filter(|(_, peg)| peg.id != last_peg.id && peg.id != last_last_peg.id)
-                            .filter_map(|(i, peg)| {
-                                let line = self.line_cache.get(&utils::hash_key(last_peg, peg))?;
-                                let loss = line.loss(&beam_state.image);
-                                Some((loss, i, line, beam_state))
-                            })
-                            .collect::<Vec<_>>()
-                    })
-                    .collect::<Vec<_>>();
+impl PatherSteps<'_> {
+    /// The best [`BeamState`] of the beam.
+    fn best(&self) -> &BeamState {
+        self.beam
+            .iter()
+            .min()
+            .expect("the beam should never be empty")
+    }
+
+    /// The work image of the best path, holding the lines drawn so far. Dark pixels are the ones
+    /// which still require yarn.
+    pub fn work_image(&self) -> &GrayImage {
+        &self.best().image
+    }
+
+    /// The [`Pegs`](Peg) of the best path so far.
+    pub fn peg_order(&self) -> Vec<Peg> {
+        self.pather.node_pegs(&self.best().peg_order)
+    }
+
+    /// The [`WrapDirections`](WrapDirection) of the best path so far, empty unless
+    /// [`PatherConfig::wrapping`].
+    pub fn wraps(&self) -> Vec<WrapDirection> {
+        self.pather.node_wraps(&self.best().peg_order)
+    }
 
-                // partial sort up to beam width
-                candidates.select_nth_unstable_by(beam_width_index, |(loss1, ..), (loss2, ..)| {
+    /// Construct the [`Blueprint`] of the best path so far.
+    pub fn into_blueprint(self) -> Blueprint {
+        let mut blueprint = Blueprint::new(
+            self.peg_order(),
+            self.pather.image.width(),
+            self.pather.image.height(),
+            Some((255, 255, 255)),
+            1.,
+            self.pather.config.progress_bar,
+        );
+        blueprint.wraps = self.wraps();
+        blueprint.pegs = self.pather.pegs.clone();
+        blueprint
+    }
+
+    /// Run an iteration of the pathing algorithm, [`None`] when pathing should stop.
+    fn step(&mut self) -> Option<PatherStep> {
+        if self.beam_width > 1 {
+            self.beam_step()
+        } else {
+            self.greedy_step()
+        }
+    }
+
+    /// Run a greedy iteration, [`None`] when pathing should stop.
+    fn greedy_step(&mut self) -> Option<PatherStep> {
+        let pather = self.pather;
+        let iter_i = self.iteration;
+        let state = &mut self.beam[0];
+        let last_peg = state.peg_order[state.peg_order.len() - 1];
+        let last_last_peg = state.peg_order[state.peg_order.len().saturating_sub(2)];
+
+        let (last, last_last) = (pather.node_peg(last_peg), pather.node_peg(last_last_peg));
+        let Some((min_loss, min_peg, min_line)) =
+            utils::iter_or_par_iter!(0..pather.n_nodes(), into)
+                .filter(|&node| pather.node_peg(node) != last && pather.node_peg(node) != last_last)
+                .filter_map(|peg| {
+                    let line = pather.get_line(last_peg, peg)?;
+                    let loss = pather.line_loss(&line, &state.image)?;
+                    Some((loss, peg, line))
+                })
+                .min_by(|(loss1, _, _), (loss2, _, _)| {
                     loss1
                         .partial_cmp(loss2)
                         .unwrap_or(std::cmp::Ordering::Equal)
-                });
+                })
+        else {
+            info!("No more valid lines at iteration {iter_i}");
+            return None;
+        };
+        if pather.early_stop(&mut self.early_stop_count, min_loss) {
+            info!("Early stopping at iteration {iter_i}");
+            return None;
+        }
 
-                let min_loss = candidates
-                    .iter()
-                    .take(self.config.beam_width)
-                    .min_by(|(loss1, ..), (loss2, ..)| {
-                        loss1
-                            .partial_cmp(loss2)
-                            .unwrap_or(std::cmp::Ordering::Equal)
-                    })
-                    .unwrap()
-                    .0;
+        debug!("line {last_peg:?} -> {min_peg:?}: {min_loss:?}");
+        state.peg_order.push(min_peg);
+        state.loss += min_loss;
+        let line_color = 255. * pather.config.yarn.opacity;
+        min_line.draw(&mut state.image, pather.config.yarn.opacity, line_color);
 
-                if self.early_stop(&mut early_stop_count, min_loss) {
-                    info!("Early stopping at iteration {iter_i}");
-                    break 'iter;
-                }
+        Some(PatherStep {
+            iteration: iter_i,
+            from: pather.pegs[last],
+            peg: pather.pegs[pather.node_peg(min_peg)],
+            wrap: pather.node_direction(min_peg),
+            loss: min_loss,
+        })
+    }
 
-                *beam = candidates
-                    .into_iter()
-                    .take(self.config.beam_width)
-                    .map(|(loss, peg_i, line, beam_state)| {
-                        let mut new_img = beam_state.image.clone();
-                        line.draw(&mut new_img, self.config.yarn.opacity, line_color);
-
-                        BeamState {
-                            peg_order: beam_state
-                                .peg_order
-                                .iter()
-                                .copied()
-                                .chain(std::iter::once(peg_i))
-                                .collect(),
-                            loss: beam_state.loss + loss,
-                            image: new_img,
-                        }
+    /// Run a beam search iteration, [`None`] when pathing should stop.
+    fn beam_step(&mut self) -> Option<PatherStep> {
+        let pather = self.pather;
+        let iter_i = self.iteration;
+
+        let mut candidates: Vec<_> = utils::iter_or_par_iter!(self.beam)
+            .flat_map(|beam_state| {
+                let last_peg = *beam_state.peg_order.last().unwrap();
+                let last_last_peg = *beam_state
+                    .peg_order
+                    .get(beam_state.peg_order.len().saturating_sub(2))
+                    .unwrap_or(&last_peg);
+
+                let (last, last_last) = (pather.node_peg(last_peg), pather.node_peg(last_last_peg));
+                utils::iter_or_par_iter!(0..pather.n_nodes(), into)
+                    .filter(move |&node| {
+                        pather.node_peg(node) != last && pather.node_peg(node) != last_last
                     })
-                    .collect();
-            }
-        };
+                    .filter_map(|peg| {
+                        let line = pather.get_line(last_peg, peg)?;
+                        let loss = pather.line_loss(&line, &beam_state.image)?;
+                        Some((loss, peg, line, beam_state))
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
 
-        #[cfg(feature = "parallel")]
-        {
-            let pool = ThreadPoolBuilder::new().build()?;
-            pool.install(|| compute(&mut beam));
+        if candidates.is_empty() {
+            info!("No more valid lines at iteration {iter_i}");
+            return None;
         }
 
-        #[cfg(not(feature = "parallel"))]
-        {
-            compute(&mut beam);
+        // partial sort up to beam width
+        let beam_width_index = (self.beam_width - 1).min(candidates.len() - 1);
+        candidates.select_nth_unstable_by(beam_width_index, |(loss1, ..), (loss2, ..)| {
+            loss1
+                .partial_cmp(loss2)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+        let min_loss = candidates
+            .iter()
+            .take(self.beam_width)
+            .min_by(|(loss1, ..), (loss2, ..)| {
+                loss1
+                    .partial_cmp(loss2)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+            .unwrap()
+            .0;
+
+        if pather.early_stop(&mut self.early_stop_count, min_loss) {
+            info!("Early stopping at iteration {iter_i}");
+            return None;
         }
 
-        let best_state = beam.into_iter().min().ok_or("Beam search failed.")?;
+        let line_color = 255. * pather.config.yarn.opacity;
+        let beam = candidates
+            .into_iter()
+            .take(self.beam_width)
+            .map(|(loss, peg_i, line, beam_state)| {
+                let mut new_img = beam_state.image.clone();
+                line.draw(&mut new_img, pather.config.yarn.opacity, line_color);
 
-        Ok(Blueprint::new(
-            best_state
-                .peg_order
-                .iter()
-                .map(|&index| self.pegs[index])
-                .collect(),
-            self.image.width(),
-            self.image.height(),
-            Some((255, 255, 255)),
-            1.,
-            self.config.progress_bar,
-        ))
+                BeamState {
+                    peg_order: beam_state
+                        .peg_order
+                        .iter()
+                        .copied()
+                        .chain(std::iter::once(peg_i))
+                        .collect(),
+                    loss: beam_state.loss + loss,
+                    image: new_img,
+                }
+            })
+            .collect();
+        self.beam = beam;
+
+        let best = self.best();
+        let peg_order = &best.peg_order;
+        let (from, node) = (
+            peg_order[peg_order.len() - 2],
+            peg_order[peg_order.len() - 1],
+        );
+        Some(PatherStep {
+            iteration: iter_i,
+            from: pather.pegs[pather.node_peg(from)],
+            peg: pather.pegs[pather.node_peg(node)],
+            wrap: pather.node_direction(node),
+            loss: min_loss,
+        })
     }
+}
 
-    /// Run the pathing algorithm. Will use the [greedy](Pather::compute_greedy) algorithm when
-    /// [`PatherConfig::beam_width`] equals 1 and the [beam search](Pather::compute_beam) algorithm
-    /// otherwise.
-    ///
-    /// If [`Pather::line_cache`] is empty, will [populate](Pather::populate_line_cache) it.
-    pub fn compute(&mut self) -> Result<Blueprint, Box<dyn Error>> {
-        if self.line_cache.is_empty() {
-            warn!("Line cache is empty, populating it.");
-            self.populate_line_cache()?;
+impl Iterator for PatherSteps<'_> {
+    type Item = PatherStep;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.iteration >= self.pather.config.iterations {
+            return None;
         }
-        if self.config.beam_width > 1 {
-            info!("Using beam search algorithm.");
-            self.compute_beam()
-        } else {
-            info!("Using greedy algorithm.");
-            self.compute_greedy()
+        if self.pather.interrupted(self.deadline, self.iteration) {
+            self.done = true;
+            return None;
+        }
+
+        #[cfg(feature = "parallel")]
+        let step = {
+            let pool = self.pool.clone();
+            pool.install(|| self.step())
+        };
+        #[cfg(not(feature = "parallel"))]
+        let step = self.step();
+
+        match step {
+            Some(step) => {
+                self.iteration += 1;
+                self.pbar.inc(1);
+                Some(step)
+            }
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::peg::shape;
+
+    #[test]
+    fn lazy_lines_match_eager() {
+        let image = GrayImage::from_fn(64, 64, |x, y| image::Luma([((x * y) % 256) as u8]));
+        let pegs: Vec<_> = shape::circle((32, 32), 28, 24)
+            .into_iter()
+            .map(|peg| peg.with_radius(2.))
+            .collect();
+        for (anti_aliasing, wrapping) in [(false, false), (true, false), (false, true)] {
+            let peg_order = |line_mode| {
+                let config = PatherConfig {
+                    iterations: 30,
+                    anti_aliasing,
+                    wrapping,
+                    line_mode,
+                    ..Default::default()
+                };
+                let mut pather = Pather::new(image.clone(), pegs.clone(), config);
+                let bp = pather.compute().unwrap();
+                let ids = bp.peg_order.iter().map(|peg| peg.id).collect::<Vec<_>>();
+                (ids, bp.wraps)
+            };
+
+            let eager = peg_order(LineMode::Eager);
+            // lazily computed lines, with and without a cache, lead to the same path
+            for cache_capacity in [0, 10, 1000] {
+                assert_eq!(peg_order(LineMode::Lazy { cache_capacity }), eager);
+            }
         }
     }
 }
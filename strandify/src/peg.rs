@@ -1,14 +1,13 @@
 use std::collections::HashSet;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::error::Error;
+use std::ops::Deref;
 
-use rand::{thread_rng, Rng};
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use crate::line::Line;
 use crate::utils;
 
-static COUNTER: AtomicUsize = AtomicUsize::new(0);
-
 #[derive(Debug, Clone, Copy, Deserialize, Serialize)]
 /// The [`Peg`] around which the [`Yarn`] is weaved.
 pub struct Peg {
@@ -16,15 +15,135 @@ pub struct Peg {
     pub x: u32,
     /// Vertical coordinate of the [`Peg`], (0, 0) is the top left corner of the image.
     pub y: u32,
-    /// [`Peg`] id, should be unique among [`Peg`] instances.
+    /// [`Peg`] id, its index in the [`PegLayout`] holding it.
     pub id: usize,
+    /// Radius of the [`Peg`], in pixels. The thread wraps around the [`Peg`] and leaves it from a
+    /// tangent point instead of its center, see [`Peg::tangent_points`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+/// Direction in which the thread wraps around a [`Peg`], as seen on the image.
+pub enum WrapDirection {
+    #[default]
+    /// Clockwise.
+    Cw,
+    /// Counter-clockwise.
+    Ccw,
+}
+
+impl WrapDirection {
+    /// The opposite [`WrapDirection`].
+    pub fn reverse(&self) -> Self {
+        match self {
+            Self::Cw => Self::Ccw,
+            Self::Ccw => Self::Cw,
+        }
+    }
+
+    /// 1 for [`WrapDirection::Cw`], -1 for [`WrapDirection::Ccw`].
+    fn sign(&self) -> f64 {
+        match self {
+            Self::Cw => 1.,
+            Self::Ccw => -1.,
+        }
+    }
+}
+
+impl std::fmt::Display for WrapDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cw => write!(f, "cw"),
+            Self::Ccw => write!(f, "ccw"),
+        }
+    }
 }
 
 impl Peg {
-    /// Creates a new [`Peg`].
+    /// Creates a new [`Peg`], its id is assigned when it is added to a [`PegLayout`].
     pub fn new(x: u32, y: u32) -> Self {
-        let id = COUNTER.fetch_add(1, Ordering::SeqCst);
-        Self { x, y, id }
+        Self {
+            x,
+            y,
+            id: 0,
+            radius: None,
+        }
+    }
+
+    /// Returns the [`Peg`] with the given radius, in pixels.
+    pub fn with_radius(self, radius: f32) -> Self {
+        Self {
+            radius: (radius > 0.).then_some(radius),
+            ..self
+        }
+    }
+
+    /// Get the tangent points of the thread going from this [`Peg`], around which it wraps in
+    /// `direction`, to the `other` [`Peg`], around which it then wraps in `other_direction`.
+    /// [`Pegs`](Peg) without radius are points, and the centers are returned when the
+    /// [`Pegs`](Peg) overlap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::peg::{Peg, WrapDirection};
+    /// let peg_a = Peg::new(0, 10).with_radius(1.);
+    /// let peg_b = Peg::new(10, 10).with_radius(1.);
+    /// // going right and wrapping clockwise, the thread runs along the top of the pegs
+    /// let (start, end) = peg_a.tangent_points(WrapDirection::Cw, &peg_b, WrapDirection::Cw);
+    /// assert_eq!((start, end), ((0., 9.), (10., 9.)));
+    /// ```
+    pub fn tangent_points(
+        &self,
+        direction: WrapDirection,
+        other: &Peg,
+        other_direction: WrapDirection,
+    ) -> ((f64, f64), (f64, f64)) {
+        let (x_a, y_a) = (self.x as f64, self.y as f64);
+        let (x_b, y_b) = (other.x as f64, other.y as f64);
+        let radius_a = direction.sign() * self.radius.unwrap_or(0.) as f64;
+        let radius_b = other_direction.sign() * other.radius.unwrap_or(0.) as f64;
+        let dist = (x_b - x_a).hypot(y_b - y_a);
+        let cos = (radius_a - radius_b) / dist;
+        if dist == 0. || cos.abs() > 1. {
+            return ((x_a, y_a), (x_b, y_b));
+        }
+        let (dir_x, dir_y) = ((x_b - x_a) / dist, (y_b - y_a) / dist);
+        // normal of the thread, pointing from the pegs' centers towards the clockwise tangent
+        // points, the y axis points down
+        let sin = (1. - cos * cos).sqrt();
+        let (normal_x, normal_y) = (cos * dir_x + sin * dir_y, cos * dir_y - sin * dir_x);
+        (
+            (x_a + radius_a * normal_x, y_a + radius_a * normal_y),
+            (x_b + radius_b * normal_x, y_b + radius_b * normal_y),
+        )
+    }
+
+    /// Get the pixel coords and coverage of the anti-aliased line joining the tangent points of
+    /// the thread wrapped around 2 [`Pegs`](Peg), see [`Peg::tangent_points`] and
+    /// [`Peg::line_to_aa`].
+    ///
+    /// # Arguments:
+    ///
+    /// * `direction`: the [`WrapDirection`] around this [`Peg`].
+    /// * `other`: the other [`Peg`] to draw the line to.
+    /// * `other_direction`: the [`WrapDirection`] around the `other` [`Peg`].
+    /// * `width`: the width of the line, in pixels.
+    /// * `min_max`: min and max values of the line (x_min, x_max, y_min, y_max), pixels outside
+    ///   of these bounds are dropped.
+    pub fn tangent_line_to(
+        &self,
+        direction: WrapDirection,
+        other: &Peg,
+        other_direction: WrapDirection,
+        width: f32,
+        min_max: Option<(u32, u32, u32, u32)>,
+    ) -> Line {
+        let (start, end) = self.tangent_points(direction, other, other_direction);
+        let dist = (end.0 - start.0).hypot(end.1 - start.1).round() as u32;
+        line_aa(start, end, width, min_max, dist)
     }
 
     /// Get the pixel coords connecting 2 [`Pegs`](Peg) using the Bresenham line algorithm and contruct a [`Line`].
@@ -33,7 +152,7 @@ impl Peg {
     ///
     /// * `other`: the other [`Peg`] to draw the line to.
     /// * `width`: the width of the line. The line resulting line width can only be odd, which
-    ///     leads to unintuitive behaviours:
+    ///   leads to unintuitive behaviours:
     ///     * `width=0` -> 1 pixel wide
     ///     * `width=1` -> 1 pixel wide
     ///     * `width=2` -> 3 pixels wide
@@ -96,6 +215,35 @@ impl Peg {
         Line::new(x_vec, y_vec, self.dist_to(other))
     }
 
+    /// Get the pixel coords and coverage of the anti-aliased line connecting 2 [`Pegs`](Peg) and
+    /// contruct a [`Line`].
+    ///
+    /// The line is modelled as a stroke with round caps between the top left corners of the
+    /// [`Pegs`](Peg)' pixels, like the lines rendered by
+    /// [`Blueprint::render_img`](crate::blueprint::Blueprint::render_img). The coverage of each
+    /// pixel is the overlap of the stroke across the pixel, so fractional widths are honoured.
+    ///
+    /// # Arguments:
+    ///
+    /// * `other`: the other [`Peg`] to draw the line to.
+    /// * `width`: the width of the line, in pixels.
+    /// * `min_max`: min and max values of the line (x_min, x_max, y_min, y_max), pixels outside
+    ///   of these bounds are dropped.
+    pub fn line_to_aa(
+        &self,
+        other: &Peg,
+        width: f32,
+        min_max: Option<(u32, u32, u32, u32)>,
+    ) -> Line {
+        line_aa(
+            (self.x as f64, self.y as f64),
+            (other.x as f64, other.y as f64),
+            width,
+            min_max,
+            self.dist_to(other),
+        )
+    }
+
     /// Get the pixels around a [`Peg`] within radius.
     ///
     /// # Arguments
@@ -119,23 +267,235 @@ impl Peg {
     /// # Arguments
     ///
     /// * `jitter`: Amount of jitter to add, in pixels.
+    /// * `rng`: the random number generator, see [`utils::seeded_rng`] for reproducible jitter.
     ///
     /// # Examples
     ///
     /// ```
     /// use strandify::peg::Peg;
+    /// use strandify::utils;
     /// let peg = Peg::new(10, 10);
-    /// let peg_jitter = peg.with_jitter(2);
+    /// let peg_jitter = peg.with_jitter(2, &mut utils::seeded_rng(Some(42)));
     /// assert_eq!(peg_jitter.id, peg.id);
+    /// let peg_again = peg.with_jitter(2, &mut utils::seeded_rng(Some(42)));
+    /// assert_eq!((peg_jitter.x, peg_jitter.y), (peg_again.x, peg_again.y));
     /// ```
-    pub fn with_jitter(&self, jitter: i64) -> Self {
-        let mut rng = thread_rng();
+    pub fn with_jitter<R: Rng>(&self, jitter: i64, rng: &mut R) -> Self {
+        let jitter = jitter.abs();
         Self {
-            x: (self.x as i64 + rng.gen_range(-jitter..jitter)) as u32,
-            y: (self.y as i64 + rng.gen_range(-jitter..jitter)) as u32,
-            id: self.id,
+            x: (self.x as i64 + rng.gen_range(-jitter..=jitter)).max(0) as u32,
+            y: (self.y as i64 + rng.gen_range(-jitter..=jitter)).max(0) as u32,
+            ..*self
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+/// The [`Pegs`](Peg) of a frame, with contiguous ids: each [`Peg::id`] is the index of the
+/// [`Peg`] in the layout. Serialized as a list of [`Pegs`](Peg), loading a list with duplicate
+/// ids fails.
+///
+/// # Examples
+///
+/// ```
+/// use strandify::peg::{Peg, PegLayout};
+/// let mut layout = PegLayout::new(vec![Peg::new(0, 0), Peg::new(10, 0)]);
+/// layout.push(Peg::new(10, 10));
+/// assert_eq!(layout.iter().map(|peg| peg.id).collect::<Vec<_>>(), vec![0, 1, 2]);
+/// assert_eq!((layout[2].x, layout[2].y), (10, 10));
+/// ```
+pub struct PegLayout {
+    pegs: Vec<Peg>,
+}
+
+impl PegLayout {
+    /// Creates a new [`PegLayout`], the ids of the [`Pegs`](Peg) are set to their index.
+    pub fn new(pegs: impl IntoIterator<Item = Peg>) -> Self {
+        let mut layout = Self::default();
+        layout.extend(pegs);
+        layout
+    }
+
+    /// Creates a new [`PegLayout`] from loaded [`Pegs`](Peg), checking that their ids are unique.
+    /// The ids are then replaced by their index.
+    ///
+    /// # Errors
+    ///
+    /// This function will return an error if two [`Pegs`](Peg) have the same id.
+    pub fn try_new(pegs: Vec<Peg>) -> Result<Self, Box<dyn Error>> {
+        let mut ids = HashSet::with_capacity(pegs.len());
+        if let Some(peg) = pegs.iter().find(|peg| !ids.insert(peg.id)) {
+            return Err(format!("Duplicate peg id {}.", peg.id).into());
+        }
+        Ok(Self::new(pegs))
+    }
+
+    /// Add a [`Peg`] to the layout and returns its id.
+    pub fn push(&mut self, peg: Peg) -> usize {
+        let id = self.pegs.len();
+        self.pegs.push(Peg { id, ..peg });
+        id
+    }
+
+    /// Returns the layout with each [`Peg`] replaced by `f`, keeping its id.
+    pub fn map(self, f: impl FnMut(Peg) -> Peg) -> Self {
+        Self::new(self.pegs.into_iter().map(f))
+    }
+
+    /// Get the layout [`Peg`] a [`Peg`] refers to: the one with the same id, or else the one at
+    /// the same coordinates.
+    pub fn find(&self, peg: &Peg) -> Option<&Peg> {
+        self.position(peg).map(|index| &self.pegs[index])
+    }
+
+    /// Get the index in the layout of the [`Peg`] a [`Peg`] refers to, see [`PegLayout::find`].
+    pub fn position(&self, peg: &Peg) -> Option<usize> {
+        self.pegs
+            .get(peg.id)
+            .filter(|other| (other.x, other.y) == (peg.x, peg.y))
+            .map(|_| peg.id)
+            .or_else(|| {
+                self.pegs
+                    .iter()
+                    .position(|other| (other.x, other.y) == (peg.x, peg.y))
+            })
+    }
+
+    /// Consumes the layout and returns its [`Pegs`](Peg).
+    pub fn into_pegs(self) -> Vec<Peg> {
+        self.pegs
+    }
+}
+
+impl Deref for PegLayout {
+    type Target = [Peg];
+
+    fn deref(&self) -> &Self::Target {
+        &self.pegs
+    }
+}
+
+impl Extend<Peg> for PegLayout {
+    fn extend<T: IntoIterator<Item = Peg>>(&mut self, pegs: T) {
+        for peg in pegs {
+            self.push(peg);
+        }
+    }
+}
+
+impl FromIterator<Peg> for PegLayout {
+    fn from_iter<T: IntoIterator<Item = Peg>>(pegs: T) -> Self {
+        Self::new(pegs)
+    }
+}
+
+impl<'a> IntoIterator for &'a PegLayout {
+    type Item = &'a Peg;
+    type IntoIter = std::slice::Iter<'a, Peg>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.pegs.iter()
+    }
+}
+
+impl From<Vec<Peg>> for PegLayout {
+    /// Creates a [`PegLayout`] from [`Pegs`](Peg), their ids are replaced by their index.
+    fn from(pegs: Vec<Peg>) -> Self {
+        Self::new(pegs)
+    }
+}
+
+impl<'de> Deserialize<'de> for PegLayout {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Self::try_new(Vec::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Get the pixel coords and coverage of the anti-aliased line between 2 points, see
+/// [`Peg::line_to_aa`].
+fn line_aa(
+    start: (f64, f64),
+    end: (f64, f64),
+    width: f32,
+    min_max: Option<(u32, u32, u32, u32)>,
+    dist: u32,
+) -> Line {
+    let half_width = width.max(0.) as f64 / 2.;
+    let (x_min, x_max, y_min, y_max): (i64, i64, i64, i64) = match min_max {
+        Some((x_min, x_max, y_min, y_max)) => {
+            (x_min as i64, x_max as i64, y_min as i64, y_max as i64)
+        }
+        None => (0, i64::MAX, 0, i64::MAX),
+    };
+
+    let (x0, y0) = start;
+    let (dx, dy) = (end.0 - x0, end.1 - y0);
+    let length_sq = dx * dx + dy * dy;
+
+    // walk along the major axis and only visit the pixels within reach of the line
+    let x_major = dx.abs() >= dy.abs();
+    let (major_start, minor_start, major_delta, minor_delta) = if x_major {
+        (x0, y0, dx, dy)
+    } else {
+        (y0, x0, dy, dx)
+    };
+    let slope = if major_delta == 0. {
+        0.
+    } else {
+        minor_delta / major_delta
+    };
+    let (major_lo, major_hi) = (
+        major_start.min(major_start + major_delta),
+        major_start.max(major_start + major_delta),
+    );
+    // width of a pixel projected onto the normal of the line
+    let footprint = if length_sq == 0. {
+        1.
+    } else {
+        (dx.abs() + dy.abs()) / length_sq.sqrt()
+    };
+    let reach = half_width + footprint;
+    let minor_reach = reach * (1. + slope * slope).sqrt();
+
+    let mut x_vec = vec![];
+    let mut y_vec = vec![];
+    let mut coverage_vec = vec![];
+    for major in ((major_lo - reach).floor() as i64)..=((major_hi + reach).ceil() as i64) {
+        let major_center = (major as f64 + 0.5).clamp(major_lo, major_hi);
+        let minor_center = minor_start + (major_center - major_start) * slope;
+        for minor in ((minor_center - minor_reach).floor() as i64)
+            ..=((minor_center + minor_reach).ceil() as i64)
+        {
+            let (x, y) = if x_major {
+                (major, minor)
+            } else {
+                (minor, major)
+            };
+            if x < x_min || x > x_max || y < y_min || y > y_max {
+                continue;
+            }
+            // distance from the pixel center to the segment
+            let (px, py) = (x as f64 + 0.5 - x0, y as f64 + 0.5 - y0);
+            let t = if length_sq == 0. {
+                0.
+            } else {
+                ((px * dx + py * dy) / length_sq).clamp(0., 1.)
+            };
+            let dist = ((px - t * dx).powi(2) + (py - t * dy).powi(2)).sqrt();
+            // overlap of the stroke with the pixel's footprint around `dist`
+            let coverage = (((dist + footprint / 2.).min(half_width)
+                - (dist - footprint / 2.).max(-half_width))
+                / footprint)
+                .clamp(0., 1.);
+            if coverage > 0. {
+                x_vec.push(x as u32);
+                y_vec.push(y as u32);
+                coverage_vec.push(coverage as f32);
+            }
         }
     }
+    Line::with_coverage(x_vec, y_vec, coverage_vec, dist)
 }
 
 /// Helper functions to generate [`Pegs`](Peg) based on different shapes.
@@ -147,7 +507,11 @@ pub mod shape {
             .0
             .into_iter()
             .zip(coords.1)
-            .map(|(x, y)| Peg::new(x, y))
+            .enumerate()
+            .map(|(id, (x, y))| Peg {
+                id,
+                ..Peg::new(x, y)
+            })
             .collect()
     }
 
@@ -185,6 +549,173 @@ pub mod shape {
         coords_to_pegs(utils::circle_coords(center, radius, n_pegs))
     }
 
+    /// Generate [`Pegs`](Peg) around an ellipse, evenly spaced by arc length.
+    ///
+    /// # Arguments
+    ///
+    /// * `center`: The center of the ellipse.
+    /// * `radii`: Horizontal and vertical radii of the ellipse.
+    /// * `n_pegs`: Number of pegs.
+    pub fn ellipse(center: (u32, u32), radii: (u32, u32), n_pegs: usize) -> Vec<Peg> {
+        coords_to_pegs(utils::ellipse_coords(center, radii, n_pegs))
+    }
+
+    /// Generate [`Pegs`](Peg) around a regular polygon.
+    ///
+    /// # Arguments
+    ///
+    /// * `center`: The center of the polygon.
+    /// * `radius`: Distance between the center and the vertices.
+    /// * `n_sides`: Number of sides, at least 3.
+    /// * `rotation`: Angle of the first vertex, in radians, clockwise from the right of the
+    ///   center.
+    /// * `n_pegs`: Number of pegs, a multiple of `n_sides` puts a peg on each vertex.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use std::f64::consts::PI;
+    /// use strandify::peg::shape;
+    ///
+    /// // a hexagon with a vertex at the top
+    /// let pegs = shape::regular_polygon((100, 100), 50, 6, -PI / 2., 60);
+    /// assert_eq!(pegs.len(), 60);
+    /// assert_eq!((pegs[0].x, pegs[0].y), (100, 50));
+    /// ```
+    pub fn regular_polygon(
+        center: (u32, u32),
+        radius: u32,
+        n_sides: usize,
+        rotation: f64,
+        n_pegs: usize,
+    ) -> Vec<Peg> {
+        coords_to_pegs(utils::regular_polygon_coords(
+            center, radius, n_sides, rotation, n_pegs,
+        ))
+    }
+
+    /// Generate [`Pegs`](Peg) along a svg path, evenly spaced by arc length. The path is scaled
+    /// to fit in the box, keeping its aspect ratio.
+    ///
+    /// # Arguments
+    ///
+    /// * `path_data`: The svg path data, e.g. the `d` attribute of a `path` element.
+    /// * `top_left`: Top left corner of the box.
+    /// * `size`: Width and height of the box.
+    /// * `n_pegs`: Number of pegs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::peg::shape;
+    ///
+    /// let heart = "M 10,30 A 20,20 0,0,1 50,30 A 20,20 0,0,1 90,30 Q 90,60 50,90 Q 10,60 10,30 z";
+    /// let pegs = shape::from_svg_path(heart, (0, 0), (200, 200), 100).unwrap();
+    /// assert_eq!(pegs.len(), 100);
+    /// assert!(pegs.iter().all(|peg| peg.x <= 200 && peg.y <= 200));
+    /// assert!(shape::from_svg_path("not a path", (0, 0), (200, 200), 100).is_err());
+    /// ```
+    pub fn from_svg_path(
+        path_data: &str,
+        top_left: (u32, u32),
+        size: (u32, u32),
+        n_pegs: usize,
+    ) -> Result<Vec<Peg>, Box<dyn std::error::Error>> {
+        Ok(coords_to_pegs(utils::svg_path_coords(
+            path_data, top_left, size, n_pegs,
+        )?))
+    }
+
+    /// Generate [`Pegs`](Peg) on a square grid filling a box, e.g. to add interior pegs inside a
+    /// frame. See [`PatherConfig::peg_clearance`](crate::pather::PatherConfig::peg_clearance) to
+    /// keep the lines from crossing them.
+    ///
+    /// # Arguments
+    ///
+    /// * `top_left`: Top left corner of the box.
+    /// * `size`: Width and height of the box.
+    /// * `spacing`: Distance between neighbouring pegs, in pixels.
+    pub fn square_grid(top_left: (u32, u32), size: (u32, u32), spacing: f64) -> Vec<Peg> {
+        coords_to_pegs(utils::square_grid_coords(top_left, size, spacing))
+    }
+
+    /// Generate [`Pegs`](Peg) on a hexagonal grid filling a box, each peg is at `spacing` of its
+    /// 6 neighbours.
+    ///
+    /// # Arguments
+    ///
+    /// * `top_left`: Top left corner of the box.
+    /// * `size`: Width and height of the box.
+    /// * `spacing`: Distance between neighbouring pegs, in pixels.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::peg::shape;
+    ///
+    /// let pegs = shape::hex_grid((0, 0), (100, 100), 20.);
+    /// let closest = pegs[1..]
+    ///     .iter()
+    ///     .map(|peg| (peg.x as f64 - pegs[0].x as f64).hypot(peg.y as f64 - pegs[0].y as f64))
+    ///     .fold(f64::INFINITY, f64::min);
+    /// assert!((closest - 20.).abs() < 1.);
+    /// ```
+    pub fn hex_grid(top_left: (u32, u32), size: (u32, u32), spacing: f64) -> Vec<Peg> {
+        coords_to_pegs(utils::hex_grid_coords(top_left, size, spacing))
+    }
+
+    /// Generate randomly scattered [`Pegs`](Peg) filling a box, no closer than `min_distance` to
+    /// each other, see [`utils::poisson_disc_coords`].
+    ///
+    /// # Arguments
+    ///
+    /// * `top_left`: Top left corner of the box.
+    /// * `size`: Width and height of the box.
+    /// * `min_distance`: Minimum distance between pegs, in pixels.
+    /// * `rng`: The random number generator.
+    pub fn poisson_disc<R: Rng>(
+        top_left: (u32, u32),
+        size: (u32, u32),
+        min_distance: f64,
+        rng: &mut R,
+    ) -> Vec<Peg> {
+        coords_to_pegs(utils::poisson_disc_coords(
+            top_left,
+            size,
+            min_distance,
+            rng,
+        ))
+    }
+
+    /// Generate [`Pegs`](Peg) scattered over a box of an image, denser where the image has more
+    /// edges, see [`utils::image_adaptive_coords`].
+    ///
+    /// # Arguments
+    ///
+    /// * `image`: The grayscale image, usually the one to path.
+    /// * `top_left`: Top left corner of the box.
+    /// * `size`: Width and height of the box.
+    /// * `n_pegs`: Number of pegs, fewer are returned when the box is too crowded.
+    /// * `min_distance`: Minimum distance between pegs, in pixels.
+    /// * `rng`: The random number generator.
+    pub fn image_adaptive<R: Rng>(
+        image: &image::GrayImage,
+        top_left: (u32, u32),
+        size: (u32, u32),
+        n_pegs: usize,
+        min_distance: f64,
+        rng: &mut R,
+    ) -> Result<Vec<Peg>, Box<dyn std::error::Error>> {
+        Ok(coords_to_pegs(utils::image_adaptive_coords(
+            image,
+            top_left,
+            size,
+            n_pegs,
+            min_distance,
+            rng,
+        )?))
+    }
+
     /// Generate [`Pegs`](Peg) on a line.
     ///
     /// # Arguments
@@ -238,6 +769,46 @@ impl Yarn {
 mod test {
     use super::*;
 
+    #[test]
+    fn peg_tangent_points() {
+        let peg_a = Peg::new(0, 0).with_radius(1.);
+        let peg_b = Peg::new(10, 0).with_radius(1.);
+        let points = |a: WrapDirection, b: WrapDirection| peg_a.tangent_points(a, &peg_b, b);
+
+        assert_eq!(
+            points(WrapDirection::Cw, WrapDirection::Cw),
+            ((0., -1.), (10., -1.))
+        );
+        assert_eq!(
+            points(WrapDirection::Ccw, WrapDirection::Ccw),
+            ((0., 1.), (10., 1.))
+        );
+        // crossing tangents, perpendicular to the radii
+        for (a, b) in [
+            (WrapDirection::Cw, WrapDirection::Ccw),
+            (WrapDirection::Ccw, WrapDirection::Cw),
+        ] {
+            let ((x0, y0), (x1, y1)) = points(a, b);
+            assert!((x0.hypot(y0) - 1.).abs() < 1e-9);
+            assert!(((x1 - 10.).hypot(y1) - 1.).abs() < 1e-9);
+            assert!((x0 * (x1 - x0) + y0 * (y1 - y0)).abs() < 1e-9);
+            assert_eq!(y0.signum(), -y1.signum());
+        }
+
+        // pegs without radius and overlapping pegs use the centers
+        let point_a = Peg::new(0, 0);
+        assert_eq!(
+            point_a.tangent_points(WrapDirection::Cw, &Peg::new(10, 0), WrapDirection::Ccw),
+            ((0., 0.), (10., 0.))
+        );
+        let big = Peg::new(1, 0).with_radius(5.);
+        assert_eq!(
+            peg_a.tangent_points(WrapDirection::Cw, &big, WrapDirection::Ccw),
+            ((0., 0.), (1., 0.))
+        );
+        assert!(Peg::new(0, 0).with_radius(0.).radius.is_none());
+    }
+
     #[test]
     fn peg_line_to() {
         let peg_a = Peg::new(0, 0);
@@ -336,6 +907,49 @@ mod test {
         assert_eq!(*line.y.iter().min().unwrap(), 4);
     }
 
+    #[test]
+    fn peg_line_to_aa() {
+        // horizontal line between pixel corners, half covers 2 rows of pixels
+        let peg_a = Peg::new(2, 5);
+        let peg_b = Peg::new(12, 5);
+        let line = peg_a.line_to_aa(&peg_b, 1., None);
+        let coverage = line.coverage.as_ref().unwrap();
+        assert_eq!(line.dist, 10);
+        for ((x, y), coverage) in line.zip().zip(coverage) {
+            if (3..12).contains(x) {
+                assert!([4, 5].contains(y));
+                assert_eq!(*coverage, 0.5);
+            }
+        }
+        // total coverage is about the area of the stroke, with its round caps
+        let stroke_area =
+            |length: f32, width: f32| length * width + std::f32::consts::PI * width * width / 4.;
+        let area: f32 = coverage.iter().sum();
+        assert!((area - stroke_area(10., 1.)).abs() < 0.5);
+
+        // fractional widths are honoured
+        let line = peg_a.line_to_aa(&peg_b, 2.5, None);
+        let area: f32 = line.coverage.unwrap().iter().sum();
+        assert!((area - stroke_area(10., 2.5)).abs() < 0.5);
+
+        // diagonal lines are not heavier than straight ones
+        let peg_b = Peg::new(12, 15);
+        let line = peg_a.line_to_aa(&peg_b, 1., None);
+        let area: f32 = line.coverage.unwrap().iter().sum();
+        assert!((area - stroke_area(200_f32.sqrt(), 1.)).abs() < 1.);
+    }
+
+    #[test]
+    fn peg_line_to_aa_min_max() {
+        let peg_a = Peg::new(0, 5);
+        let peg_b = Peg::new(10, 5);
+        let line = peg_a.line_to_aa(&peg_b, 4., Some((0, 10, 4, 5)));
+        assert_eq!(*line.x.iter().max().unwrap(), 10);
+        assert_eq!(*line.x.iter().min().unwrap(), 0);
+        assert_eq!(*line.y.iter().max().unwrap(), 5);
+        assert_eq!(*line.y.iter().min().unwrap(), 4);
+    }
+
     #[test]
     fn peg_around() {
         let peg = Peg::new(10, 10);
@@ -348,9 +962,53 @@ mod test {
     fn peg_jitter() {
         let peg = Peg::new(10, 10);
         let jitter = 2;
-        let peg_jitter = peg.with_jitter(jitter);
+        let mut rng = utils::seeded_rng(Some(0));
+        let peg_jitter = peg.with_jitter(jitter, &mut rng);
         assert!(peg_jitter.x <= (peg.x as i64 + jitter) as u32);
         assert!(peg_jitter.x >= (peg.x as i64 - jitter) as u32);
         assert_eq!(peg_jitter.id, peg.id);
+        // no jitter keeps the peg in place
+        let peg_still = peg.with_jitter(0, &mut rng);
+        assert_eq!((peg_still.x, peg_still.y), (peg.x, peg.y));
+    }
+
+    #[test]
+    fn peg_layout() {
+        let ids = |layout: &PegLayout| layout.iter().map(|peg| peg.id).collect::<Vec<_>>();
+        let layout: PegLayout =
+            serde_json::from_str(r#"[{"x": 0, "y": 0, "id": 7}, {"x": 5, "y": 0, "id": 3}]"#)
+                .unwrap();
+        assert_eq!(ids(&layout), vec![0, 1]);
+        assert!(serde_json::from_str::<PegLayout>(
+            r#"[{"x": 0, "y": 0, "id": 7}, {"x": 5, "y": 0, "id": 7}]"#
+        )
+        .is_err());
+
+        // by id, then by coords when the id points to another peg
+        assert_eq!(
+            layout
+                .find(&Peg {
+                    id: 1,
+                    ..Peg::new(5, 0)
+                })
+                .unwrap()
+                .id,
+            1
+        );
+        assert_eq!(
+            layout
+                .find(&Peg {
+                    id: 0,
+                    ..Peg::new(5, 0)
+                })
+                .unwrap()
+                .id,
+            1
+        );
+        assert!(layout.find(&Peg::new(1, 1)).is_none());
+
+        let layout = layout.map(|peg| peg.with_radius(2.));
+        assert_eq!(ids(&layout), vec![0, 1]);
+        assert!(layout.iter().all(|peg| peg.radius == Some(2.)));
     }
 }
@@ -0,0 +1,387 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+
+use crate::blueprint::Blueprint;
+use crate::instructions;
+
+/// Maximum number of [`RepeatedSegment`] listed by [`Stats::to_text`].
+const TEXT_REPEATED_SEGMENTS: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// Bounding box of the used [`Pegs`](crate::peg::Peg), from their centers, in pixels.
+pub struct BoundingBox {
+    /// Smallest horizontal coordinate.
+    pub min_x: u32,
+    /// Smallest vertical coordinate.
+    pub min_y: u32,
+    /// Largest horizontal coordinate.
+    pub max_x: u32,
+    /// Largest vertical coordinate.
+    pub max_y: u32,
+}
+
+impl BoundingBox {
+    /// Width of the [`BoundingBox`], in pixels.
+    pub fn width(&self) -> u32 {
+        self.max_x - self.min_x
+    }
+
+    /// Height of the [`BoundingBox`], in pixels.
+    pub fn height(&self) -> u32 {
+        self.max_y - self.min_y
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// A segment of thread laid more than once between the same 2 pegs, in either direction.
+pub struct RepeatedSegment {
+    /// Numbers of the 2 [`Pegs`](crate::peg::Peg), see [`Stats::peg_usage`], smallest first.
+    pub pegs: (usize, usize),
+    /// Number of times the segment is laid.
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// Statistics of a [`Blueprint`], to estimate its build time and spot degenerate paths.
+///
+/// Lines are counted over all the [`Strands`](crate::blueprint::Strand), the lengths are in
+/// pixels.
+pub struct Stats {
+    /// Number of [`Strands`](crate::blueprint::Strand), 1 for single yarn blueprints.
+    pub strands: usize,
+    /// Number of lines, segments of thread between 2 pegs.
+    pub lines: usize,
+    /// Number of distinct pegs visited.
+    pub unique_pegs: usize,
+    /// Number of pegs of the frame, [`Blueprint::pegs`], [`None`] if not recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub frame_pegs: Option<usize>,
+    /// Number of visits of each peg, by peg number, the numbers of the build
+    /// [`Instructions`](crate::instructions::Instructions), starting at 1.
+    pub peg_usage: BTreeMap<usize, usize>,
+    /// Segments laid more than once, most repeated first.
+    pub repeated_segments: Vec<RepeatedSegment>,
+    /// Total length of thread.
+    pub total_length: f64,
+    /// Average length of a line, 0 without lines.
+    pub average_length: f64,
+    /// Total length of thread in mm, [`None`] if the [`Blueprint::frame_size`] is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total_length_mm: Option<f64>,
+    /// Average length of a line in mm, [`None`] if the [`Blueprint::frame_size`] is not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub average_length_mm: Option<f64>,
+    /// Largest number of consecutive lines of a strand which don't revisit a peg.
+    pub longest_run: usize,
+    /// [`BoundingBox`] of the visited pegs, [`None`] without pegs.
+    pub bounding_box: Option<BoundingBox>,
+}
+
+impl Stats {
+    /// Compute the [`Stats`] of a [`Blueprint`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::blueprint::Blueprint;
+    /// use strandify::peg::Peg;
+    ///
+    /// let (a, b, c) = (Peg::new(0, 0), Peg::new(30, 0), Peg::new(30, 40));
+    /// let bp = Blueprint::new(vec![a, b, c, a, b], 50, 50, None, 1., false);
+    /// let stats = bp.stats();
+    /// assert_eq!(stats.lines, 4);
+    /// assert_eq!(stats.unique_pegs, 3);
+    /// assert_eq!(stats.total_length, 150.);
+    /// assert_eq!(stats.longest_run, 2);
+    /// assert_eq!(stats.repeated_segments[0].count, 2);
+    /// ```
+    pub fn new(blueprint: &Blueprint) -> Self {
+        let strands = instructions::strands(blueprint);
+        let lengths = blueprint.segment_lengths();
+        let total_length: f64 = lengths.iter().sum();
+        let average_length = total_length / lengths.len().max(1) as f64;
+        let scale = blueprint.mm_per_pixel();
+
+        let mut peg_usage = BTreeMap::new();
+        let mut segment_counts: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut longest_run = 0;
+        let mut bounding_box: Option<BoundingBox> = None;
+        // every peg of the peg orders is numbered
+        let numbers = instructions::peg_numbers(&instructions::numbered_pegs(blueprint));
+        for strand in &strands {
+            let keys: Vec<usize> = strand
+                .peg_order
+                .iter()
+                .map(|peg| numbers[&(peg.x, peg.y)])
+                .collect();
+            for key in &keys {
+                *peg_usage.entry(*key).or_insert(0) += 1;
+            }
+            for peg in &strand.peg_order {
+                bounding_box = Some(match bounding_box {
+                    None => BoundingBox {
+                        min_x: peg.x,
+                        min_y: peg.y,
+                        max_x: peg.x,
+                        max_y: peg.y,
+                    },
+                    Some(bbox) => BoundingBox {
+                        min_x: bbox.min_x.min(peg.x),
+                        min_y: bbox.min_y.min(peg.y),
+                        max_x: bbox.max_x.max(peg.x),
+                        max_y: bbox.max_y.max(peg.y),
+                    },
+                });
+            }
+            for pair in keys.windows(2) {
+                let key = (pair[0].min(pair[1]), pair[0].max(pair[1]));
+                *segment_counts.entry(key).or_insert(0) += 1;
+            }
+            longest_run = longest_run.max(longest_run_without_revisit(keys.into_iter()));
+        }
+
+        let mut repeated_segments: Vec<RepeatedSegment> = segment_counts
+            .into_iter()
+            .filter(|(_, count)| *count > 1)
+            .map(|(pegs, count)| RepeatedSegment { pegs, count })
+            .collect();
+        repeated_segments.sort_by(|a, b| b.count.cmp(&a.count).then(a.pegs.cmp(&b.pegs)));
+
+        Self {
+            strands: strands.len(),
+            lines: lengths.len(),
+            unique_pegs: peg_usage.len(),
+            frame_pegs: (!blueprint.pegs.is_empty()).then_some(blueprint.pegs.len()),
+            peg_usage,
+            repeated_segments,
+            total_length,
+            average_length,
+            total_length_mm: scale.map(|scale| total_length * scale),
+            average_length_mm: scale.map(|scale| average_length * scale),
+            longest_run,
+            bounding_box,
+        }
+    }
+
+    /// Histogram of the peg usage, the number of pegs for each number of visits, fewest visits
+    /// first.
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<(usize, usize)>`: Pairs of number of visits and number of pegs visited that many
+    ///   times.
+    pub fn usage_histogram(&self) -> Vec<(usize, usize)> {
+        let mut histogram = BTreeMap::new();
+        for count in self.peg_usage.values() {
+            *histogram.entry(*count).or_insert(0) += 1;
+        }
+        histogram.into_iter().collect()
+    }
+
+    /// Number of lines laid over a segment already laid, see [`Stats::repeated_segments`].
+    pub fn repeated_lines(&self) -> usize {
+        self.repeated_segments
+            .iter()
+            .map(|segment| segment.count - 1)
+            .sum()
+    }
+
+    /// Format the [`Stats`] as a human readable report, with the lengths in m when the
+    /// [`Blueprint::frame_size`] is set.
+    pub fn to_text(&self) -> String {
+        let length = |pixels: f64, mm: Option<f64>| match mm {
+            Some(mm) => format!("{:.2} m", mm / 1000.),
+            None => format!("{pixels:.0} px"),
+        };
+        let mut out = String::new();
+        writeln!(out, "Strands:           {}", self.strands).unwrap();
+        writeln!(out, "Lines:             {}", self.lines).unwrap();
+        match self.frame_pegs {
+            Some(frame_pegs) => writeln!(
+                out,
+                "Unique pegs:       {} of {frame_pegs}",
+                self.unique_pegs
+            )
+            .unwrap(),
+            None => writeln!(out, "Unique pegs:       {}", self.unique_pegs).unwrap(),
+        }
+        writeln!(
+            out,
+            "Thread length:     {}",
+            length(self.total_length, self.total_length_mm)
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "Average length:    {}",
+            length(self.average_length, self.average_length_mm)
+        )
+        .unwrap();
+        writeln!(out, "Longest run:       {} lines", self.longest_run).unwrap();
+        writeln!(
+            out,
+            "Repeated segments: {} ({} lines)",
+            self.repeated_segments.len(),
+            self.repeated_lines()
+        )
+        .unwrap();
+        if let Some(bbox) = self.bounding_box {
+            writeln!(
+                out,
+                "Bounding box:      ({}, {}) to ({}, {}), {}x{} px",
+                bbox.min_x,
+                bbox.min_y,
+                bbox.max_x,
+                bbox.max_y,
+                bbox.width(),
+                bbox.height()
+            )
+            .unwrap();
+        }
+
+        writeln!(out, "\nPeg usage:\n{:>6} {:>6}", "visits", "pegs").unwrap();
+        for (visits, pegs) in self.usage_histogram() {
+            writeln!(out, "{visits:>6} {pegs:>6}").unwrap();
+        }
+
+        if !self.repeated_segments.is_empty() {
+            writeln!(
+                out,
+                "\nMost repeated segments:\n{:>13} {:>6}",
+                "pegs", "count"
+            )
+            .unwrap();
+            for segment in self.repeated_segments.iter().take(TEXT_REPEATED_SEGMENTS) {
+                let pegs = format!("{} - {}", segment.pegs.0, segment.pegs.1);
+                writeln!(out, "{pegs:>13} {:>6}", segment.count).unwrap();
+            }
+        }
+        out
+    }
+}
+
+/// Largest number of consecutive steps of a peg order, lines, which don't revisit a peg, with a
+/// sliding window over the peg numbers.
+fn longest_run_without_revisit(peg_ids: impl Iterator<Item = usize>) -> usize {
+    let peg_ids: Vec<usize> = peg_ids.collect();
+    let mut window = HashSet::new();
+    let mut start = 0;
+    let mut longest = 0;
+    for (end, id) in peg_ids.iter().enumerate() {
+        while window.contains(id) {
+            window.remove(&peg_ids[start]);
+            start += 1;
+        }
+        window.insert(*id);
+        // the window holds end - start + 1 pegs, joined by end - start lines
+        longest = longest.max(end - start);
+    }
+    longest
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::blueprint::Strand;
+    use crate::instructions::Instructions;
+    use crate::peg::{Peg, PegLayout};
+
+    #[test]
+    fn longest_run() {
+        assert_eq!(longest_run_without_revisit([].into_iter()), 0);
+        assert_eq!(longest_run_without_revisit([1].into_iter()), 0);
+        assert_eq!(longest_run_without_revisit([1, 2, 3, 4].into_iter()), 3);
+        assert_eq!(
+            longest_run_without_revisit([1, 2, 1, 3, 4, 5, 3].into_iter()),
+            4
+        );
+    }
+
+    #[test]
+    fn blueprint_stats() {
+        let pegs: PegLayout = [(0, 0), (10, 0), (10, 10), (0, 10)]
+            .into_iter()
+            .map(|(x, y)| Peg::new(x, y))
+            .collect();
+        let [a, b, c, _] = [pegs[0], pegs[1], pegs[2], pegs[3]];
+        let mut bp = Blueprint::from_strands(
+            vec![
+                Strand::new((0, 0, 0), vec![a, b, a, b, c]),
+                Strand::new((255, 0, 0), vec![c, b]),
+            ],
+            20,
+            20,
+            None,
+            1.,
+            false,
+        );
+        bp.pegs = pegs.clone();
+        bp.pegs.push(Peg::new(5, 15));
+        bp.frame_size = Some(2000.);
+
+        let stats = Stats::new(&bp);
+        assert_eq!(stats.strands, 2);
+        assert_eq!(stats.lines, 5);
+        assert_eq!(stats.unique_pegs, 3);
+        assert_eq!(stats.frame_pegs, Some(5));
+        // numbered like the instructions, clockwise from the top right peg
+        let numbers = Instructions::new(&bp, 10).pegs;
+        let number = |peg: &Peg| {
+            1 + numbers
+                .iter()
+                .position(|other| (other.x, other.y) == (peg.x, peg.y))
+                .unwrap()
+        };
+        let (a, b, c) = (number(&a), number(&b), number(&c));
+        assert_eq!((a, b, c), (5, 1, 2));
+        assert_eq!(stats.peg_usage[&a], 2);
+        assert_eq!(stats.peg_usage[&b], 3);
+        assert_eq!(
+            stats.peg_usage.keys().copied().collect::<Vec<_>>(),
+            vec![1, 2, 5]
+        );
+        assert_eq!(stats.usage_histogram(), vec![(2, 2), (3, 1)]);
+        assert_eq!(stats.total_length, 50.);
+        assert_eq!(stats.average_length, 10.);
+        assert_eq!(stats.longest_run, 2);
+
+        // a - b 3 times, b - c twice across strands, in either direction
+        assert_eq!(
+            stats.repeated_segments,
+            vec![
+                RepeatedSegment {
+                    pegs: (b, a),
+                    count: 3
+                },
+                RepeatedSegment {
+                    pegs: (b, c),
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(stats.repeated_lines(), 3);
+        assert_eq!(
+            stats.bounding_box,
+            Some(BoundingBox {
+                min_x: 0,
+                min_y: 0,
+                max_x: 10,
+                max_y: 10
+            })
+        );
+
+        assert_eq!(stats.total_length_mm, Some(5000.));
+        assert_eq!(stats.average_length_mm, Some(1000.));
+        let text = stats.to_text();
+        assert!(text.contains("Thread length:     5.00 m"));
+        assert!(text.contains("Unique pegs:       3 of 5"));
+
+        let empty = Stats::new(&Blueprint::new(vec![], 10, 10, None, 1., false));
+        assert_eq!(empty.lines, 0);
+        assert_eq!(empty.average_length, 0.);
+        assert_eq!(empty.bounding_box, None);
+        assert_eq!(empty.total_length_mm, None);
+    }
+}
@@ -0,0 +1,417 @@
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use svg::node::element::{Circle, Line as LineSVG, Text};
+use svg::node::Text as TextNode;
+use svg::{Document, Node};
+
+use crate::blueprint::Blueprint;
+use crate::instructions::{frame_order, numbered_pegs};
+use crate::peg::Peg;
+
+/// Height of the peg number labels, in mm.
+const LABEL_SIZE: f64 = 3.;
+/// Stroke width of the svg template, in mm.
+const STROKE_WIDTH: f64 = 0.1;
+
+/// Layer of a [`Shape`], the dxf layer name and the svg stroke color.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Layer {
+    Pegs,
+    Labels,
+    Frame,
+    Marks,
+}
+
+impl Layer {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Pegs => "PEGS",
+            Self::Labels => "LABELS",
+            Self::Frame => "FRAME",
+            Self::Marks => "MARKS",
+        }
+    }
+
+    fn color(&self) -> &'static str {
+        match self {
+            Self::Pegs => "red",
+            Self::Labels | Self::Frame => "black",
+            Self::Marks => "blue",
+        }
+    }
+}
+
+/// A drawing primitive of the [`Template`], in mm from the top left corner of the sheet.
+#[derive(Debug, Clone, PartialEq)]
+enum Shape {
+    Circle {
+        layer: Layer,
+        x: f64,
+        y: f64,
+        radius: f64,
+    },
+    Line {
+        layer: Layer,
+        start: (f64, f64),
+        end: (f64, f64),
+    },
+    Label {
+        x: f64,
+        y: f64,
+        content: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+/// A peg board template, to drill or laser cut the frame. Holds the hole of every [`Peg`] with its
+/// number, the frame outline and registration marks, in mm.
+pub struct Template {
+    /// The pegs, peg number `n` is at index `n - 1`, numbered the same as the build
+    /// [`Instructions`](crate::instructions::Instructions).
+    pub pegs: Vec<Peg>,
+    /// Width of the frame, in pixels.
+    pub width: u32,
+    /// Height of the frame, in pixels.
+    pub height: u32,
+    /// Physical width of the frame, in mm, the height is scaled accordingly.
+    pub frame_size: f64,
+    /// Diameter of the peg holes, in mm.
+    pub peg_diameter: f64,
+    /// Margin around the frame, in mm, the registration marks are drawn in it.
+    pub margin: f64,
+}
+
+impl Template {
+    /// Create a new [`Template`].
+    ///
+    /// # Arguments
+    ///
+    /// * `pegs`: The [`Pegs`](Peg), deduplicated and numbered clockwise around the frame,
+    ///   starting from the top, followed by the interior pegs row by row.
+    /// * `width`: Width of the frame, in pixels.
+    /// * `height`: Height of the frame, in pixels.
+    /// * `frame_size`: Physical width of the frame, in mm.
+    pub fn new(pegs: &[Peg], width: u32, height: u32, frame_size: f64) -> Self {
+        Self::numbered(frame_order(pegs), width, height, frame_size)
+    }
+
+    /// Create a [`Template`] of already numbered `pegs`, kept in order.
+    fn numbered(pegs: Vec<Peg>, width: u32, height: u32, frame_size: f64) -> Self {
+        Self {
+            pegs,
+            width,
+            height,
+            frame_size,
+            peg_diameter: 2.,
+            margin: 15.,
+        }
+    }
+
+    /// Create the [`Template`] of a [`Blueprint`], with its pegs numbered like its build
+    /// [`Instructions`](crate::instructions::Instructions). [`None`] if the
+    /// [`Blueprint::frame_size`] is not set.
+    pub fn from_blueprint(blueprint: &Blueprint) -> Option<Self> {
+        let frame_size = blueprint.frame_size?;
+        Some(Self::numbered(
+            numbered_pegs(blueprint),
+            blueprint.width,
+            blueprint.height,
+            frame_size,
+        ))
+    }
+
+    /// Size of a pixel in mm.
+    pub fn mm_per_pixel(&self) -> f64 {
+        self.frame_size / self.width as f64
+    }
+
+    /// Physical size of the frame, in mm.
+    pub fn frame_dimensions(&self) -> (f64, f64) {
+        (self.frame_size, self.height as f64 * self.mm_per_pixel())
+    }
+
+    /// Size of the sheet, the frame and its margin, in mm.
+    pub fn sheet_dimensions(&self) -> (f64, f64) {
+        let (width, height) = self.frame_dimensions();
+        (width + 2. * self.margin, height + 2. * self.margin)
+    }
+
+    /// Position of a [`Peg`] on the sheet, in mm.
+    fn position(&self, peg: &Peg) -> (f64, f64) {
+        let scale = self.mm_per_pixel();
+        (
+            self.margin + peg.x as f64 * scale,
+            self.margin + peg.y as f64 * scale,
+        )
+    }
+
+    /// The drawing primitives of the [`Template`].
+    fn shapes(&self) -> Vec<Shape> {
+        let mut shapes = vec![];
+        let (frame_width, frame_height) = self.frame_dimensions();
+        let (sheet_width, sheet_height) = self.sheet_dimensions();
+
+        // frame outline
+        let corners = [
+            (self.margin, self.margin),
+            (self.margin + frame_width, self.margin),
+            (self.margin + frame_width, self.margin + frame_height),
+            (self.margin, self.margin + frame_height),
+        ];
+        for (index, start) in corners.iter().enumerate() {
+            shapes.push(Shape::Line {
+                layer: Layer::Frame,
+                start: *start,
+                end: corners[(index + 1) % corners.len()],
+            });
+        }
+
+        // registration marks, a circled cross in each corner of the margin
+        let mark_size = self.margin / 4.;
+        for (x, y) in [
+            (self.margin / 2., self.margin / 2.),
+            (sheet_width - self.margin / 2., self.margin / 2.),
+            (
+                sheet_width - self.margin / 2.,
+                sheet_height - self.margin / 2.,
+            ),
+            (self.margin / 2., sheet_height - self.margin / 2.),
+        ] {
+            shapes.push(Shape::Circle {
+                layer: Layer::Marks,
+                x,
+                y,
+                radius: mark_size / 2.,
+            });
+            shapes.push(Shape::Line {
+                layer: Layer::Marks,
+                start: (x - mark_size, y),
+                end: (x + mark_size, y),
+            });
+            shapes.push(Shape::Line {
+                layer: Layer::Marks,
+                start: (x, y - mark_size),
+                end: (x, y + mark_size),
+            });
+        }
+
+        // peg holes, labeled on the outer side, away from the center of the frame
+        let (center_x, center_y) = (
+            self.margin + frame_width / 2.,
+            self.margin + frame_height / 2.,
+        );
+        let label_offset = self.peg_diameter / 2. + LABEL_SIZE;
+        for (index, peg) in self.pegs.iter().enumerate() {
+            let (x, y) = self.position(peg);
+            shapes.push(Shape::Circle {
+                layer: Layer::Pegs,
+                x,
+                y,
+                radius: self.peg_diameter / 2.,
+            });
+            let (dx, dy) = (x - center_x, y - center_y);
+            let norm = dx.hypot(dy);
+            let (dx, dy) = if norm > 0. {
+                (dx / norm, dy / norm)
+            } else {
+                (0., -1.)
+            };
+            shapes.push(Shape::Label {
+                x: x + dx * label_offset,
+                y: y + dy * label_offset,
+                content: (index + 1).to_string(),
+            });
+        }
+        shapes
+    }
+
+    /// Render the [`Template`] as a svg, with dimensions in mm.
+    pub fn render_svg(&self) -> Document {
+        let (sheet_width, sheet_height) = self.sheet_dimensions();
+        let mut document = Document::new()
+            .set("viewBox", (0, 0, sheet_width, sheet_height))
+            .set("width", format!("{sheet_width}mm"))
+            .set("height", format!("{sheet_height}mm"));
+
+        for shape in self.shapes() {
+            match shape {
+                Shape::Circle {
+                    layer,
+                    x,
+                    y,
+                    radius,
+                } => document.append(
+                    Circle::new()
+                        .set("cx", x)
+                        .set("cy", y)
+                        .set("r", radius)
+                        .set("fill", "none")
+                        .set("stroke", layer.color())
+                        .set("stroke-width", STROKE_WIDTH),
+                ),
+                Shape::Line { layer, start, end } => document.append(
+                    LineSVG::new()
+                        .set("x1", start.0)
+                        .set("y1", start.1)
+                        .set("x2", end.0)
+                        .set("y2", end.1)
+                        .set("stroke", layer.color())
+                        .set("stroke-width", STROKE_WIDTH),
+                ),
+                Shape::Label { x, y, content } => document.append(
+                    Text::new("")
+                        .set("x", x)
+                        .set("y", y)
+                        .set("font-size", LABEL_SIZE)
+                        .set("font-family", "monospace")
+                        .set("text-anchor", "middle")
+                        .set("dominant-baseline", "middle")
+                        .set("fill", Layer::Labels.color())
+                        .add(TextNode::new(content)),
+                ),
+            }
+        }
+        document
+    }
+
+    /// Render the [`Template`] as an ascii dxf drawing, in mm, with one layer for the pegs, the
+    /// labels, the frame and the registration marks.
+    pub fn to_dxf(&self) -> String {
+        let (_, sheet_height) = self.sheet_dimensions();
+        // the dxf y axis points up
+        let flip = |y: f64| sheet_height - y;
+
+        let mut out = String::new();
+        // $INSUNITS 4: millimeters
+        out.push_str("0\nSECTION\n2\nHEADER\n9\n$INSUNITS\n70\n4\n0\nENDSEC\n");
+        out.push_str("0\nSECTION\n2\nENTITIES\n");
+        for shape in self.shapes() {
+            match shape {
+                Shape::Circle {
+                    layer,
+                    x,
+                    y,
+                    radius,
+                } => {
+                    let _ = write!(
+                        out,
+                        "0\nCIRCLE\n8\n{}\n10\n{x:.4}\n20\n{:.4}\n30\n0.0\n40\n{radius:.4}\n",
+                        layer.name(),
+                        flip(y)
+                    );
+                }
+                Shape::Line { layer, start, end } => {
+                    let _ = write!(
+                        out,
+                        "0\nLINE\n8\n{}\n10\n{:.4}\n20\n{:.4}\n30\n0.0\n11\n{:.4}\n21\n{:.4}\n31\n0.0\n",
+                        layer.name(),
+                        start.0,
+                        flip(start.1),
+                        end.0,
+                        flip(end.1)
+                    );
+                }
+                Shape::Label { x, y, content } => {
+                    // centered horizontally (72) and vertically (73) on the alignment point
+                    let y = flip(y);
+                    let _ = write!(
+                        out,
+                        "0\nTEXT\n8\n{}\n10\n{x:.4}\n20\n{y:.4}\n30\n0.0\n40\n{LABEL_SIZE:.4}\n1\n{content}\n72\n1\n11\n{x:.4}\n21\n{y:.4}\n31\n0.0\n73\n2\n",
+                        Layer::Labels.name()
+                    );
+                }
+            }
+        }
+        out.push_str("0\nENDSEC\n0\nEOF\n");
+        out
+    }
+
+    /// Write the [`Template`] to file.
+    ///
+    /// # Arguments:
+    ///
+    /// * `path`: Output file path, either svg or dxf.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .ok_or("Could not determine extension.")?
+            .to_string_lossy();
+        match extension.as_ref() {
+            "svg" => svg::save(path, &self.render_svg())?,
+            "dxf" => std::fs::write(path, self.to_dxf())?,
+            _ => return Err(format!("Unsupported template format '{extension}'.").into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn template_from_blueprint() {
+        let pegs = [
+            Peg::new(0, 0),
+            Peg::new(100, 0),
+            Peg::new(100, 50),
+            Peg::new(0, 50),
+        ];
+        let mut bp = Blueprint::new(
+            vec![pegs[0], pegs[2], pegs[1], pegs[3]],
+            100,
+            50,
+            None,
+            1.,
+            false,
+        );
+        assert!(bp.template().is_none());
+
+        bp.frame_size = Some(200.);
+        let template = bp.template().unwrap();
+        assert_eq!(template.frame_dimensions(), (200., 100.));
+        assert_eq!(template.sheet_dimensions(), (230., 130.));
+        let numbers: Vec<(u32, u32)> = template.pegs.iter().map(|peg| (peg.x, peg.y)).collect();
+        assert_eq!(numbers, vec![(100, 0), (100, 50), (0, 50), (0, 0)]);
+        // the bottom right peg, with the margin
+        assert_eq!(template.position(&template.pegs[1]), (215., 115.));
+
+        // the pegs missing from the frame's pegs are numbered after them, like the instructions
+        bp.pegs = pegs[..3].iter().copied().collect();
+        let template = bp.template().unwrap();
+        let numbers: Vec<(u32, u32)> = template.pegs.iter().map(|peg| (peg.x, peg.y)).collect();
+        assert_eq!(numbers, vec![(100, 0), (100, 50), (0, 0), (0, 50)]);
+        let instructions = bp.instructions(10);
+        assert!(template
+            .pegs
+            .iter()
+            .zip(&instructions.pegs)
+            .all(|(a, b)| (a.x, a.y) == (b.x, b.y)));
+    }
+
+    #[test]
+    fn template_formats() {
+        let pegs = vec![Peg::new(10, 10), Peg::new(90, 10), Peg::new(50, 90)];
+        let template = Template::new(&pegs, 100, 100, 500.);
+
+        let svg = template.render_svg().to_string();
+        assert!(svg.contains("width=\"530mm\""));
+        // 3 pegs and 4 registration marks
+        assert_eq!(svg.matches("<circle").count(), 7);
+        assert_eq!(svg.matches("<text").count(), 3);
+        // frame outline and the crosses of the registration marks
+        assert_eq!(svg.matches("<line").count(), 4 + 8);
+
+        let dxf = template.to_dxf();
+        assert_eq!(dxf.matches("\nCIRCLE\n8\nPEGS\n").count(), 3);
+        assert_eq!(dxf.matches("\nCIRCLE\n8\nMARKS\n").count(), 4);
+        assert_eq!(dxf.matches("\nLINE\n8\nFRAME\n").count(), 4);
+        assert_eq!(dxf.matches("\nTEXT\n").count(), 3);
+        assert!(dxf.ends_with("0\nEOF\n"));
+        // first peg, top left, at 15 + 10 * 5 = 65mm from the left and from the top, y flipped
+        assert!(dxf.contains("10\n65.0000\n20\n465.0000\n"));
+    }
+}
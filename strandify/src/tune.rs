@@ -0,0 +1,517 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::Write as _;
+use std::path::Path;
+
+use image::GrayImage;
+use log::{debug, info};
+use rand::seq::SliceRandom;
+use rand::Rng;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::blueprint::Blueprint;
+use crate::metrics::Metrics;
+use crate::pather::{LineMode, Pather, PatherConfig};
+use crate::peg::{Peg, Yarn};
+use crate::utils;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// The values of the tuned [`PatherConfig`] parameters of a [`Trial`].
+pub struct Candidate {
+    /// [`Yarn::opacity`] of the [`PatherConfig::yarn`].
+    pub line_opacity: f64,
+    /// [`Yarn::width`] of the [`PatherConfig::yarn`].
+    pub line_width: f32,
+    /// Number of [`Pegs`](Peg).
+    pub peg_number: usize,
+    /// [`PatherConfig::iterations`].
+    pub iterations: usize,
+}
+
+impl Candidate {
+    /// The [`PatherConfig`] of this [`Candidate`], the other parameters are taken from `config`.
+    pub fn config(&self, config: &PatherConfig) -> PatherConfig {
+        PatherConfig {
+            iterations: self.iterations,
+            yarn: Yarn::new(self.line_width, self.line_opacity, config.yarn.color),
+            ..config.clone()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The values to explore for each tuned parameter, see [`Candidate`].
+pub struct SearchSpace {
+    /// [`Candidate::line_opacity`] values.
+    pub line_opacity: Vec<f64>,
+    /// [`Candidate::line_width`] values.
+    pub line_width: Vec<f32>,
+    /// [`Candidate::peg_number`] values.
+    pub peg_number: Vec<usize>,
+    /// [`Candidate::iterations`] values.
+    pub iterations: Vec<usize>,
+}
+
+impl Default for SearchSpace {
+    fn default() -> Self {
+        Self {
+            line_opacity: vec![0.05, 0.1, 0.2],
+            line_width: vec![2.],
+            peg_number: vec![288],
+            iterations: vec![2000, 4000],
+        }
+    }
+}
+
+impl SearchSpace {
+    /// All the combinations of the parameter values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::tune::SearchSpace;
+    ///
+    /// let space = SearchSpace {
+    ///     line_opacity: vec![0.1, 0.2],
+    ///     line_width: vec![1., 2., 3.],
+    ///     peg_number: vec![100],
+    ///     iterations: vec![500, 1000],
+    /// };
+    /// assert_eq!(space.grid().len(), 12);
+    /// ```
+    pub fn grid(&self) -> Vec<Candidate> {
+        let mut candidates = vec![];
+        for &peg_number in &self.peg_number {
+            for &line_width in &self.line_width {
+                for &line_opacity in &self.line_opacity {
+                    for &iterations in &self.iterations {
+                        candidates.push(Candidate {
+                            line_opacity,
+                            line_width,
+                            peg_number,
+                            iterations,
+                        });
+                    }
+                }
+            }
+        }
+        candidates
+    }
+
+    /// Sample `n` random [`Candidates`](Candidate). The line opacity and iterations are drawn
+    /// uniformly between the smallest and largest of their values, the line width and peg number
+    /// are picked among their values, so that the [`Candidates`](Candidate) share their lines,
+    /// see [`Tuner`].
+    pub fn random<R: Rng>(&self, n: usize, rng: &mut R) -> Vec<Candidate> {
+        fn range<T: PartialOrd + Copy>(values: &[T]) -> Option<(T, T)> {
+            let mut values = values.iter().copied();
+            let first = values.next()?;
+            Some(values.fold((first, first), |(min, max), value| {
+                (
+                    if value < min { value } else { min },
+                    if value > max { value } else { max },
+                )
+            }))
+        }
+        let (Some(opacity), Some(iterations)) =
+            (range(&self.line_opacity), range(&self.iterations))
+        else {
+            return vec![];
+        };
+        if self.line_width.is_empty() || self.peg_number.is_empty() {
+            return vec![];
+        }
+
+        (0..n)
+            .map(|_| Candidate {
+                line_opacity: rng.gen_range(opacity.0..=opacity.1),
+                line_width: *self.line_width.choose(rng).unwrap(),
+                peg_number: *self.peg_number.choose(rng).unwrap(),
+                iterations: rng.gen_range(iterations.0..=iterations.1),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// The [`Metrics`] used to rank the [`Trials`](Trial).
+pub enum Objective {
+    /// Lowest [`Metrics::mse`].
+    Mse,
+    /// Highest [`Metrics::psnr`].
+    Psnr,
+    /// Highest [`Metrics::ssim`].
+    Ssim,
+    /// Lowest [`Metrics::blurred_mse`].
+    #[default]
+    BlurredMse,
+}
+
+/// Names of the [`Objectives`](Objective), see [`Objective::from_name`].
+pub const OBJECTIVE_NAMES: [&str; 4] = ["mse", "psnr", "ssim", "blurred-mse"];
+
+impl Objective {
+    /// Get an [`Objective`] from its name.
+    ///
+    /// # Arguments
+    ///
+    /// * `name`: One of [`OBJECTIVE_NAMES`].
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "mse" => Ok(Self::Mse),
+            "psnr" => Ok(Self::Psnr),
+            "ssim" => Ok(Self::Ssim),
+            "blurred-mse" => Ok(Self::BlurredMse),
+            _ => Err(format!("Unrecognized objective '{name}'")),
+        }
+    }
+
+    /// The loss of the `metrics` for this [`Objective`], lower is better.
+    pub fn loss(&self, metrics: &Metrics) -> f64 {
+        match self {
+            Self::Mse => metrics.mse,
+            Self::Psnr => -metrics.psnr,
+            Self::Ssim => -metrics.ssim,
+            Self::BlurredMse => metrics.blurred_mse,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// The result of the pathing with a [`Candidate`].
+pub struct Trial {
+    /// The tuned parameters.
+    #[serde(flatten)]
+    pub candidate: Candidate,
+    /// The [`Metrics`] of the [`Trial::blueprint`] compared to the source image.
+    #[serde(flatten)]
+    pub metrics: Metrics,
+    /// The computed [`Blueprint`].
+    #[serde(skip)]
+    pub blueprint: Blueprint,
+}
+
+#[derive(Debug)]
+/// Parameter sweep of the [`PatherConfig`], paths an image with each [`Candidate`] and scores
+/// the resulting [`Blueprints`](Blueprint) against it, see [`Metrics`].
+///
+/// The [`Candidates`](Candidate) with the same [`Candidate::peg_number`] and
+/// [`Candidate::line_width`] share their lines, which are computed once, see
+/// [`Pather::with_config`]. The groups, and the pathing of the [`Candidates`](Candidate) of each
+/// group, run in parallel.
+pub struct Tuner<F> {
+    /// Input grayscale image, to path and to score against.
+    pub image: GrayImage,
+    /// Creates the [`Pegs`](Peg) from their number.
+    pub pegs: F,
+    /// The base [`PatherConfig`], the tuned parameters are overwritten by each [`Candidate`].
+    pub config: PatherConfig,
+    /// The [`Yarn`] used to render the [`Blueprints`](Blueprint) when scoring them.
+    pub yarn: Yarn,
+    /// The [`Objective`] used to rank the [`Trials`](Trial).
+    pub objective: Objective,
+}
+
+impl<F> Tuner<F>
+where
+    F: Fn(usize) -> Vec<Peg> + Sync,
+{
+    /// Creates a new [`Tuner`].
+    pub fn new(image: GrayImage, pegs: F, config: PatherConfig, yarn: Yarn) -> Self {
+        Self {
+            image,
+            pegs,
+            config,
+            yarn,
+            objective: Objective::default(),
+        }
+    }
+
+    /// Path and score all the `candidates`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use strandify::pather::PatherConfig;
+    /// use strandify::peg::{shape, Yarn};
+    /// use strandify::tune::{SearchSpace, Tuner};
+    ///
+    /// let image = image::GrayImage::from_fn(64, 64, |x, _| image::Luma([(x * 4) as u8]));
+    /// let tuner = Tuner::new(
+    ///     image,
+    ///     |n| shape::circle((32, 32), 30, n),
+    ///     PatherConfig::default(),
+    ///     Yarn::default(),
+    /// );
+    /// let space = SearchSpace {
+    ///     line_opacity: vec![0.1, 0.5],
+    ///     line_width: vec![1.],
+    ///     peg_number: vec![16, 32],
+    ///     iterations: vec![20],
+    /// };
+    /// let report = tuner.run(&space.grid()).unwrap();
+    /// assert_eq!(report.trials.len(), 4);
+    /// let best = report.best().unwrap();
+    /// ```
+    pub fn run(&self, candidates: &[Candidate]) -> Result<Report, Box<dyn Error>> {
+        // the candidates sharing the same lines
+        let mut groups: BTreeMap<(usize, u32), Vec<Candidate>> = BTreeMap::new();
+        for candidate in candidates {
+            groups
+                .entry((candidate.peg_number, candidate.line_width.to_bits()))
+                .or_default()
+                .push(*candidate);
+        }
+        info!(
+            "Tuning {} candidates, {} line caches",
+            candidates.len(),
+            groups.len()
+        );
+
+        let pbar =
+            utils::pbar(candidates.len() as u64, !self.config.progress_bar)?.with_message("Tuning");
+        let groups: Vec<Vec<Candidate>> = groups.into_values().collect();
+        let mut trials: Vec<Trial> = utils::iter_or_par_iter!(groups, into)
+            .map(|group| {
+                let config = PatherConfig {
+                    progress_bar: false,
+                    ..group[0].config(&self.config)
+                };
+                let mut pather =
+                    Pather::new(self.image.clone(), (self.pegs)(group[0].peg_number), config);
+                if pather.config.line_mode == LineMode::Eager {
+                    pather
+                        .populate_line_cache()
+                        .map_err(|err| err.to_string())?;
+                }
+
+                utils::iter_or_par_iter!(group, into)
+                    .map(|candidate| {
+                        debug!("Pathing {candidate:?}");
+                        let mut pather = pather.with_config(candidate.config(&pather.config));
+                        let blueprint = pather.compute().map_err(|err| err.to_string())?;
+                        let metrics = blueprint
+                            .score(&self.image, &self.yarn)
+                            .map_err(|err| err.to_string())?;
+                        pbar.inc(1);
+                        Ok(Trial {
+                            candidate,
+                            metrics,
+                            blueprint,
+                        })
+                    })
+                    .collect::<Result<Vec<_>, String>>()
+            })
+            .collect::<Result<Vec<_>, String>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        pbar.finish_and_clear();
+
+        trials.sort_by(|a, b| {
+            self.objective
+                .loss(&a.metrics)
+                .total_cmp(&self.objective.loss(&b.metrics))
+        });
+        Ok(Report {
+            objective: self.objective,
+            trials,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// The [`Trials`](Trial) of a [`Tuner`], ranked by their [`Objective`].
+pub struct Report {
+    /// The [`Objective`] used to rank the [`Trials`](Trial).
+    pub objective: Objective,
+    /// The [`Trials`](Trial), best first.
+    pub trials: Vec<Trial>,
+}
+
+impl Report {
+    /// The best [`Trial`], [`None`] if there are no [`Trials`](Trial).
+    pub fn best(&self) -> Option<&Trial> {
+        self.trials.first()
+    }
+
+    /// Format the [`Report`] as a text table, best [`Trial`] first.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(
+            out,
+            "{:>4} {:>12} {:>10} {:>10} {:>10} {:>10} {:>9} {:>7} {:>11}",
+            "rank",
+            "line_opacity",
+            "line_width",
+            "peg_number",
+            "iterations",
+            "mse",
+            "psnr",
+            "ssim",
+            "blurred_mse"
+        )
+        .unwrap();
+        for (rank, trial) in self.trials.iter().enumerate() {
+            writeln!(
+                out,
+                "{:>4} {:>12.3} {:>10.2} {:>10} {:>10} {:>10.6} {:>9.2} {:>7.4} {:>11.6}",
+                rank + 1,
+                trial.candidate.line_opacity,
+                trial.candidate.line_width,
+                trial.candidate.peg_number,
+                trial.candidate.iterations,
+                trial.metrics.mse,
+                trial.metrics.psnr,
+                trial.metrics.ssim,
+                trial.metrics.blurred_mse
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Format the [`Report`] as CSV, with one row per [`Trial`], best first.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from(
+            "rank,line_opacity,line_width,peg_number,iterations,mse,psnr,ssim,blurred_mse\n",
+        );
+        for (rank, trial) in self.trials.iter().enumerate() {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{}",
+                rank + 1,
+                trial.candidate.line_opacity,
+                trial.candidate.line_width,
+                trial.candidate.peg_number,
+                trial.candidate.iterations,
+                trial.metrics.mse,
+                trial.metrics.psnr,
+                trial.metrics.ssim,
+                trial.metrics.blurred_mse
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    /// Write the [`Report`] to file.
+    ///
+    /// # Arguments:
+    ///
+    /// * `path`: Output file path, either `txt`, `csv` or `json`.
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .ok_or("Could not determine extension.")?
+            .to_string_lossy();
+        match extension.as_ref() {
+            "txt" => std::fs::write(path, self.to_text())?,
+            "csv" => std::fs::write(path, self.to_csv())?,
+            "json" => std::fs::write(path, serde_json::to_string_pretty(self)?)?,
+            _ => return Err(format!("Unsupported report format '{extension}'.").into()),
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::peg::shape;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn search_space_random() {
+        let space = SearchSpace {
+            line_opacity: vec![0.2, 0.05, 0.1],
+            line_width: vec![2.],
+            peg_number: vec![100, 300],
+            iterations: vec![1000],
+        };
+        let candidates = space.random(50, &mut StdRng::seed_from_u64(0));
+        assert_eq!(candidates.len(), 50);
+        for candidate in &candidates {
+            assert!((0.05..=0.2).contains(&candidate.line_opacity));
+            assert_eq!(candidate.line_width, 2.);
+            assert!([100, 300].contains(&candidate.peg_number));
+            assert_eq!(candidate.iterations, 1000);
+        }
+        // the peg numbers are picked among the values, sharing their lines
+        assert!(candidates
+            .iter()
+            .any(|candidate| candidate.peg_number == 100));
+        assert!(candidates
+            .iter()
+            .any(|candidate| candidate.peg_number == 300));
+
+        let empty = SearchSpace {
+            iterations: vec![],
+            ..space
+        };
+        assert!(empty.grid().is_empty());
+        assert!(empty.random(5, &mut StdRng::seed_from_u64(0)).is_empty());
+    }
+
+    #[test]
+    fn objective() {
+        let metrics = Metrics {
+            mse: 0.1,
+            psnr: 10.,
+            ssim: 0.5,
+            blurred_mse: 0.05,
+        };
+        for name in OBJECTIVE_NAMES {
+            assert!(Objective::from_name(name).is_ok());
+        }
+        assert!(Objective::from_name("l1").is_err());
+        assert_eq!(Objective::Psnr.loss(&metrics), -10.);
+        assert_eq!(Objective::BlurredMse.loss(&metrics), 0.05);
+    }
+
+    #[test]
+    fn tuner_run() {
+        // dark left half
+        let image = GrayImage::from_fn(64, 64, |x, _| image::Luma([if x < 32 { 0 } else { 255 }]));
+        let mut tuner = Tuner::new(
+            image,
+            |n| shape::circle((32, 32), 30, n),
+            PatherConfig::default(),
+            Yarn::new(1., 0.5, (0, 0, 0)),
+        );
+        tuner.objective = Objective::Mse;
+        let space = SearchSpace {
+            line_opacity: vec![0.2],
+            line_width: vec![1., 2.],
+            peg_number: vec![24],
+            iterations: vec![1, 40],
+        };
+        let report = tuner.run(&space.grid()).unwrap();
+        assert_eq!(report.trials.len(), 4);
+        for trial in &report.trials {
+            assert_eq!(trial.blueprint.pegs.len(), 24);
+            assert_eq!(
+                trial.blueprint.peg_order.len(),
+                trial.candidate.iterations + 1
+            );
+        }
+        assert!(report
+            .trials
+            .windows(2)
+            .all(|trials| trials[0].metrics.mse <= trials[1].metrics.mse));
+        assert_eq!(report.best().unwrap().candidate.iterations, 40);
+
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.starts_with("rank,line_opacity,line_width,peg_number,iterations,mse"));
+        let json: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&report).unwrap()).unwrap();
+        assert_eq!(json["objective"], "mse");
+        assert_eq!(json["trials"][0]["iterations"], 40);
+    }
+}
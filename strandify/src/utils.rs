@@ -1,8 +1,11 @@
 use std::{error::Error, f64::consts::PI, path::Path};
 
-use crate::peg::Peg;
+use image::GrayImage;
 use indicatif::{ProgressBar, ProgressStyle};
 use log::debug;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 macro_rules! iter_or_par_iter {
     ($iter:expr) => {{
@@ -135,6 +138,493 @@ pub fn rectangle_coords(
     )
 }
 
+/// Number of straight segments used to flatten an ellipse.
+const ELLIPSE_SEGMENTS: usize = 1024;
+
+/// Number of straight segments used to flatten each curve of a svg path.
+const CURVE_SEGMENTS: usize = 32;
+
+/// Points joined by straight segments.
+type Polyline = Vec<(f64, f64)>;
+
+/// Compute the coords of points evenly spaced by arc length around an ellipse, starting from the
+/// rightmost point.
+///
+/// # Arguments
+///
+/// * (`center_x`,` center_y`): the coords of the center point
+/// * (`radius_x`, `radius_y`): the horizontal and vertical radii
+/// * `n_points`: the number of points
+pub fn ellipse_coords(
+    (center_x, center_y): (u32, u32),
+    (radius_x, radius_y): (u32, u32),
+    n_points: usize,
+) -> (Vec<u32>, Vec<u32>) {
+    let outline: Polyline = (0..=ELLIPSE_SEGMENTS)
+        .map(|i| {
+            let angle = 2. * PI * i as f64 / ELLIPSE_SEGMENTS as f64;
+            (
+                center_x as f64 + radius_x as f64 * angle.cos(),
+                center_y as f64 + radius_y as f64 * angle.sin(),
+            )
+        })
+        .collect();
+    polyline_coords(&[outline], n_points)
+}
+
+/// Compute the coords of points evenly spaced around a regular polygon, starting from its first
+/// vertex. When `n_points` is a multiple of `n_sides`, every vertex gets a point.
+///
+/// # Arguments
+///
+/// * (`center_x`,` center_y`): the coords of the center point
+/// * `radius`: the distance between the center and the vertices
+/// * `n_sides`: the number of sides, at least 3
+/// * `rotation`: the angle of the first vertex in radians, 0 is to the right of the center, and
+///   the angle increases clockwise
+/// * `n_points`: the number of points
+pub fn regular_polygon_coords(
+    (center_x, center_y): (u32, u32),
+    radius: u32,
+    n_sides: usize,
+    rotation: f64,
+    n_points: usize,
+) -> (Vec<u32>, Vec<u32>) {
+    let n_sides = n_sides.max(3);
+    let outline: Polyline = (0..=n_sides)
+        .map(|i| {
+            let angle = rotation + 2. * PI * i as f64 / n_sides as f64;
+            (
+                center_x as f64 + radius as f64 * angle.cos(),
+                center_y as f64 + radius as f64 * angle.sin(),
+            )
+        })
+        .collect();
+    polyline_coords(&[outline], n_points)
+}
+
+/// Compute the coords of points evenly spaced by arc length along a svg path, scaled to fit in a
+/// box while keeping its aspect ratio, and centered in it. The points are shared between the
+/// subpaths in proportion to their length.
+///
+/// # Arguments
+///
+/// * `path_data`: the svg path data, e.g. the `d` attribute of a `path` element
+/// * `top_left`: the coordinate of the top left corner of the box
+/// * (`width`, `height`): the dimensions of the box
+/// * `n_points`: the number of points
+pub fn svg_path_coords(
+    path_data: &str,
+    top_left: (u32, u32),
+    (width, height): (u32, u32),
+    n_points: usize,
+) -> Result<(Vec<u32>, Vec<u32>), Box<dyn Error>> {
+    let subpaths = flatten_svg_path(path_data)?;
+    let points = subpaths.iter().flatten();
+    let (min_x, max_x) = points
+        .clone()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (x, _)| {
+            (min.min(*x), max.max(*x))
+        });
+    let (min_y, max_y) = points.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (_, y)| {
+        (min.min(*y), max.max(*y))
+    });
+    let (path_width, path_height) = (max_x - min_x, max_y - min_y);
+    if path_width == 0. && path_height == 0. {
+        return Err("The svg path has no length.".into());
+    }
+
+    // a straight horizontal or vertical path only constrains one dimension
+    let scale = [(width, path_width), (height, path_height)]
+        .into_iter()
+        .filter(|(_, path_size)| *path_size > 0.)
+        .map(|(size, path_size)| size as f64 / path_size)
+        .fold(f64::INFINITY, f64::min);
+    let offset_x = top_left.0 as f64 + (width as f64 - path_width * scale) / 2.;
+    let offset_y = top_left.1 as f64 + (height as f64 - path_height * scale) / 2.;
+    let subpaths: Vec<Polyline> = subpaths
+        .into_iter()
+        .map(|subpath| {
+            subpath
+                .into_iter()
+                .map(|(x, y)| {
+                    (
+                        offset_x + (x - min_x) * scale,
+                        offset_y + (y - min_y) * scale,
+                    )
+                })
+                .collect()
+        })
+        .collect();
+    Ok(polyline_coords(&subpaths, n_points))
+}
+
+/// Parse svg path data and flatten it into polylines, one per subpath, with the curves split
+/// into [`CURVE_SEGMENTS`] straight segments.
+fn flatten_svg_path(path_data: &str) -> Result<Vec<Polyline>, Box<dyn Error>> {
+    let mut subpaths: Vec<Polyline> = vec![];
+    let mut current: Polyline = vec![];
+    let curve = |current: &mut Polyline, point: &dyn Fn(f64) -> (f64, f64)| {
+        current.extend((1..=CURVE_SEGMENTS).map(|i| point(i as f64 / CURVE_SEGMENTS as f64)));
+    };
+
+    for segment in svgtypes::SimplifyingPathParser::from(path_data) {
+        let segment = segment.map_err(|err| format!("Invalid svg path: {err}"))?;
+        let (x0, y0) = current.last().copied().unwrap_or_default();
+        match segment {
+            svgtypes::SimplePathSegment::MoveTo { x, y } => {
+                if current.len() > 1 {
+                    subpaths.push(std::mem::take(&mut current));
+                }
+                current = vec![(x, y)];
+            }
+            svgtypes::SimplePathSegment::LineTo { x, y } => current.push((x, y)),
+            svgtypes::SimplePathSegment::Quadratic { x1, y1, x, y } => {
+                curve(&mut current, &|t| {
+                    let (a, b, c) = ((1. - t).powi(2), 2. * (1. - t) * t, t * t);
+                    (a * x0 + b * x1 + c * x, a * y0 + b * y1 + c * y)
+                });
+            }
+            svgtypes::SimplePathSegment::CurveTo {
+                x1,
+                y1,
+                x2,
+                y2,
+                x,
+                y,
+            } => {
+                curve(&mut current, &|t| {
+                    let (a, b) = ((1. - t).powi(3), 3. * (1. - t).powi(2) * t);
+                    let (c, d) = (3. * (1. - t) * t * t, t.powi(3));
+                    (
+                        a * x0 + b * x1 + c * x2 + d * x,
+                        a * y0 + b * y1 + c * y2 + d * y,
+                    )
+                });
+            }
+            svgtypes::SimplePathSegment::ClosePath => {
+                if let Some(&start) = current.first() {
+                    current.push(start);
+                }
+            }
+        }
+    }
+    if current.len() > 1 {
+        subpaths.push(current);
+    }
+    if subpaths.is_empty() {
+        return Err("The svg path is empty.".into());
+    }
+    Ok(subpaths)
+}
+
+/// Compute the coords of points evenly spaced by arc length along polylines, the points are
+/// shared between the polylines in proportion to their length. The end of a polyline is
+/// skipped, so closed polylines don't get a duplicated point.
+fn polyline_coords(polylines: &[Polyline], n_points: usize) -> (Vec<u32>, Vec<u32>) {
+    let segments: Vec<_> = polylines
+        .iter()
+        .flat_map(|polyline| {
+            polyline
+                .iter()
+                .zip(polyline.iter().skip(1))
+                .map(|(a, b)| (*a, *b, (b.0 - a.0).hypot(b.1 - a.1)))
+        })
+        .collect();
+    let total_length: f64 = segments.iter().map(|(_, _, length)| length).sum();
+    if n_points == 0 || total_length == 0. {
+        return (vec![], vec![]);
+    }
+
+    let spacing = total_length / n_points as f64;
+    let mut points = Vec::with_capacity(n_points);
+    let mut start_length = 0.;
+    let mut segments = segments.iter().peekable();
+    for i in 0..n_points {
+        let target = i as f64 * spacing;
+        // move to the segment holding the target arc length
+        while let Some((_, _, length)) = segments.peek() {
+            if start_length + length > target {
+                break;
+            }
+            start_length += length;
+            segments.next();
+        }
+        let Some(((x0, y0), (x1, y1), length)) = segments.peek() else {
+            break;
+        };
+        let t = (target - start_length) / length;
+        points.push((
+            (x0 + t * (x1 - x0)).round().max(0.) as u32,
+            (y0 + t * (y1 - y0)).round().max(0.) as u32,
+        ));
+    }
+    points.into_iter().unzip()
+}
+
+/// Number of candidates drawn around each point of the [`poisson_disc_coords`] before giving up
+/// on it.
+const POISSON_DISC_CANDIDATES: usize = 30;
+
+/// Round points to pixel coords, the points are expected to be non negative.
+fn round_coords(points: impl IntoIterator<Item = (f64, f64)>) -> (Vec<u32>, Vec<u32>) {
+    points
+        .into_iter()
+        .map(|(x, y)| (x.round().max(0.) as u32, y.round().max(0.) as u32))
+        .unzip()
+}
+
+/// Compute the coords of the points of a grid filling a box, centered in it. Odd rows are
+/// shifted by half the spacing when `hexagonal`, and the rows are packed closer so that each
+/// point is at `spacing` of its 6 neighbours.
+fn grid_coords(
+    (x0, y0): (u32, u32),
+    (width, height): (u32, u32),
+    spacing: f64,
+    hexagonal: bool,
+) -> (Vec<u32>, Vec<u32>) {
+    let row_spacing = if hexagonal {
+        spacing * 3_f64.sqrt() / 2.
+    } else {
+        spacing
+    };
+    let (width, height) = (width as f64, height as f64);
+    let n_rows = (height / row_spacing).floor() as usize + 1;
+    let n_cols = (width / spacing).floor() as usize + 1;
+    let offset_y = y0 as f64 + (height - (n_rows - 1) as f64 * row_spacing) / 2.;
+
+    let points = (0..n_rows).flat_map(|row| {
+        let shift = if hexagonal && row % 2 == 1 {
+            spacing / 2.
+        } else {
+            0.
+        };
+        // shifted rows lose their last point when it doesn't fit
+        let row_cols = if (n_cols - 1) as f64 * spacing + shift > width {
+            n_cols - 1
+        } else {
+            n_cols
+        };
+        let offset_x = x0 as f64 + (width - (n_cols - 1) as f64 * spacing) / 2. + shift;
+        (0..row_cols).map(move |col| {
+            (
+                offset_x + col as f64 * spacing,
+                offset_y + row as f64 * row_spacing,
+            )
+        })
+    });
+    round_coords(points)
+}
+
+/// Compute the coords of the points of a square grid filling a box, centered in it.
+///
+/// # Arguments
+///
+/// * `top_left`: the coordinate of the top left corner of the box
+/// * (`width`, `height`): the dimensions of the box
+/// * `spacing`: the distance between neighbouring points
+pub fn square_grid_coords(
+    top_left: (u32, u32),
+    size: (u32, u32),
+    spacing: f64,
+) -> (Vec<u32>, Vec<u32>) {
+    grid_coords(top_left, size, spacing.max(1.), false)
+}
+
+/// Compute the coords of the points of a hexagonal grid filling a box, centered in it. Each point
+/// is at the same distance of its 6 neighbours.
+///
+/// # Arguments
+///
+/// * `top_left`: the coordinate of the top left corner of the box
+/// * (`width`, `height`): the dimensions of the box
+/// * `spacing`: the distance between neighbouring points
+pub fn hex_grid_coords(
+    top_left: (u32, u32),
+    size: (u32, u32),
+    spacing: f64,
+) -> (Vec<u32>, Vec<u32>) {
+    grid_coords(top_left, size, spacing.max(1.), true)
+}
+
+/// Spatial index of points, to find the points near a position.
+struct PointGrid {
+    cell_size: f64,
+    n_cols: usize,
+    n_rows: usize,
+    cells: Vec<Vec<(f64, f64)>>,
+}
+
+impl PointGrid {
+    fn new(width: f64, height: f64, cell_size: f64) -> Self {
+        let n_cols = (width / cell_size).floor() as usize + 1;
+        let n_rows = (height / cell_size).floor() as usize + 1;
+        Self {
+            cell_size,
+            n_cols,
+            n_rows,
+            cells: vec![vec![]; n_cols * n_rows],
+        }
+    }
+
+    fn cell(&self, (x, y): (f64, f64)) -> (usize, usize) {
+        (
+            ((x / self.cell_size) as usize).min(self.n_cols - 1),
+            ((y / self.cell_size) as usize).min(self.n_rows - 1),
+        )
+    }
+
+    fn insert(&mut self, point: (f64, f64)) {
+        let (col, row) = self.cell(point);
+        self.cells[row * self.n_cols + col].push(point);
+    }
+
+    /// Whether a point lies within `distance` of `point`, `distance` is at most the cell size.
+    fn has_neighbour(&self, point: (f64, f64), distance: f64) -> bool {
+        let (col, row) = self.cell(point);
+        (row.saturating_sub(1)..(row + 2).min(self.n_rows)).any(|row| {
+            (col.saturating_sub(1)..(col + 2).min(self.n_cols)).any(|col| {
+                self.cells[row * self.n_cols + col]
+                    .iter()
+                    .any(|other| (other.0 - point.0).hypot(other.1 - point.1) < distance)
+            })
+        })
+    }
+}
+
+/// Compute the coords of randomly scattered points filling a box, no closer than `min_distance`
+/// to each other, with Bridson's Poisson-disc sampling. The points are evenly distributed but
+/// don't form a regular pattern.
+///
+/// # Arguments
+///
+/// * `top_left`: the coordinate of the top left corner of the box
+/// * (`width`, `height`): the dimensions of the box
+/// * `min_distance`: the minimum distance between points
+/// * `rng`: the random number generator
+pub fn poisson_disc_coords<R: Rng>(
+    (x0, y0): (u32, u32),
+    (width, height): (u32, u32),
+    min_distance: f64,
+    rng: &mut R,
+) -> (Vec<u32>, Vec<u32>) {
+    let min_distance = min_distance.max(1.);
+    let (width, height) = (width as f64, height as f64);
+    let mut grid = PointGrid::new(width, height, min_distance);
+    let first = (rng.gen_range(0. ..=width), rng.gen_range(0. ..=height));
+    grid.insert(first);
+    let mut points = vec![first];
+    let mut active = vec![first];
+
+    while !active.is_empty() {
+        let index = rng.gen_range(0..active.len());
+        let (x, y) = active[index];
+        // draw candidates in the annulus between 1 and 2 minimum distances
+        let candidate = (0..POISSON_DISC_CANDIDATES)
+            .map(|_| {
+                let angle = rng.gen_range(0. ..2. * PI);
+                let radius = rng.gen_range(min_distance..2. * min_distance);
+                (x + radius * angle.cos(), y + radius * angle.sin())
+            })
+            .find(|&(cx, cy)| {
+                (0. ..=width).contains(&cx)
+                    && (0. ..=height).contains(&cy)
+                    && !grid.has_neighbour((cx, cy), min_distance)
+            });
+        match candidate {
+            Some(point) => {
+                grid.insert(point);
+                points.push(point);
+                active.push(point);
+            }
+            None => {
+                active.swap_remove(index);
+            }
+        }
+    }
+    round_coords(
+        points
+            .into_iter()
+            .map(|(x, y)| (x0 as f64 + x, y0 as f64 + y)),
+    )
+}
+
+/// Edge strength of each pixel of a grayscale image, the magnitude of its Sobel gradient.
+fn edge_strength(image: &GrayImage) -> Vec<f64> {
+    let (width, height) = image.dimensions();
+    let pixel = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        image.get_pixel(x, y).0[0] as f64
+    };
+    (0..height as i64)
+        .flat_map(|y| (0..width as i64).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            let gx = pixel(x + 1, y - 1) + 2. * pixel(x + 1, y) + pixel(x + 1, y + 1)
+                - pixel(x - 1, y - 1)
+                - 2. * pixel(x - 1, y)
+                - pixel(x - 1, y + 1);
+            let gy = pixel(x - 1, y + 1) + 2. * pixel(x, y + 1) + pixel(x + 1, y + 1)
+                - pixel(x - 1, y - 1)
+                - 2. * pixel(x, y - 1)
+                - pixel(x + 1, y - 1);
+            gx.hypot(gy)
+        })
+        .collect()
+}
+
+/// Compute the coords of points scattered over a box of an image, denser where the image has
+/// more edges. The positions are drawn with a probability proportional to the edge strength,
+/// with a floor so that flat areas still get some points, and rejected when closer than
+/// `min_distance` to a previous point. Fewer than `n_points` are returned when the box is too
+/// crowded.
+///
+/// # Arguments
+///
+/// * `image`: the grayscale image
+/// * `top_left`: the coordinate of the top left corner of the box, inside the image
+/// * (`width`, `height`): the dimensions of the box
+/// * `n_points`: the number of points
+/// * `min_distance`: the minimum distance between points
+/// * `rng`: the random number generator
+pub fn image_adaptive_coords<R: Rng>(
+    image: &GrayImage,
+    (x0, y0): (u32, u32),
+    (width, height): (u32, u32),
+    n_points: usize,
+    min_distance: f64,
+    rng: &mut R,
+) -> Result<(Vec<u32>, Vec<u32>), Box<dyn Error>> {
+    let width = width.min(image.width().saturating_sub(x0));
+    let height = height.min(image.height().saturating_sub(y0));
+    if width == 0 || height == 0 {
+        return Err("The box doesn't overlap the image.".into());
+    }
+    let edges = edge_strength(&image::imageops::crop_imm(image, x0, y0, width, height).to_image());
+    // flat areas get a tenth of the average edge strength
+    let floor = edges.iter().sum::<f64>() / edges.len() as f64 / 10. + f64::EPSILON;
+    let distribution = WeightedIndex::new(edges.iter().map(|edge| edge + floor))?;
+
+    let mut grid = PointGrid::new(width as f64, height as f64, min_distance.max(1.));
+    let mut points = vec![];
+    let mut rejected = 0;
+    while points.len() < n_points && rejected < n_points.max(1) * POISSON_DISC_CANDIDATES {
+        let index = distribution.sample(rng);
+        let point = ((index as u32 % width) as f64, (index as u32 / width) as f64);
+        if grid.has_neighbour(point, min_distance) {
+            rejected += 1;
+            continue;
+        }
+        grid.insert(point);
+        points.push(point);
+    }
+    Ok(round_coords(
+        points
+            .into_iter()
+            .map(|(x, y)| (x0 as f64 + x, y0 as f64 + y)),
+    ))
+}
+
 /// Get the pixels around a point within `radius`.
 ///
 /// # Arguments
@@ -193,12 +683,27 @@ where
     }
 }
 
-/// Create a hash key from two pegs. Used to construct the [`Pather::line_cache`](crate::pather::Pather::line_cache).
-pub fn hash_key(peg_a: &Peg, peg_b: &Peg) -> (usize, usize) {
-    if peg_a.id < peg_b.id {
-        (peg_a.id, peg_b.id)
-    } else {
-        (peg_b.id, peg_a.id)
+/// Euclidean distance between two RGB colors.
+pub fn color_dist(color_a: [u8; 3], (r, g, b): (u8, u8, u8)) -> f64 {
+    let delta_r = color_a[0] as f64 - r as f64;
+    let delta_g = color_a[1] as f64 - g as f64;
+    let delta_b = color_a[2] as f64 - b as f64;
+    (delta_r * delta_r + delta_g * delta_g + delta_b * delta_b).sqrt()
+}
+
+/// 64 bit FNV-1a hash of some bytes, stable across platforms and compiler versions.
+pub fn fnv1a_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Create a random number generator, seeded for reproducible layouts, or from entropy when
+/// `seed` is [`None`].
+pub fn seeded_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
     }
 }
 
@@ -228,6 +733,20 @@ mod test {
         assert_eq!(abs_diff(2, 1), 1);
     }
 
+    #[test]
+    fn test_fnv1a_hash() {
+        assert_eq!(fnv1a_hash(b""), 0xcbf29ce484222325);
+        assert_eq!(fnv1a_hash(b"a"), 0xaf63dc4c8601ec8c);
+        assert_ne!(fnv1a_hash(b"ab"), fnv1a_hash(b"ba"));
+    }
+
+    #[test]
+    fn test_color_dist() {
+        assert_eq!(color_dist([0, 0, 0], (0, 0, 0)), 0.);
+        assert_eq!(color_dist([3, 4, 0], (0, 0, 0)), 5.);
+        assert_eq!(color_dist([0, 0, 0], (3, 0, 4)), 5.);
+    }
+
     #[test]
     fn test_line_coords() {
         // 5 points between 0 and 10
@@ -246,4 +765,119 @@ mod test {
         assert_eq!(x.last(), Some(&x_end));
         assert_eq!(y.last(), Some(&y_end));
     }
+
+    #[test]
+    fn test_ellipse_coords() {
+        let (x, y) = ellipse_coords((100, 50), (80, 20), 40);
+        assert_eq!(x.len(), 40);
+        assert_eq!((x[0], y[0]), (180, 50));
+        for (x, y) in x.iter().zip(&y) {
+            let (dx, dy) = ((*x as f64 - 100.) / 80., (*y as f64 - 50.) / 20.);
+            assert!((dx.hypot(dy) - 1.).abs() < 0.05);
+        }
+        // evenly spaced by arc length, not by angle
+        let spacings: Vec<f64> = x
+            .iter()
+            .zip(&y)
+            .zip(x.iter().zip(&y).cycle().skip(1))
+            .map(|((x0, y0), (x1, y1))| (*x1 as f64 - *x0 as f64).hypot(*y1 as f64 - *y0 as f64))
+            .collect();
+        let max = spacings.iter().cloned().fold(0., f64::max);
+        let min = spacings.iter().cloned().fold(f64::INFINITY, f64::min);
+        assert!(max - min < 2.);
+    }
+
+    #[test]
+    fn test_regular_polygon_coords() {
+        // a square with a peg on each vertex and in the middle of each side
+        let (x, y) = regular_polygon_coords((10, 10), 10, 4, 0., 8);
+        assert_eq!(x, vec![20, 15, 10, 5, 0, 5, 10, 15]);
+        assert_eq!(y, vec![10, 15, 20, 15, 10, 5, 0, 5]);
+        // at least 3 sides
+        let (x, _) = regular_polygon_coords((10, 10), 10, 1, 0., 3);
+        assert_eq!(x.len(), 3);
+    }
+
+    #[test]
+    fn test_svg_path_coords() {
+        // a 10x5 rectangle scaled up to fit in the box, centered vertically
+        let (x, y) = svg_path_coords("M 0 0 h 10 v 5 h -10 z", (10, 10), (100, 100), 6).unwrap();
+        assert_eq!(x, vec![10, 60, 110, 110, 60, 10]);
+        assert_eq!(y, vec![35, 35, 35, 85, 85, 85]);
+
+        // subpaths share the points according to their length
+        let (x, _) = svg_path_coords("M 0 0 H 30 M 0 10 H 10", (0, 0), (30, 30), 4).unwrap();
+        assert_eq!(x, vec![0, 10, 20, 0]);
+
+        // straight lines only fit one dimension
+        let (x, y) = svg_path_coords("M 0 0 V 10", (0, 0), (100, 20), 2).unwrap();
+        assert_eq!(x, vec![50, 50]);
+        assert_eq!(y, vec![0, 10]);
+
+        assert!(svg_path_coords("", (0, 0), (10, 10), 5).is_err());
+        assert!(svg_path_coords("M 5 5 Z", (0, 0), (10, 10), 5).is_err());
+        assert!(svg_path_coords("M 0 0 X 5", (0, 0), (10, 10), 5).is_err());
+    }
+
+    #[test]
+    fn test_grid_coords() {
+        // 3x2 points centered in the box
+        let (x, y) = square_grid_coords((10, 10), (25, 15), 10.);
+        assert_eq!(x, vec![13, 23, 33, 13, 23, 33]);
+        assert_eq!(y, vec![13, 13, 13, 23, 23, 23]);
+
+        let (x, y) = hex_grid_coords((0, 0), (100, 100), 10.);
+        let points: Vec<(f64, f64)> = x
+            .iter()
+            .zip(&y)
+            .map(|(x, y)| (*x as f64, *y as f64))
+            .collect();
+        assert!(points.iter().all(|(x, y)| *x <= 100. && *y <= 100.));
+        // the second row is shifted by half the spacing
+        assert_eq!(x[0] + 5, x[11]);
+        assert_ne!(y[0], y[11]);
+        for (index, (x0, y0)) in points.iter().enumerate() {
+            let neighbours = points
+                .iter()
+                .filter(|(x1, y1)| (x1 - x0).hypot(y1 - y0) < 11.)
+                .count();
+            assert!(neighbours <= 7, "point {index} has {neighbours} neighbours");
+        }
+    }
+
+    #[test]
+    fn test_poisson_disc_coords() {
+        let mut rng = seeded_rng(Some(0));
+        let (x, y) = poisson_disc_coords((20, 20), (200, 100), 10., &mut rng);
+        let points: Vec<(f64, f64)> = x
+            .iter()
+            .zip(&y)
+            .map(|(x, y)| (*x as f64, *y as f64))
+            .collect();
+        // fills the box, a hex grid of spacing 10 would hold ~230 points
+        assert!(points.len() > 100);
+        assert!(points
+            .iter()
+            .all(|(x, y)| (20. ..=220.).contains(x) && (20. ..=120.).contains(y)));
+        for (i, (x0, y0)) in points.iter().enumerate() {
+            for (x1, y1) in &points[i + 1..] {
+                // rounding to pixels can bring points slightly closer
+                assert!((x1 - x0).hypot(y1 - y0) > 10. - 2_f64.sqrt());
+            }
+        }
+    }
+
+    #[test]
+    fn test_image_adaptive_coords() {
+        // a white image with a black right half, the edge is in the middle
+        let image =
+            GrayImage::from_fn(100, 100, |x, _| image::Luma([if x < 50 { 255 } else { 0 }]));
+        let mut rng = seeded_rng(Some(0));
+        let (x, _) = image_adaptive_coords(&image, (0, 0), (100, 100), 40, 4., &mut rng).unwrap();
+        assert_eq!(x.len(), 40);
+        let near_edge = x.iter().filter(|x| (40..60).contains(*x)).count();
+        assert!(near_edge > 20);
+
+        assert!(image_adaptive_coords(&image, (100, 0), (10, 10), 10, 4., &mut rng).is_err());
+    }
 }